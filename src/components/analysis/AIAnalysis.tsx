@@ -40,6 +40,7 @@ export function AIAnalysis({ filePath, fileName }: AIAnalysisProps) {
       const response = await fetch(`http://localhost:8000/api/upload/content/${encodeURIComponent(filePath)}`, {
         headers: {
           'Accept': 'text/plain',
+          'Authorization': `Bearer ${localStorage.getItem('token')}`,
         },
       });
       
@@ -67,6 +68,7 @@ export function AIAnalysis({ filePath, fileName }: AIAnalysisProps) {
 
         headers: {
           'Accept': 'application/json',
+          'Authorization': `Bearer ${localStorage.getItem('token')}`,
         },
       });
       
@@ -0,0 +1,16 @@
+-- Create review_findings table (AI code review results per upload)
+CREATE TABLE IF NOT EXISTS review_findings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    upload_id UUID NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    category VARCHAR(32) NOT NULL CHECK (category IN ('bug', 'security', 'performance', 'style')),
+    severity VARCHAR(32) NOT NULL CHECK (severity IN ('info', 'low', 'medium', 'high', 'critical')),
+    line_start INTEGER NOT NULL,
+    line_end INTEGER NOT NULL,
+    explanation TEXT NOT NULL,
+    suggested_fix TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CHECK (line_start >= 1 AND line_end >= line_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_review_findings_upload_id ON review_findings(upload_id);
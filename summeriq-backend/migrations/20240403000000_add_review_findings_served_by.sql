@@ -0,0 +1,4 @@
+-- Provider and model that produced each finding
+ALTER TABLE review_findings
+    ADD COLUMN ai_provider VARCHAR(100),
+    ADD COLUMN ai_model VARCHAR(255);
@@ -0,0 +1,2 @@
+-- Prompt template version each finding was produced with
+ALTER TABLE review_findings ADD COLUMN prompt_version VARCHAR(100);
@@ -0,0 +1,25 @@
+-- Admin flag and optional per-user override of the daily AI token budget
+ALTER TABLE users
+    ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN ai_daily_token_budget INTEGER CHECK (ai_daily_token_budget >= 0);
+
+-- Create ai_usage table (one row per AI completion)
+CREATE TABLE IF NOT EXISTS ai_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Rate limiter / budget key: `user:{id}` or `ip:{addr}`
+    caller VARCHAR(255) NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    upload_id UUID REFERENCES uploads(id) ON DELETE SET NULL,
+    feature VARCHAR(32) NOT NULL CHECK (feature IN ('chat', 'analysis', 'docs', 'review', 'changelog', 'other')),
+    provider VARCHAR(100) NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    prompt_version VARCHAR(100),
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    total_tokens INTEGER NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_ai_usage_caller_created_at ON ai_usage(caller, created_at);
+CREATE INDEX IF NOT EXISTS idx_ai_usage_user_id_created_at ON ai_usage(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_ai_usage_created_at ON ai_usage(created_at);
@@ -0,0 +1,58 @@
+-- Create file_analyses table (AI analysis of one file of an upload)
+CREATE TABLE IF NOT EXISTS file_analyses (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    upload_id UUID NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    -- SHA-256 of the prompt versions, path and content the analysis was made from
+    content_hash VARCHAR(64) NOT NULL,
+    language VARCHAR(50) NOT NULL,
+    file_purpose TEXT NOT NULL,
+    dependencies JSONB NOT NULL DEFAULT '[]',
+    served_by JSONB NOT NULL DEFAULT '[]',
+    prompt_versions JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (upload_id, file_path, content_hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_file_analyses_upload_id ON file_analyses(upload_id);
+
+-- Create project_docs table (generated documentation of an upload)
+CREATE TABLE IF NOT EXISTS project_docs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    upload_id UUID NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    -- Full ProjectDocumentation as returned by the API
+    document JSONB NOT NULL,
+    served_by JSONB NOT NULL DEFAULT '[]',
+    prompt_versions JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_docs_upload_id_created_at ON project_docs(upload_id, created_at DESC);
+
+-- Create conversations table (chat threads, optionally about an upload)
+CREATE TABLE IF NOT EXISTS conversations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    upload_id UUID REFERENCES uploads(id) ON DELETE CASCADE,
+    title VARCHAR(255),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversations_user_id_updated_at ON conversations(user_id, updated_at DESC);
+CREATE INDEX IF NOT EXISTS idx_conversations_upload_id ON conversations(upload_id);
+
+-- Create conversation_messages table
+CREATE TABLE IF NOT EXISTS conversation_messages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('user', 'assistant')),
+    content TEXT NOT NULL,
+    -- Set on assistant messages
+    ai_provider VARCHAR(100),
+    ai_model VARCHAR(255),
+    prompt_version VARCHAR(100),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_messages_conversation_id ON conversation_messages(conversation_id, created_at);
@@ -0,0 +1,9 @@
+-- Create auth_rate_events table: login and registration attempts for the Postgres-backed
+-- auth rate limiter (AUTH_LIMITER_STORE=postgres). Keys look like `login:ip:<addr>`.
+CREATE TABLE IF NOT EXISTS auth_rate_events (
+    id BIGSERIAL PRIMARY KEY,
+    key VARCHAR(320) NOT NULL,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_rate_events_key ON auth_rate_events(key, occurred_at);
@@ -0,0 +1,20 @@
+-- Create share_links table: read-only links to one upload, generated document or
+-- conversation for people without an account. The link token is signed and names the
+-- row by id; revoking the row voids the token.
+CREATE TABLE IF NOT EXISTS share_links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    upload_id UUID REFERENCES uploads(id) ON DELETE CASCADE,
+    doc_id UUID REFERENCES project_docs(id) ON DELETE CASCADE,
+    conversation_id UUID REFERENCES conversations(id) ON DELETE CASCADE,
+    -- bcrypt hash; anyone with the link may view it when absent
+    password_hash VARCHAR(255),
+    view_count INTEGER NOT NULL DEFAULT 0,
+    last_viewed_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    CHECK (num_nonnulls(upload_id, doc_id, conversation_id) = 1)
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_links_user_id ON share_links(user_id);
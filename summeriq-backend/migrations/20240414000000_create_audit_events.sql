@@ -0,0 +1,35 @@
+-- Create audit_events table: who did what to which resource, from where.
+-- Rows are never changed or removed, and actors and targets are plain values rather than
+-- foreign keys, so events outlive the users and uploads they mention.
+CREATE TABLE IF NOT EXISTS audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- NULL for anonymous requests such as failed logins and share link views
+    actor_id UUID,
+    -- e.g. auth.login, upload.create, share.view
+    action VARCHAR(64) NOT NULL,
+    -- e.g. user, upload, share_link, with the target's id
+    target_type VARCHAR(32),
+    target_id TEXT,
+    ip_address VARCHAR(64),
+    user_agent TEXT,
+    details JSONB NOT NULL DEFAULT '{}'::jsonb
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_occurred_at ON audit_events(occurred_at);
+CREATE INDEX IF NOT EXISTS idx_audit_events_actor_id ON audit_events(actor_id, id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_target ON audit_events(target_type, target_id, id);
+
+CREATE OR REPLACE FUNCTION reject_audit_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_event_changes();
+
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_event_changes();
@@ -0,0 +1,17 @@
+-- Create secret_findings table (credentials detected in uploads at extraction time)
+CREATE TABLE IF NOT EXISTS secret_findings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    upload_id UUID NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    rule VARCHAR(64) NOT NULL,
+    -- NULL when the file was flagged by its name
+    line INTEGER,
+    -- First characters of the secret, never the secret itself
+    preview VARCHAR(32) NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_secret_findings_upload_id ON secret_findings(upload_id);
+
+-- Path patterns the uploader excluded; matching files were never extracted
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS excluded_paths TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+-- Gitignore-style patterns given with an upload, hidden from listings, analysis and docs
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS ignore_patterns TEXT[] NOT NULL DEFAULT '{}';
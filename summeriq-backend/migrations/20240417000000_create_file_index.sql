@@ -0,0 +1,23 @@
+-- Create file_index table (the extracted tree of an upload, one row per file or directory)
+CREATE TABLE IF NOT EXISTS file_index (
+    upload_id UUID NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    -- Order of the entry in the tree, directories before files; the root is 0
+    position INTEGER NOT NULL,
+    -- Relative to the project root; '' for the root
+    path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    is_dir BOOLEAN NOT NULL,
+    depth INTEGER NOT NULL,
+    -- Position of the last entry below a directory, the entry's own position for files
+    subtree_end INTEGER NOT NULL,
+    -- Bytes of a file, or of every file below a directory
+    size BIGINT NOT NULL,
+    -- Entries directly below a directory, NULL for files
+    child_count INTEGER,
+    -- Type, encoding and language of a file
+    metadata JSONB,
+    PRIMARY KEY (upload_id, position),
+    UNIQUE (upload_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_file_index_upload_depth ON file_index(upload_id, depth, position);
@@ -0,0 +1,301 @@
+use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::{middleware::Logger, web, App, HttpResponse};
+use sqlx::PgPool;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::handlers;
+use crate::routes::{chat, documentation, upload};
+use crate::services::auth::AuthSettings;
+use crate::services::auth_limiter::{AuthLimitSettings, AuthLimiter, LimiterStore, MemoryLimiterStore, PgLimiterStore};
+use crate::services::mailer::{FileMailer, Mailer, SmtpMailer};
+use crate::services::oidc::{OidcClient, OidcSettings};
+use crate::services::organizations::OrganizationSettings;
+use crate::services::shares::ShareSettings;
+use crate::services::prompts::PromptRegistry;
+use crate::services::providers::{
+    AiProvider, BreakerSettings, CassetteMode, CassetteProvider, OpenAiCompatibleProvider, ProviderChain,
+};
+use crate::services::tokens::TokenBudget;
+use crate::services::{
+    AIService, AnalysisService, AuditService, AuthService, ConversationService, FileIndexService, OrganizationService,
+    ProjectService, ReviewService, SecretService, ShareService, StorageService, UsageService,
+};
+
+/// Everything the HTTP handlers share. Cloning is cheap and every clone refers to the same
+/// services, so one state can be handed to each server worker.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: web::Data<Config>,
+    pub pool: web::Data<PgPool>,
+    pub storage_service: web::Data<StorageService>,
+    pub ai_service: web::Data<AIService>,
+    pub analysis_service: web::Data<AnalysisService>,
+    pub auth_service: web::Data<AuthService>,
+    pub audit_service: web::Data<AuditService>,
+    pub organization_service: web::Data<OrganizationService>,
+    pub conversation_service: web::Data<ConversationService>,
+    pub file_index_service: web::Data<FileIndexService>,
+    pub share_service: web::Data<ShareService>,
+    pub project_service: web::Data<ProjectService>,
+    pub review_service: web::Data<ReviewService>,
+    pub secret_service: web::Data<SecretService>,
+    pub usage_service: web::Data<UsageService>,
+}
+
+impl AppState {
+    /// Builds the services with the AI providers named in the configuration.
+    pub fn from_config(config: Config, pool: PgPool) -> Result<Self, AppError> {
+        let providers = providers_from_config(&config)?;
+        Self::new(config, pool, providers)
+    }
+
+    /// Builds the services, sending AI requests to `providers` in fallback order.
+    pub fn new(config: Config, pool: PgPool, providers: Vec<Arc<dyn AiProvider>>) -> Result<Self, AppError> {
+        let storage_service =
+            StorageService::new(config.storage_path.clone()).with_ignore_patterns(config.ignore_patterns.clone());
+        let usage_service = UsageService::new(pool.clone(), config.ai_daily_token_budget);
+
+        let provider_chain = ProviderChain::new(providers, BreakerSettings {
+            failure_threshold: config.ai_breaker_failure_threshold,
+            open_for: Duration::from_secs(config.ai_breaker_open_secs),
+        });
+        let prompts = PromptRegistry::load(&config.prompts_dir)?;
+        let ai_service = AIService::new(
+            Arc::new(provider_chain),
+            Arc::new(prompts),
+            TokenBudget::new(config.ai_context_tokens),
+        )
+        .with_usage_tracking(usage_service.clone());
+
+        let analysis_service = AnalysisService::new(
+            pool.clone(),
+            config.groq_api_key.clone(),
+            storage_service.clone(),
+            ai_service.clone(),
+        );
+        let mailer = mailer_from_config(&config)?;
+        let auth_limiter = auth_limiter_from_config(&config, &pool);
+        let mut auth_service = AuthService::new(
+            pool.clone(),
+            AuthSettings::from_config(&config),
+            mailer.clone(),
+            auth_limiter.clone(),
+        );
+        if let Some(settings) = OidcSettings::from_config(&config) {
+            auth_service = auth_service.with_oidc(OidcClient::new(settings));
+        }
+        let organization_service =
+            OrganizationService::new(pool.clone(), OrganizationSettings::from_config(&config), mailer);
+        let audit_service = AuditService::new(pool.clone());
+        let conversation_service = ConversationService::new(pool.clone());
+        let file_index_service = FileIndexService::new(pool.clone(), storage_service.clone());
+        let share_service = ShareService::new(
+            pool.clone(),
+            ShareSettings::from_config(&config),
+            organization_service.clone(),
+            conversation_service.clone(),
+            storage_service.clone(),
+            file_index_service.clone(),
+            auth_limiter,
+        );
+        let project_service = ProjectService::new(pool.clone(), storage_service.clone());
+        let review_service = ReviewService::new(pool.clone(), storage_service.clone(), ai_service.clone(), config.ai_review_max_files);
+        let secret_service = SecretService::new(pool.clone(), storage_service.clone());
+
+        Ok(Self {
+            config: web::Data::new(config),
+            pool: web::Data::new(pool),
+            storage_service: web::Data::new(storage_service),
+            ai_service: web::Data::new(ai_service),
+            analysis_service: web::Data::new(analysis_service),
+            auth_service: web::Data::new(auth_service),
+            audit_service: web::Data::new(audit_service),
+            organization_service: web::Data::new(organization_service),
+            conversation_service: web::Data::new(conversation_service),
+            file_index_service: web::Data::new(file_index_service),
+            share_service: web::Data::new(share_service),
+            project_service: web::Data::new(project_service),
+            review_service: web::Data::new(review_service),
+            secret_service: web::Data::new(secret_service),
+            usage_service: web::Data::new(usage_service),
+        })
+    }
+}
+
+/// One provider per `AI_PROVIDERS` entry, wrapped in a cassette when `AI_CASSETTE_MODE` is set.
+fn providers_from_config(config: &Config) -> Result<Vec<Arc<dyn AiProvider>>, AppError> {
+    let cassette_mode = CassetteMode::parse(&config.ai_cassette_mode).map_err(AppError::InternalServerError)?;
+    config
+        .ai_providers
+        .iter()
+        .map(|provider| {
+            let cassette = Path::new(&config.ai_cassette_dir).join(format!("{}.json", provider.name));
+            Ok(match cassette_mode {
+                None => Arc::new(OpenAiCompatibleProvider::new(provider)) as Arc<dyn AiProvider>,
+                Some(CassetteMode::Record) => Arc::new(CassetteProvider::record(
+                    Arc::new(OpenAiCompatibleProvider::new(provider)),
+                    cassette,
+                )),
+                Some(CassetteMode::Replay) => Arc::new(CassetteProvider::replay(cassette)?),
+            })
+        })
+        .collect()
+}
+
+/// Delivers email over SMTP, or into `MAIL_DIR` when `MAIL_TRANSPORT` is `file`.
+fn mailer_from_config(config: &Config) -> Result<Arc<dyn Mailer>, AppError> {
+    Ok(match config.mail_transport.to_lowercase().as_str() {
+        "smtp" => Arc::new(SmtpMailer::new(&config.smtp_url, &config.mail_from)?),
+        _ => Arc::new(FileMailer::new(&config.mail_dir)),
+    })
+}
+
+/// Counts login, registration and share link password attempts in memory, or in Postgres when
+/// `AUTH_LIMITER_STORE` is `postgres` so that every server instance shares the limits.
+fn auth_limiter_from_config(config: &Config, pool: &PgPool) -> AuthLimiter {
+    let settings = AuthLimitSettings::from_config(config);
+    let store: Arc<dyn LimiterStore> = match config.auth_limiter_store.to_lowercase().as_str() {
+        "postgres" => Arc::new(PgLimiterStore::new(pool.clone(), settings.retention())),
+        _ => Arc::new(MemoryLimiterStore::new(settings.retention())),
+    };
+    AuthLimiter::new(store, settings)
+}
+
+/// The complete application: CORS for the configured origins, logging, shared state and
+/// every `/api` route.
+pub fn build_app(
+    state: AppState,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let cors = state
+        .config
+        .cors_allowed_origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+        .allowed_headers(vec!["Content-Type", "Authorization", handlers::shares::SHARE_PASSWORD_HEADER])
+        .supports_credentials()
+        .max_age(3600);
+
+    App::new()
+        .wrap(cors)
+        .wrap(Logger::default())
+        .app_data(state.config)
+        .app_data(state.pool)
+        .app_data(state.storage_service)
+        .app_data(state.analysis_service)
+        .app_data(state.ai_service)
+        .app_data(state.auth_service)
+        .app_data(state.audit_service)
+        .app_data(state.organization_service)
+        .app_data(state.conversation_service)
+        .app_data(state.file_index_service)
+        .app_data(state.share_service)
+        .app_data(state.project_service)
+        .app_data(state.review_service)
+        .app_data(state.secret_service)
+        .app_data(state.usage_service)
+        .service(
+            web::scope("/api")
+                .service(
+                    web::scope("/auth")
+                        .route("/register", web::post().to(handlers::auth::register))
+                        .route("/register", web::method(Method::OPTIONS).to(|| async { HttpResponse::Ok().finish() }))
+                        .route("/login", web::post().to(handlers::auth::login))
+                        .route("/login", web::method(Method::OPTIONS).to(|| async { HttpResponse::Ok().finish() }))
+                        .route("/refresh", web::post().to(handlers::auth::refresh))
+                        .route("/logout", web::post().to(handlers::auth::logout))
+                        .route("/logout-all", web::post().to(handlers::auth::logout_all))
+                        .route("/sessions", web::get().to(handlers::auth::list_sessions))
+                        .route("/verify-email", web::post().to(handlers::auth::verify_email))
+                        .route("/verify-email/resend", web::post().to(handlers::auth::resend_verification_email))
+                        .route("/password-reset", web::post().to(handlers::auth::request_password_reset))
+                        .route("/password-reset/confirm", web::post().to(handlers::auth::reset_password))
+                        .route("/oidc/login", web::get().to(handlers::auth::oidc_login))
+                        .route("/oidc/callback", web::post().to(handlers::auth::oidc_callback))
+                )
+                .service(
+                    web::scope("/upload")
+                        .route("", web::post().to(handlers::upload::upload_file))
+                        .route("", web::method(Method::OPTIONS).to(|| async { HttpResponse::Ok().finish() }))
+                        .route("/{file_id}", web::get().to(upload::get_file))
+                        .route("/content/{path:.*}", web::get().to(upload::get_file_content))
+                )
+                .service(
+                    web::scope("/analysis")
+                        .route("/file/{path:.*}", web::get().to(crate::routes::analysis::analyze_file))
+                        .route("/file/{path:.*}", web::method(Method::OPTIONS).to(|| async { HttpResponse::Ok().finish() }))
+                )
+                .service(
+                    web::scope("/projects")
+                        .route("", web::post().to(handlers::project::create_project))
+                        .route("", web::get().to(handlers::project::list_projects))
+                        .route("/{id}", web::get().to(handlers::project::get_project))
+                        .route("/{id}/versions", web::post().to(handlers::project::add_version))
+                        .route("/{id}/tree", web::get().to(handlers::project::tree))
+                        .route("/{id}/diff", web::get().to(handlers::project::diff_versions))
+                        .route("/{id}/changelog", web::get().to(handlers::project::changelog))
+                )
+                .service(
+                    web::scope("/uploads")
+                        .route("/{id}", web::delete().to(handlers::upload::delete_upload))
+                        .route("/{id}/review", web::post().to(handlers::review::review_upload))
+                        .route("/{id}/findings", web::get().to(handlers::review::list_findings))
+                        .route("/{id}/secrets", web::get().to(handlers::upload::list_secret_findings))
+                )
+                .service(
+                    web::scope("/orgs")
+                        .route("", web::post().to(handlers::organizations::create_organization))
+                        .route("", web::get().to(handlers::organizations::list_organizations))
+                        .route("/{id}", web::get().to(handlers::organizations::get_organization))
+                        .route("/{id}/uploads", web::get().to(handlers::organizations::list_organization_uploads))
+                        .route("/{id}/members/{user_id}", web::put().to(handlers::organizations::update_member_role))
+                        .route("/{id}/members/{user_id}", web::delete().to(handlers::organizations::remove_member))
+                        .route("/{id}/invites", web::post().to(handlers::organizations::create_invite))
+                        .route("/{id}/invites", web::get().to(handlers::organizations::list_invites))
+                        .route("/{id}/invites/{invite_id}", web::delete().to(handlers::organizations::revoke_invite))
+                )
+                .route("/invites/accept", web::post().to(handlers::organizations::accept_invite))
+                .route("/shares", web::post().to(handlers::shares::create_share_link))
+                .route("/shares", web::get().to(handlers::shares::list_share_links))
+                .route("/shares/{id}", web::delete().to(handlers::shares::revoke_share_link))
+                .route("/public/shares/{token}", web::get().to(handlers::shares::view_shared))
+                .route("/public/shares/{token}/tree", web::get().to(handlers::shares::view_shared_tree))
+                .route("/public/shares/{token}/files/{path:.*}", web::get().to(handlers::shares::view_shared_file))
+                .route("/me/ai-usage", web::get().to(handlers::usage::my_usage))
+                .route("/me/api-tokens", web::post().to(handlers::api_tokens::create_api_token))
+                .route("/me/api-tokens", web::get().to(handlers::api_tokens::list_api_tokens))
+                .route("/me/api-tokens/{id}", web::delete().to(handlers::api_tokens::revoke_api_token))
+                .route("/admin/ai-usage", web::get().to(handlers::usage::all_usage))
+                .route("/admin/config", web::get().to(handlers::admin::get_config))
+                .route("/admin/audit-events", web::get().to(handlers::admin::list_audit_events))
+                .route("/admin/audit-events/export", web::get().to(handlers::admin::export_audit_events))
+                .service(
+                    web::scope("/chat")
+                        .route("", web::post().to(chat::chat))
+                        .route("", web::method(Method::OPTIONS).to(|| async { HttpResponse::Ok().finish() }))
+                )
+                .configure(|cfg| {
+                    cfg.service(
+                        web::resource("/documentation/project/{path:.*}")
+                            .route(web::get().to(handlers::documentation::get_project_documentation))
+                            .route(web::method(Method::OPTIONS).to(|| async { HttpResponse::Ok().finish() }))
+                    );
+                })
+                .configure(documentation::configure)
+        )
+}
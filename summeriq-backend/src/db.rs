@@ -1,18 +1,94 @@
+use sqlx::migrate::{Migrate, Migrator};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
+use tracing::info;
 
-pub async fn init_db() -> Result<PgPool, sqlx::Error> {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
+/// Migrations embedded from `./migrations` at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+pub async fn connect(database_url: &str, max_connections: u32) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(database_url)
+        .await
+}
+
+/// Applies every pending migration. Fails without changing anything when an applied
+/// migration was edited or is missing from this build.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
+    let pending = migration_status(pool)
+        .await?
+        .iter()
+        .filter(|m| m.state == MigrationState::Pending)
+        .count();
+    info!("Applying {} pending database migrations", pending);
+    MIGRATOR.run(pool).await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but the file has changed since.
+    Modified,
+    /// Applied to the database but not part of this build.
+    Unknown,
+}
+
+impl MigrationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Modified => "modified",
+            MigrationState::Unknown => "unknown",
+        }
+    }
+}
 
-    // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&pool)
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// Compares the embedded migrations with those recorded in the database, without
+/// modifying it.
+pub async fn migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>, sqlx::migrate::MigrateError> {
+    let has_table: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
         .await?;
+    let applied = if has_table {
+        pool.acquire().await?.list_applied_migrations().await?
+    } else {
+        Vec::new()
+    };
+
+    let mut statuses: Vec<MigrationStatus> = MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|migration| {
+            let state = match applied.iter().find(|a| a.version == migration.version) {
+                None => MigrationState::Pending,
+                Some(a) if a.checksum != migration.checksum => MigrationState::Modified,
+                Some(_) => MigrationState::Applied,
+            };
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state,
+            }
+        })
+        .collect();
 
-    Ok(pool)
+    for migration in applied.iter().filter(|a| !MIGRATOR.version_exists(a.version)) {
+        statuses.push(MigrationStatus {
+            version: migration.version,
+            description: String::new(),
+            state: MigrationState::Unknown,
+        });
+    }
+    statuses.sort_by_key(|s| s.version);
+    Ok(statuses)
 }
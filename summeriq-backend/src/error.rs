@@ -22,6 +22,16 @@ pub enum AppError {
 
     #[error("Not Found: {0}")]
     NotFound(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Quota Exceeded: {0}")]
+    QuotaExceeded(String),
+
+    /// Too many attempts; the client may retry after `retry_after_secs`.
+    #[error("Too Many Requests: {message}")]
+    RateLimited { message: String, retry_after_secs: u64 },
 }
 
 impl ResponseError for AppError {
@@ -42,6 +52,15 @@ impl ResponseError for AppError {
             AppError::NotFound(ref message) => {
                 HttpResponse::NotFound().json(serde_json::json!({ "error": message }))
             }
+            AppError::Forbidden(ref message) => {
+                HttpResponse::Forbidden().json(serde_json::json!({ "error": message }))
+            }
+            AppError::QuotaExceeded(ref message) => {
+                HttpResponse::TooManyRequests().json(serde_json::json!({ "error": message }))
+            }
+            AppError::RateLimited { ref message, retry_after_secs } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(serde_json::json!({ "error": message, "retry_after": retry_after_secs })),
         }
     }
 }
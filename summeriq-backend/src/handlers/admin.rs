@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse, Responder};
+use tracing::info;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::middleware::{AdminUser, Audit};
+use crate::models::audit::{AuditAction, AuditQuery};
+use crate::services::AuditService;
+
+/// Effective configuration with secrets masked.
+pub async fn get_config(admin: AdminUser, config: web::Data<Config>) -> Result<impl Responder, AppError> {
+    info!("Admin {} requested the configuration", admin.id());
+    Ok(HttpResponse::Ok().json(config.redacted()))
+}
+
+/// A page of audit events matching the query's filters, newest first.
+pub async fn list_audit_events(
+    _admin: AdminUser,
+    audit_service: web::Data<AuditService>,
+    query: web::Query<AuditQuery>,
+) -> Result<impl Responder, AppError> {
+    let page = audit_service.query(&query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// Every audit event matching the query's filters as newline-delimited JSON. The export
+/// is itself audited.
+pub async fn export_audit_events(
+    admin: AdminUser,
+    audit: Audit,
+    audit_service: web::Data<AuditService>,
+    query: web::Query<AuditQuery>,
+) -> Result<impl Responder, AppError> {
+    let query = query.into_inner();
+    let filters = serde_json::json!({
+        "actor_id": query.actor_id,
+        "action": query.action,
+        "target_type": query.target_type,
+        "target_id": query.target_id,
+        "since": query.since,
+        "until": query.until,
+    });
+    audit.record_with(admin.id(), AuditAction::AuditExported, None, filters).await;
+    info!("Admin {} exported the audit log", admin.id());
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(("Content-Disposition", "attachment; filename=\"audit-events.ndjson\""))
+        .streaming(audit_service.export(query)))
+}
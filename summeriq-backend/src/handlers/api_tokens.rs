@@ -0,0 +1,50 @@
+use actix_web::{web, HttpResponse, Responder};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::api_token::CreateApiToken;
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::services::AuthService;
+
+// Token management needs a signed-in session, so a leaked API token cannot mint or revoke others
+
+pub async fn create_api_token(
+    user: AuthUser,
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+    data: web::Json<CreateApiToken>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    if let Err(e) = data.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let created = auth_service.api_tokens().create(user.id(), data.into_inner()).await?;
+    let token = &created.api_token;
+    let details = serde_json::json!({ "name": token.name, "scopes": token.scopes });
+    audit.record_with(user.id(), AuditAction::ApiTokenCreated, AuditTarget::ApiToken(token.id), details).await;
+    Ok(HttpResponse::Created().json(created))
+}
+
+pub async fn list_api_tokens(
+    user: AuthUser,
+    auth_service: web::Data<AuthService>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let tokens = auth_service.api_tokens().list(user.id()).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+pub async fn revoke_api_token(
+    user: AuthUser,
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+    token_id: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let token_id = token_id.into_inner();
+    auth_service.api_tokens().revoke(user.id(), token_id).await?;
+    audit.record(user.id(), AuditAction::ApiTokenRevoked, AuditTarget::ApiToken(token_id)).await;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,14 +1,18 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use serde_json::json;
-use tracing::{info, error};
 use validator::Validate;
 
 
 use crate::error::AppError;
-use crate::models::user::{CreateUser, LoginUser};
+use crate::middleware::{Audit, AuthUser};
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::session::{RefreshRequest, SessionMeta};
+use crate::models::user::{CreateUser, EmailRequest, LoginUser, OidcCallback, ResetPasswordRequest, VerifyEmailRequest};
 use crate::services::AuthService;
 
 pub async fn register(
+    req: HttpRequest,
+    audit: Audit,
     auth_service: web::Data<AuthService>,
     user_data: web::Json<CreateUser>,
 ) -> Result<impl Responder, AppError> {
@@ -17,30 +21,192 @@ pub async fn register(
         return Err(AppError::BadRequest(format!("Validation error: {}", e)));
     }
     // Try to register the user, handle duplicate email
-    let user = match auth_service.register(user_data.into_inner()).await {
+    let meta = SessionMeta::from_request(&req);
+    let user = match auth_service.register(user_data.into_inner(), &meta).await {
         Ok(user) => user,
         Err(AppError::InternalServerError(ref msg)) if msg.contains("users_email_key") || msg.contains("duplicate key value") => {
             return Err(AppError::BadRequest("Email already exists".to_string()));
         },
         Err(e) => return Err(e),
     };
-    // Generate token for the new user
-    let token = auth_service.generate_token(&user)?;
+    audit.record(user.id, AuditAction::Register, AuditTarget::User(user.id)).await;
+    if auth_service.requires_email_verification() {
+        return Ok(HttpResponse::Created().json(json!({
+            "message": "User registered successfully; check your email to verify your address",
+            "user": user
+        })));
+    }
+    // Sign the new user in right away
+    let tokens = auth_service.start_session(&user, meta).await?;
     Ok(HttpResponse::Created().json(json!({
         "message": "User registered successfully",
         "user": user,
-        "token": token
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in
     })))
 }
 
 pub async fn login(
+    req: HttpRequest,
+    audit: Audit,
     auth_service: web::Data<AuthService>,
     credentials: web::Json<LoginUser>,
 ) -> Result<impl Responder, AppError> {
-    let (user, token) = auth_service.login(credentials.into_inner()).await?;
+    let email = credentials.email.clone();
+    let (user, tokens) = match auth_service.login(credentials.into_inner(), SessionMeta::from_request(&req)).await {
+        Ok(login) => login,
+        Err(e) => {
+            let details = json!({ "method": "password", "email": email, "reason": e.to_string() });
+            audit.record_with(None, AuditAction::LoginFailed, None, details).await;
+            return Err(e);
+        }
+    };
+    let details = json!({ "method": "password" });
+    audit.record_with(user.id, AuditAction::Login, AuditTarget::User(user.id), details).await;
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Login successful",
+        "user": user,
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in
+    })))
+}
+
+/// Starts a single sign-on login by redirecting to the identity provider.
+pub async fn oidc_login(auth_service: web::Data<AuthService>) -> Result<impl Responder, AppError> {
+    let url = auth_service.oidc_login_url().await?;
+    Ok(HttpResponse::Found().insert_header(("Location", url)).finish())
+}
+
+/// Completes a single sign-on login; answers like a password login.
+pub async fn oidc_callback(
+    req: HttpRequest,
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+    callback: web::Json<OidcCallback>,
+) -> Result<impl Responder, AppError> {
+    let (user, tokens) = match auth_service
+        .oidc_login(&callback.code, &callback.state, SessionMeta::from_request(&req))
+        .await
+    {
+        Ok(login) => login,
+        Err(e) => {
+            let details = json!({ "method": "oidc", "reason": e.to_string() });
+            audit.record_with(None, AuditAction::LoginFailed, None, details).await;
+            return Err(e);
+        }
+    };
+    let details = json!({ "method": "oidc" });
+    audit.record_with(user.id, AuditAction::Login, AuditTarget::User(user.id), details).await;
     Ok(HttpResponse::Ok().json(json!({
         "message": "Login successful",
         "user": user,
-        "token": token
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in
+    })))
+}
+
+pub async fn refresh(
+    auth_service: web::Data<AuthService>,
+    body: web::Json<RefreshRequest>,
+) -> Result<impl Responder, AppError> {
+    let tokens = auth_service.refresh(&body.refresh_token).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Ends the session of the presented access token.
+pub async fn logout(
+    user: AuthUser,
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+) -> Result<impl Responder, AppError> {
+    let session_id = user.require_session()?;
+    auth_service.logout(user.id(), session_id).await?;
+    audit.record(user.id(), AuditAction::Logout, AuditTarget::Session(session_id)).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Ends every session of the user, signing out all devices including this one.
+pub async fn logout_all(
+    user: AuthUser,
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let revoked = auth_service.logout_all(user.id()).await?;
+    let details = json!({ "revoked_sessions": revoked });
+    audit.record_with(user.id(), AuditAction::LogoutAll, AuditTarget::User(user.id()), details).await;
+    Ok(HttpResponse::Ok().json(json!({ "revoked_sessions": revoked })))
+}
+
+pub async fn list_sessions(
+    user: AuthUser,
+    auth_service: web::Data<AuthService>,
+) -> Result<impl Responder, AppError> {
+    let current = user.require_session()?;
+    let sessions = auth_service.list_sessions(user.id()).await?;
+    let sessions: Vec<_> = sessions
+        .into_iter()
+        .map(|session| {
+            let is_current = session.id == current;
+            json!({ "session": session, "current": is_current })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "sessions": sessions })))
+}
+
+pub async fn verify_email(
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+    body: web::Json<VerifyEmailRequest>,
+) -> Result<impl Responder, AppError> {
+    let user_id = auth_service.verify_email(&body.token).await?;
+    audit.record(user_id, AuditAction::EmailVerified, AuditTarget::User(user_id)).await;
+    Ok(HttpResponse::Ok().json(json!({ "message": "Email address verified" })))
+}
+
+pub async fn resend_verification_email(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    body: web::Json<EmailRequest>,
+) -> Result<impl Responder, AppError> {
+    if let Err(e) = body.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    auth_service.resend_verification_email(&body.email, &SessionMeta::from_request(&req)).await?;
+    Ok(HttpResponse::Accepted().json(json!({
+        "message": "If the address belongs to an unverified account, a new link is on its way"
     })))
-} 
\ No newline at end of file
+}
+
+pub async fn request_password_reset(
+    req: HttpRequest,
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+    body: web::Json<EmailRequest>,
+) -> Result<impl Responder, AppError> {
+    if let Err(e) = body.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    auth_service.request_password_reset(&body.email, &SessionMeta::from_request(&req)).await?;
+    let details = json!({ "email": body.email });
+    audit.record_with(None, AuditAction::PasswordResetRequested, None, details).await;
+    Ok(HttpResponse::Accepted().json(json!({
+        "message": "If the address belongs to an account, a reset link is on its way"
+    })))
+}
+
+pub async fn reset_password(
+    audit: Audit,
+    auth_service: web::Data<AuthService>,
+    body: web::Json<ResetPasswordRequest>,
+) -> Result<impl Responder, AppError> {
+    if let Err(e) = body.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let user_id = auth_service.reset_password(&body.token, &body.new_password).await?;
+    audit.record(user_id, AuditAction::PasswordReset, AuditTarget::User(user_id)).await;
+    Ok(HttpResponse::Ok().json(json!({ "message": "Password updated; sign in again on every device" })))
+}
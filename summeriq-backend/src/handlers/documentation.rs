@@ -1,8 +1,21 @@
 use actix_web::{web, HttpResponse};
 use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
 use crate::services::{AIService, StorageService, AnalysisService};
+use crate::models::usage::AiFeature;
+use crate::services::ai::{AiResponse, Provenance};
+use crate::services::usage::UsageContext;
+use crate::services::OrganizationService;
+use crate::services::rate_limit::user_caller_key;
+use crate::services::prompts::{DocArchitecturePrompt, DocFileSummaryPrompt, DocFinalPrompt};
 use crate::error::AppError;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::api_token::ApiScope;
+use crate::models::organization::Permission;
 use std::fs;
+use tracing::warn;
 use urlencoding::decode;
 
 #[derive(Serialize)]
@@ -23,12 +36,31 @@ pub struct Relationship {
 
 #[derive(Serialize)]
 pub struct ProjectDocumentation {
+    /// Id of the stored copy, which share links refer to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
     pub project_name: String,
     pub description: String,
     pub architecture: String,
     pub file_analyses: Vec<FileAnalysisDoc>,
     pub dependencies: Vec<String>,
     pub setup_instructions: String,
+    /// Providers, models and prompt versions that generated the AI-written parts.
+    #[serde(flatten)]
+    pub provenance: Provenance,
+}
+
+/// The text of an AI answer, or `None` when the providers failed. An exhausted budget or a
+/// rate limit is returned, so the user is told to wait rather than sent placeholder docs.
+fn answer_text(result: Result<AiResponse<String>, AppError>, provenance: &mut Provenance) -> Result<Option<String>, AppError> {
+    match result {
+        Ok(response) => Ok(Some(provenance.record(response))),
+        Err(e @ (AppError::QuotaExceeded(_) | AppError::RateLimited { .. })) => Err(e),
+        Err(e) => {
+            warn!("Documentation step failed: {}", e);
+            Ok(None)
+        }
+    }
 }
 
 fn normalize_name(name: &str) -> String {
@@ -71,11 +103,16 @@ fn extract_markdown_section<'a>(content: &'a str, headings: &[&str]) -> Option<S
 }
 
 pub async fn get_project_documentation(
+    user: AuthUser,
+    audit: Audit,
     path: web::Path<String>,
     ai_service: web::Data<AIService>,
     storage_service: web::Data<StorageService>,
     _analysis_service: web::Data<AnalysisService>,
+    organizations: web::Data<OrganizationService>,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, AppError> {
+    user.require(ApiScope::Docs)?;
     tracing::info!("get_project_documentation: incoming path: {}", path);
     let path = path.into_inner();
     let decoded_path = decode(&path).map(|c| c.to_string()).unwrap_or(path.clone());
@@ -87,18 +124,25 @@ pub async fn get_project_documentation(
     let project_name = path_parts[0];
     tracing::info!("get_project_documentation: using project_name: {}", project_name);
     let uuid = storage_service.get_file_id(project_name).await?;
+    let upload_id = organizations.authorize_stored(user.id(), &uuid, Permission::Analyze).await?;
+    let ai_service = ai_service.for_caller(&user_caller_key(user.id())).with_context(UsageContext {
+        feature: AiFeature::Docs,
+        user_id: Some(user.id()),
+        upload_id: Some(upload_id),
+    });
     let extracted_dir = format!("extracted_{}", uuid);
     let files = storage_service.list_files(&extracted_dir).await?;
 
-    // Helper to flatten file tree to a list of (path, is_dir)
-    fn flatten_files(nodes: &[crate::services::storage::FileNode], parent: &str, out: &mut Vec<(String, bool)>) {
+    // Helper to flatten file tree to a list of (path, is_dir, is_source)
+    fn flatten_files(nodes: &[crate::services::storage::FileNode], parent: &str, out: &mut Vec<(String, bool, bool)>) {
         for node in nodes {
             let full_path = if parent.is_empty() {
                 node.name.clone()
             } else {
                 format!("{}/{}", parent, node.name)
             };
-            out.push((full_path.clone(), node.is_dir));
+            let is_source = node.metadata.as_ref().is_none_or(|metadata| metadata.is_source());
+            out.push((full_path.clone(), node.is_dir, is_source));
             if node.is_dir {
                 if let Some(children) = &node.children {
                     flatten_files(children, &full_path, out);
@@ -111,12 +155,12 @@ pub async fn get_project_documentation(
 
     // Key files to prioritize for content (entry points, configs, main modules)
     let key_file_names = [
-        "main.rs", "App.tsx", "app.tsx", "index.tsx", "index.js", "package.json", "Cargo.toml", "tsconfig.json", "next.config.js", "next.config.mjs", ".env", "README.md"
+        "main.rs", "App.tsx", "app.tsx", "index.tsx", "index.js", "package.json", "Cargo.toml", "tsconfig.json", "next.config.js", "next.config.mjs", "README.md"
     ];
 
     // Step 1: Generate the file/folder structure string
     let mut structure = String::new();
-    for (path, is_dir) in &file_list {
+    for (path, is_dir, _) in &file_list {
         if *is_dir {
             structure.push_str(&format!("[DIR] {}\n", path));
         } else {
@@ -125,37 +169,36 @@ pub async fn get_project_documentation(
     }
 
     // Step 1: Ask the AI for a high-level architecture summary based on the structure
-    let structure_prompt = format!(
-        r#"
-You are an expert technical writer and software architect. Here is the file and folder structure of a software project:
-
-{structure}
-
-Please give a high-level architectural overview of how the folders and files relate to each other. Focus on helping a junior developer understand how this is structured and why. If you include a Mermaid diagram, ALWAYS wrap it in a code block using triple backticks and the word 'mermaid' (like this: ```mermaid ... ```), with no extra text before or after the code block. Do not use meta language or explanations—output only the content and diagram.
-"#,
-        structure = structure
-    );
-    let structure_summary = ai_service.analyze_text(&structure_prompt).await.unwrap_or_else(|_| "".to_string());
+    let structure_prompt = ai_service.prompts().render(&DocArchitecturePrompt { structure: &structure })?;
+    let mut provenance = Provenance::default();
+    // Set when a step failed and a placeholder stands in for its answer; such docs are not stored
+    let mut incomplete = false;
+    let structure_summary = answer_text(ai_service.analyze_text(&structure_prompt).await, &mut provenance)?
+        .unwrap_or_else(|| {
+            incomplete = true;
+            String::new()
+        });
 
-    // Step 2: Dynamically select up to 8 key files for detailed summary
+    // Step 2: Dynamically select up to 8 key files for detailed summary, leaving out binary,
+    // generated and vendored files
     fn score_file(path: &str) -> i32 {
         let lower = path.to_lowercase();
         let mut score = 0;
         // Shallowest path (fewer slashes = higher score)
         score += 10 - lower.matches('/').count() as i32;
         // Name contains key words
-        for kw in ["main", "index", "config", "readme", "app", "setup", "env"] {
+        for kw in ["main", "index", "config", "readme", "app", "setup"] {
             if lower.contains(kw) { score += 5; }
         }
         // Code/config extension
-        for ext in [".rs", ".ts", ".tsx", ".js", ".jsx", ".json", ".toml", ".md", ".txt", ".yaml", ".yml", ".html", ".css", ".scss", ".mjs", ".cjs", ".env", ".lock", ".sql"] {
+        for ext in [".rs", ".ts", ".tsx", ".js", ".jsx", ".json", ".toml", ".md", ".txt", ".yaml", ".yml", ".html", ".css", ".scss", ".mjs", ".cjs", ".lock", ".sql"] {
             if lower.ends_with(ext) { score += 2; }
         }
         score
     }
     let mut scored_files: Vec<_> = file_list.iter()
-        .filter(|(_, is_dir)| !*is_dir)
-        .map(|(path, _)| (path.clone(), score_file(path)))
+        .filter(|(_, is_dir, is_source)| !*is_dir && *is_source)
+        .map(|(path, _, _)| (path.clone(), score_file(path)))
         .collect();
     scored_files.sort_by(|a, b| b.1.cmp(&a.1));
     let key_files: Vec<_> = scored_files.iter().take(8).map(|(p, _)| p.clone()).collect();
@@ -175,20 +218,12 @@ Please give a high-level architectural overview of how the folders and files rel
                 &content_bytes
             };
             if let Ok(content) = String::from_utf8(content_bytes.to_vec()) {
-                let file_prompt = format!(
-                    r#"
-Here is the file `{path}` from a software project:
-
----
-{content}
----
-
-Summarize in 1-2 sentences, directly and explicitly, what this file does and how it fits into the project. Do not use meta language, markdown formatting, or explanations—output only the summary.
-"#,
-                    path = path,
-                    content = content
-                );
-                description = ai_service.analyze_text(&file_prompt).await.unwrap_or_else(|_| format!("No summary available for {path}"));
+                let file_prompt = ai_service.prompts().render(&DocFileSummaryPrompt { path, content: &content })?;
+                description = answer_text(ai_service.analyze_text(&file_prompt).await, &mut provenance)?
+                    .unwrap_or_else(|| {
+                        incomplete = true;
+                        format!("No summary available for {path}")
+                    });
                 // Try to parse dependencies for package.json and Cargo.toml
                 if name == "package.json" {
                     if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
@@ -237,8 +272,8 @@ Summarize in 1-2 sentences, directly and explicitly, what this file does and how
 
     // Step 4: Extract setup instructions from README.md if present
     let mut setup_instructions = String::new();
-    let readme_path = file_list.iter().find(|(p, _)| p.to_lowercase().ends_with("readme.md"));
-    if let Some((readme_rel_path, _)) = readme_path {
+    let readme_path = file_list.iter().find(|(p, _, _)| p.to_lowercase().ends_with("readme.md"));
+    if let Some((readme_rel_path, _, _)) = readme_path {
         let full_path = format!("{}/{}", extracted_dir, readme_rel_path);
         if let Ok(content_bytes) = storage_service.read_file(&full_path).await {
             if let Ok(content) = String::from_utf8(content_bytes) {
@@ -273,27 +308,42 @@ Summarize in 1-2 sentences, directly and explicitly, what this file does and how
     if omitted_count > 0 {
         all_summaries.push_str(&format!("\n--- Some file summaries omitted due to size limits ({} omitted). ---\n", omitted_count));
     }
-    let final_prompt = format!(
-        r#"
-You are an expert technical writer, software architect, and educator. Your job is to generate the best possible documentation for this software project, specifically for junior developers and newcomers.
-
-Below are the project structure overview and summaries of key files. Please synthesize these into a complete, beginner-friendly documentation that explains the architecture, file relationships, technology stack, developer flow, and learning tips. Use diagrams, Markdown formatting, and a welcoming, educational tone.
-
-{all_summaries}
-
-Now, generate the final documentation as described above.
-"#,
-        all_summaries = all_summaries
-    );
-    let final_doc = ai_service.analyze_text(&final_prompt).await.unwrap_or_else(|_| "No documentation available.".to_string());
+    let final_prompt = ai_service.prompts().render(&DocFinalPrompt { summaries: &all_summaries })?;
+    let final_doc = answer_text(ai_service.analyze_text(&final_prompt).await, &mut provenance)?
+        .unwrap_or_else(|| {
+            incomplete = true;
+            "No documentation available.".to_string()
+        });
 
-    let doc = ProjectDocumentation {
+    let mut doc = ProjectDocumentation {
+        id: None,
         project_name: project_name.to_string(),
         description: final_doc,
         architecture: structure_summary,
         file_analyses,
         dependencies,
         setup_instructions,
+        provenance,
     };
+    if !incomplete {
+        let document = serde_json::to_value(&doc).map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        doc.id = Some(
+            sqlx::query_scalar!(
+                r#"
+                INSERT INTO project_docs (upload_id, document, served_by, prompt_versions)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id
+                "#,
+                upload_id,
+                document,
+                serde_json::to_value(&doc.provenance.served_by).map_err(|e| AppError::InternalServerError(e.to_string()))?,
+                serde_json::to_value(&doc.provenance.prompt_versions).map_err(|e| AppError::InternalServerError(e.to_string()))?
+            )
+            .fetch_one(pool.get_ref())
+            .await?,
+        );
+    }
+    let details = serde_json::json!({ "doc_id": doc.id });
+    audit.record_with(user.id(), AuditAction::DocsGenerated, AuditTarget::Upload(upload_id), details).await;
     Ok(HttpResponse::Ok().json(doc))
 } 
\ No newline at end of file
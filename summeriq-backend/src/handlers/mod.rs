@@ -1,4 +1,11 @@
+pub mod admin;
+pub mod api_tokens;
 pub mod upload;
 pub mod auth;
 pub mod analysis;
-pub mod documentation; 
\ No newline at end of file
+pub mod documentation;
+pub mod organizations;
+pub mod project;
+pub mod review;
+pub mod shares;
+pub mod usage;
@@ -0,0 +1,124 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::middleware::AuthUser;
+use crate::models::api_token::ApiScope;
+use crate::models::organization::{AcceptInvite, CreateOrganization, InviteMember, UpdateMemberRole};
+use crate::services::OrganizationService;
+
+// Changing an organization or its membership needs a signed-in session; API tokens may only read
+
+pub async fn create_organization(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    data: web::Json<CreateOrganization>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    if let Err(e) = data.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let org = organizations.create(user.id(), data.into_inner()).await?;
+    Ok(HttpResponse::Created().json(org))
+}
+
+pub async fn list_organizations(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let orgs = organizations.list(user.id()).await?;
+    Ok(HttpResponse::Ok().json(orgs))
+}
+
+pub async fn get_organization(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    org_id: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let org_id = org_id.into_inner();
+    let org = organizations.get(user.id(), org_id).await?;
+    let members = organizations.members(user.id(), org_id).await?;
+    Ok(HttpResponse::Ok().json(json!({ "organization": org, "members": members })))
+}
+
+pub async fn list_organization_uploads(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    org_id: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let uploads = organizations.uploads(user.id(), org_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(uploads))
+}
+
+pub async fn update_member_role(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Json<UpdateMemberRole>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let (org_id, member_id) = path.into_inner();
+    organizations.update_member_role(user.id(), org_id, member_id, data.role).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn remove_member(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let (org_id, member_id) = path.into_inner();
+    organizations.remove_member(user.id(), org_id, member_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn create_invite(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    org_id: web::Path<Uuid>,
+    data: web::Json<InviteMember>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    if let Err(e) = data.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let invite = organizations.invite(user.id(), org_id.into_inner(), data.into_inner()).await?;
+    Ok(HttpResponse::Created().json(invite))
+}
+
+pub async fn list_invites(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    org_id: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let invites = organizations.invites(user.id(), org_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(invites))
+}
+
+pub async fn revoke_invite(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let (org_id, invite_id) = path.into_inner();
+    organizations.revoke_invite(user.id(), org_id, invite_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn accept_invite(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    data: web::Json<AcceptInvite>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let org = organizations.accept_invite(user.id(), &data.token).await?;
+    Ok(HttpResponse::Ok().json(org))
+}
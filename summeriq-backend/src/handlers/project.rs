@@ -0,0 +1,116 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::middleware::AuthUser;
+use crate::models::api_token::ApiScope;
+use crate::models::file_index::TreeQuery;
+use crate::models::project::{AddProjectVersion, CreateProject};
+use crate::models::usage::AiFeature;
+use crate::services::rate_limit::user_caller_key;
+use crate::services::usage::UsageContext;
+use crate::services::{AIService, FileIndexService, ProjectService};
+
+#[derive(Debug, Deserialize)]
+pub struct VersionRange {
+    pub from: i32,
+    pub to: i32,
+}
+
+pub async fn create_project(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+    data: web::Json<CreateProject>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Upload)?;
+    if let Err(e) = data.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let project = project_service.create_project(user.id(), data.into_inner()).await?;
+    Ok(HttpResponse::Created().json(project))
+}
+
+pub async fn list_projects(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let projects = project_service.list_projects(user.id()).await?;
+    Ok(HttpResponse::Ok().json(projects))
+}
+
+pub async fn get_project(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+    project_id: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let project = project_service.get_project(user.id(), project_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(project))
+}
+
+pub async fn add_version(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+    project_id: web::Path<Uuid>,
+    data: web::Json<AddProjectVersion>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Upload)?;
+    if let Err(e) = data.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let version = project_service
+        .add_version(user.id(), project_id.into_inner(), data.into_inner())
+        .await?;
+    Ok(HttpResponse::Created().json(version))
+}
+
+/// One level of a project version's files at a time, from the persisted file index.
+pub async fn tree(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+    file_index_service: web::Data<FileIndexService>,
+    project_id: web::Path<Uuid>,
+    query: web::Query<TreeQuery>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let version = project_service
+        .find_version(user.id(), project_id.into_inner(), query.version)
+        .await?;
+    let page = file_index_service.list_tree(&version, &query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn diff_versions(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+    project_id: web::Path<Uuid>,
+    range: web::Query<VersionRange>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let diff = project_service
+        .diff_versions(user.id(), project_id.into_inner(), range.from, range.to)
+        .await?;
+    Ok(HttpResponse::Ok().json(diff))
+}
+
+pub async fn changelog(
+    user: AuthUser,
+    project_service: web::Data<ProjectService>,
+    ai_service: web::Data<AIService>,
+    project_id: web::Path<Uuid>,
+    range: web::Query<VersionRange>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Docs)?;
+    let ai_service = ai_service.for_caller(&user_caller_key(user.id())).with_context(UsageContext {
+        feature: AiFeature::Changelog,
+        user_id: Some(user.id()),
+        upload_id: None,
+    });
+    let changelog = project_service
+        .changelog(&ai_service, user.id(), project_id.into_inner(), range.from, range.to)
+        .await?;
+    Ok(HttpResponse::Ok().json(changelog))
+}
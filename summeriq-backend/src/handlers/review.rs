@@ -0,0 +1,40 @@
+use actix_web::{web, HttpResponse, Responder};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::middleware::AuthUser;
+use crate::models::api_token::ApiScope;
+use crate::models::organization::Permission;
+use crate::models::review::{FindingFilter, ReviewRequest};
+use crate::services::{OrganizationService, ReviewService};
+
+pub async fn review_upload(
+    user: AuthUser,
+    review_service: web::Data<ReviewService>,
+    organizations: web::Data<OrganizationService>,
+    upload_id: web::Path<Uuid>,
+    request: web::Json<ReviewRequest>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Docs)?;
+    let upload_id = upload_id.into_inner();
+    organizations.authorize_upload(user.id(), upload_id, Permission::Analyze).await?;
+    let report = match &request.path {
+        Some(path) => review_service.review_file(user.id(), upload_id, path).await?,
+        None => review_service.review_project(user.id(), upload_id).await?,
+    };
+    Ok(HttpResponse::Ok().json(report))
+}
+
+pub async fn list_findings(
+    user: AuthUser,
+    review_service: web::Data<ReviewService>,
+    organizations: web::Data<OrganizationService>,
+    upload_id: web::Path<Uuid>,
+    filter: web::Query<FindingFilter>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let upload_id = upload_id.into_inner();
+    organizations.authorize_upload(user.id(), upload_id, Permission::View).await?;
+    let findings = review_service.list_findings(upload_id, filter.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(findings))
+}
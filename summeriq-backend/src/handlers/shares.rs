@@ -0,0 +1,110 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::file_index::TreeQuery;
+use crate::models::session::SessionMeta;
+use crate::models::share::{CreateShareLink, ShareViewer};
+use crate::routes::upload::project_file_response;
+use crate::services::ShareService;
+
+/// Header carrying the password of a protected share link. A header rather than a query
+/// parameter keeps it out of access logs.
+pub const SHARE_PASSWORD_HEADER: &str = "X-Share-Password";
+
+// Share links publish data to anyone holding them, so only signed-in sessions may manage them
+
+pub async fn create_share_link(
+    user: AuthUser,
+    audit: Audit,
+    shares: web::Data<ShareService>,
+    data: web::Json<CreateShareLink>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    if let Err(e) = data.validate() {
+        return Err(AppError::BadRequest(format!("Validation error: {}", e)));
+    }
+    let created = shares.create(user.id(), data.into_inner()).await?;
+    let link = &created.link;
+    let details = serde_json::json!({
+        "resource": link.resource,
+        "resource_id": link.resource_id,
+        "expires_at": link.expires_at,
+        "password_protected": link.password_protected,
+    });
+    audit.record_with(user.id(), AuditAction::ShareCreated, AuditTarget::ShareLink(link.id), details).await;
+    Ok(HttpResponse::Created().json(created))
+}
+
+pub async fn list_share_links(
+    user: AuthUser,
+    shares: web::Data<ShareService>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let links = shares.list(user.id()).await?;
+    Ok(HttpResponse::Ok().json(links))
+}
+
+pub async fn revoke_share_link(
+    user: AuthUser,
+    audit: Audit,
+    shares: web::Data<ShareService>,
+    link_id: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    user.require_session()?;
+    let link_id = link_id.into_inner();
+    shares.revoke(user.id(), link_id).await?;
+    audit.record(user.id(), AuditAction::ShareRevoked, AuditTarget::ShareLink(link_id)).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Public: the resource a share link points to.
+pub async fn view_shared(
+    req: HttpRequest,
+    audit: Audit,
+    shares: web::Data<ShareService>,
+    token: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let client = SessionMeta::from_request(&req);
+    let (link_id, resource) = shares.view(&token, viewer(&req, &client)).await?;
+    audit.record(None, AuditAction::ShareViewed, AuditTarget::ShareLink(link_id)).await;
+    Ok(HttpResponse::Ok().json(resource))
+}
+
+/// Public: a directory of a shared upload, paged like a project tree.
+pub async fn view_shared_tree(
+    req: HttpRequest,
+    shares: web::Data<ShareService>,
+    token: web::Path<String>,
+    query: web::Query<TreeQuery>,
+) -> Result<impl Responder, AppError> {
+    let client = SessionMeta::from_request(&req);
+    let (_, page) = shares.tree(&token, viewer(&req, &client), &query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// Public: a file of a shared upload.
+pub async fn view_shared_file(
+    req: HttpRequest,
+    audit: Audit,
+    shares: web::Data<ShareService>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder, AppError> {
+    let (token, file_path) = path.into_inner();
+    let client = SessionMeta::from_request(&req);
+    let (link_id, content) = shares.file(&token, viewer(&req, &client), &file_path).await?;
+    let response = project_file_response(&file_path, content);
+    let details = serde_json::json!({ "path": file_path });
+    audit.record_with(None, AuditAction::ShareViewed, AuditTarget::ShareLink(link_id), details).await;
+    Ok(response)
+}
+
+fn viewer<'a>(req: &'a HttpRequest, client: &'a SessionMeta) -> ShareViewer<'a> {
+    ShareViewer {
+        password: req.headers().get(SHARE_PASSWORD_HEADER).and_then(|value| value.to_str().ok()),
+        address: client.client_address(),
+    }
+}
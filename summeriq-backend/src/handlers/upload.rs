@@ -1,18 +1,23 @@
 use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse, Responder, HttpRequest};
+use actix_web::{web, HttpResponse, Responder};
 use futures::{StreamExt, TryStreamExt};
 use serde_json::json;
 use tracing::{info, error};
 use uuid::Uuid;
-use crate::services::storage::FileNode;
+use crate::services::storage::{parse_ignore_patterns, ExcludedPaths, FileNode};
 use serde_json::Value;
 use sqlx::types::Json;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
 use crate::models::upload::{Upload, CreateUpload};
 use crate::services::StorageService;
-use crate::services::AuthService;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::api_token::ApiScope;
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::organization::Permission;
+use crate::models::secret::SecretFinding;
+use crate::services::{AuthService, FileIndexService, OrganizationService, SecretService};
 
 #[derive(Serialize)]
 struct UploadResponse {
@@ -20,41 +25,73 @@ struct UploadResponse {
     file_id: Uuid,
     filename: String,
     upload: UploadRecord,
+    /// Credentials found in the extracted files; they are redacted before reaching the AI.
+    secret_findings: Vec<SecretFinding>,
+}
+
+/// Query of an upload: the organization to share it with (without one it stays private),
+/// comma-separated path patterns to leave out of the archive, such as `.env,secrets/**`, and
+/// comma-separated gitignore-style patterns to hide from listings, analysis and docs.
+#[derive(Deserialize)]
+pub struct UploadTarget {
+    organization_id: Option<Uuid>,
+    exclude: Option<String>,
+    ignore: Option<String>,
 }
 
 #[derive(Serialize)]
 struct UploadRecord {
     id: Uuid,
     user_id: Uuid,
+    organization_id: Option<Uuid>,
     filename: String,
     original_filename: String,
     mime_type: String,
     size: i64,
+    /// Root level of an archive; `/api/projects/{id}/tree` lists the rest.
     extracted_files: Option<Value>,
+    excluded_paths: Vec<String>,
+    ignore_patterns: Vec<String>,
     created_at: Option<chrono::DateTime<chrono::Utc>>,
     updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+/// The top entries of a tree, without what lies below them.
+fn root_level(nodes: &[FileNode]) -> Vec<FileNode> {
+    nodes
+        .iter()
+        .map(|node| FileNode {
+            name: node.name.clone(),
+            path: node.path.clone(),
+            is_dir: node.is_dir,
+            children: None,
+            metadata: node.metadata.clone(),
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file(
     config: web::Data<Config>,
     storage_service: web::Data<StorageService>,
     auth_service: web::Data<AuthService>,
-    req: HttpRequest,
+    organizations: web::Data<OrganizationService>,
+    secret_service: web::Data<SecretService>,
+    file_index_service: web::Data<FileIndexService>,
+    user: AuthUser,
+    audit: Audit,
+    target: web::Query<UploadTarget>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, crate::error::AppError> {
-    // Get user ID from auth token
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| crate::error::AppError::AuthenticationError("Missing authorization header".to_string()))?;
-
-    let token = auth_header
-        .strip_prefix("Bearer ")
-        .ok_or_else(|| crate::error::AppError::AuthenticationError("Invalid token format".to_string()))?;
-
-    let user_id = auth_service.verify_token(token)?;
+    user.require(ApiScope::Upload)?;
+    let user_id = user.id();
+    if let Some(org_id) = target.organization_id {
+        organizations.authorize(user_id, org_id, Permission::Upload).await?;
+    }
+    let excluded = ExcludedPaths::parse(target.exclude.as_deref().unwrap_or_default())?;
+    let ignore_patterns = parse_ignore_patterns(target.ignore.as_deref().unwrap_or_default())?;
 
+    let max_upload_bytes = config.max_upload_mb * 1024 * 1024;
     let mut file_content = Vec::new();
     let mut filename = None;
     let mut mime_type = None;
@@ -66,6 +103,12 @@ pub async fn upload_file(
 
         while let Some(chunk) = field.next().await {
             let data = chunk?;
+            if (file_content.len() + data.len()) as u64 > max_upload_bytes {
+                return Err(crate::error::AppError::UploadError(format!(
+                    "File exceeds the upload limit of {} MB",
+                    config.max_upload_mb
+                )));
+            }
             file_content.extend_from_slice(&data);
         }
     }
@@ -75,33 +118,47 @@ pub async fn upload_file(
     let file_id = Uuid::new_v4();
     let final_filename = format!("{}_{}", file_id, filename);
 
+    let is_archive = mime_type == "application/zip" || filename.ends_with(".zip") || filename.ends_with(".sip");
+    let extract_dir = format!("extracted_{}", file_id);
+    if is_archive && !excluded.is_empty() {
+        file_content = storage_service.remove_excluded(&file_content, &excluded)?;
+    }
+
     // Save file to storage
     storage_service.save_file(&file_content, &final_filename).await?;
 
     // Extract ZIP file if it's a ZIP or SIP
-    let extracted_files = if mime_type == "application/zip" || filename.ends_with(".zip") || filename.ends_with(".sip") {
-        let extract_dir = format!("extracted_{}", file_id);
-        storage_service.extract_zip(&file_content, &extract_dir).await?;
-        let files = storage_service.list_files(&extract_dir).await?;
-        Some(serde_json::to_value(files).map_err(|e| crate::error::AppError::InternalServerError(e.to_string()))?)
+    let files = if is_archive {
+        storage_service.extract_zip(&file_content, &extract_dir, &ignore_patterns).await?;
+        Some(storage_service.list_files(&extract_dir).await?)
     } else {
         None
     };
+    // Whole trees can be huge: only the root level is stored and returned, deeper levels are
+    // listed from the file index
+    let extracted_files = files
+        .as_deref()
+        .map(|nodes| serde_json::to_value(root_level(nodes)))
+        .transpose()
+        .map_err(|e| crate::error::AppError::InternalServerError(e.to_string()))?;
 
     let extracted_files_json = extracted_files.map(Json);
 
     let rec = sqlx::query!(
         r#"
-        INSERT INTO uploads (user_id, filename, original_filename, mime_type, size, extracted_files)
-        VALUES ($1, $2, $3, $4, $5, $6)
-        RETURNING id, user_id, filename, original_filename, mime_type, size, extracted_files as "extracted_files: Json<Value>", created_at, updated_at
+        INSERT INTO uploads (user_id, organization_id, filename, original_filename, mime_type, size, extracted_files, excluded_paths, ignore_patterns)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id, user_id, organization_id, filename, original_filename, mime_type, size, extracted_files as "extracted_files: Json<Value>", excluded_paths, ignore_patterns, created_at, updated_at
         "#, 
         user_id,
+        target.organization_id,
         final_filename, 
         filename,
         mime_type,
         file_content.len() as i64, 
-        extracted_files_json.map(|v| v.0)
+        extracted_files_json.map(|v| v.0),
+        &excluded.patterns(),
+        &ignore_patterns
     )
     .fetch_one(&auth_service.pool)
     .await?;
@@ -109,30 +166,80 @@ pub async fn upload_file(
     let upload_record = UploadRecord {
         id: rec.id,
         user_id: rec.user_id,
+        organization_id: rec.organization_id,
         filename: rec.filename,
         original_filename: rec.original_filename,
         mime_type: rec.mime_type,
         size: rec.size,
         extracted_files: rec.extracted_files.map(|v| v.0),
+        excluded_paths: rec.excluded_paths,
+        ignore_patterns: rec.ignore_patterns,
         created_at: rec.created_at,
         updated_at: rec.updated_at,
     };
 
+    if let Some(files) = &files {
+        file_index_service.index_upload(upload_record.id, files).await?;
+    }
+
+    let secret_findings = if is_archive {
+        secret_service.scan_upload(upload_record.id, &extract_dir).await?
+    } else {
+        Vec::new()
+    };
+
+    let details = json!({
+        "original_filename": upload_record.original_filename,
+        "size": upload_record.size,
+        "organization_id": upload_record.organization_id,
+        "excluded_paths": upload_record.excluded_paths,
+        "secret_findings": secret_findings.len(),
+    });
+    audit.record_with(user_id, AuditAction::UploadCreated, AuditTarget::Upload(upload_record.id), details).await;
     info!("File uploaded successfully: {}", final_filename);
     Ok(HttpResponse::Created().json(UploadResponse {
         message: "File uploaded successfully".to_string(),
         file_id,
         filename: final_filename,
         upload: upload_record,
+        secret_findings,
     }))
 }
 
-pub async fn get_file(
+/// Credentials the secret scanner found in an upload.
+pub async fn list_secret_findings(
+    user: AuthUser,
+    organizations: web::Data<OrganizationService>,
+    secret_service: web::Data<SecretService>,
+    upload_id: web::Path<Uuid>,
+) -> Result<HttpResponse, crate::error::AppError> {
+    user.require(ApiScope::Read)?;
+    let upload_id = upload_id.into_inner();
+    organizations.authorize_upload(user.id(), upload_id, Permission::View).await?;
+    let findings = secret_service.list_findings(upload_id).await?;
+    Ok(HttpResponse::Ok().json(findings))
+}
+
+/// Deletes an upload with everything derived from it, and its stored files.
+pub async fn delete_upload(
+    user: AuthUser,
+    audit: Audit,
+    organizations: web::Data<OrganizationService>,
     storage_service: web::Data<StorageService>,
-    file_id: web::Path<Uuid>,
+    auth_service: web::Data<AuthService>,
+    upload_id: web::Path<Uuid>,
 ) -> Result<HttpResponse, crate::error::AppError> {
-    let content = storage_service.read_file(&file_id.to_string()).await?;
-    Ok(HttpResponse::Ok()
-        .content_type("application/octet-stream")
-        .body(content))
-} 
\ No newline at end of file
+    user.require(ApiScope::Upload)?;
+    let upload_id = upload_id.into_inner();
+    organizations.authorize_upload(user.id(), upload_id, Permission::Delete).await?;
+
+    let filename = sqlx::query_scalar!("DELETE FROM uploads WHERE id = $1 RETURNING filename", upload_id)
+        .fetch_optional(&auth_service.pool)
+        .await?
+        .ok_or_else(|| crate::error::AppError::NotFound("Upload not found".to_string()))?;
+    storage_service.delete_upload(&filename).await?;
+    audit.record(user.id(), AuditAction::UploadDeleted, AuditTarget::Upload(upload_id)).await;
+
+    info!("User {} deleted upload {}", user.id(), upload_id);
+    Ok(HttpResponse::NoContent().finish())
+}
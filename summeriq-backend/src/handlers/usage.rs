@@ -0,0 +1,31 @@
+use actix_web::{web, HttpResponse, Responder};
+use tracing::info;
+
+use crate::error::AppError;
+use crate::middleware::{AdminUser, AuthUser};
+use crate::models::api_token::ApiScope;
+use crate::models::usage::UsageQuery;
+use crate::services::rate_limit::user_caller_key;
+use crate::services::UsageService;
+
+pub async fn my_usage(
+    user: AuthUser,
+    usage_service: web::Data<UsageService>,
+    query: web::Query<UsageQuery>,
+) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    let report = usage_service
+        .user_report(&user_caller_key(user.id()), user.id(), query.days)
+        .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+pub async fn all_usage(
+    admin: AdminUser,
+    usage_service: web::Data<UsageService>,
+    query: web::Query<UsageQuery>,
+) -> Result<impl Responder, AppError> {
+    info!("Admin {} requested the AI usage report", admin.id());
+    let report = usage_service.admin_report(query.days).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
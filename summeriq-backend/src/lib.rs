@@ -1,8 +1,12 @@
+pub mod app;
 pub mod config;
 pub mod db;
 pub mod error;
 pub mod handlers;
+pub mod middleware;
 pub mod models;
 pub mod storage;
 pub mod services;
-pub mod routes; 
\ No newline at end of file
+pub mod routes;
+
+pub use app::{build_app, AppState};
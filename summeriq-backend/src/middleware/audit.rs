@@ -0,0 +1,60 @@
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::session::SessionMeta;
+use crate::services::audit::{AuditService, NewAuditEvent};
+
+/// Writes audit events for the current request, stamped with its client address and
+/// user agent.
+pub struct Audit {
+    service: web::Data<AuditService>,
+    client: SessionMeta,
+}
+
+impl Audit {
+    pub async fn record(
+        &self,
+        actor_id: impl Into<Option<Uuid>>,
+        action: AuditAction,
+        target: impl Into<Option<AuditTarget>>,
+    ) {
+        self.record_with(actor_id, action, target, Value::Null).await
+    }
+
+    /// Like [`Audit::record`], with action-specific `details`.
+    pub async fn record_with(
+        &self,
+        actor_id: impl Into<Option<Uuid>>,
+        action: AuditAction,
+        target: impl Into<Option<AuditTarget>>,
+        details: Value,
+    ) {
+        self.service
+            .record(NewAuditEvent {
+                actor_id: actor_id.into(),
+                action,
+                target: target.into(),
+                client: self.client.clone(),
+                details,
+            })
+            .await
+    }
+}
+
+impl FromRequest for Audit {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.app_data::<web::Data<AuditService>>()
+                .cloned()
+                .map(|service| Audit { service, client: SessionMeta::from_request(req) })
+                .ok_or_else(|| AppError::InternalServerError("Audit service not configured".to_string())),
+        )
+    }
+}
@@ -1,54 +1,114 @@
-use axum::{
-    extract::Request,
-    http::StatusCode,
-    middleware::Next,
-    response::Response,
-};
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use serde::{Deserialize, Serialize};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
 use uuid::Uuid;
-use crate::{
-    error::AppError,
-    services::auth::AuthService,
-};
-use std::sync::Arc;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Claims {
-    pub sub: Uuid,
-    pub exp: usize,
-}
 
-pub struct AuthMiddleware {
-    auth_service: Arc<AuthService>,
+use crate::error::AppError;
+use crate::models::api_token::ApiScope;
+use crate::services::auth::Credential;
+use crate::services::AuthService;
+
+/// Authenticated user extracted from the `Authorization: Bearer <token>` header, which holds
+/// either a session access token or a personal API token.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    user_id: Uuid,
+    credential: Credential,
 }
 
-impl AuthMiddleware {
-    pub fn new(auth_service: Arc<AuthService>) -> Self {
-        Self { auth_service }
+impl AuthUser {
+    pub fn id(&self) -> Uuid {
+        self.user_id
+    }
+
+    /// Session the access token belongs to; `None` for API tokens.
+    pub fn session_id(&self) -> Option<Uuid> {
+        match self.credential {
+            Credential::Session(session_id) => Some(session_id),
+            Credential::ApiToken { .. } => None,
+        }
+    }
+
+    /// Fails unless the credential allows `scope`. Sessions allow every scope.
+    pub fn require(&self, scope: ApiScope) -> Result<(), AppError> {
+        match &self.credential {
+            Credential::Session(_) => Ok(()),
+            Credential::ApiToken { scopes, .. } if scopes.contains(&scope) => Ok(()),
+            Credential::ApiToken { .. } => Err(AppError::Forbidden(format!(
+                "API token lacks the '{}' scope",
+                scope.as_str()
+            ))),
+        }
+    }
+
+    /// Session of the request, failing for API tokens, which may not manage the account.
+    pub fn require_session(&self) -> Result<Uuid, AppError> {
+        self.session_id()
+            .ok_or_else(|| AppError::Forbidden("This endpoint needs a signed-in session, not an API token".to_string()))
     }
 }
 
-pub async fn auth_middleware(
-    auth: Arc<AuthService>,
-    req: Request,
-    next: Next,
-) -> Result<Response, AppError> {
-    let auth_header = req
+/// Resolves the bearer token of `req`. The returned future owns everything it needs, so it
+/// can be awaited after the request is released.
+fn authenticate(req: &HttpRequest) -> LocalBoxFuture<'static, Result<AuthUser, AppError>> {
+    let auth_service = req.app_data::<web::Data<AuthService>>().cloned();
+    let token = req
         .headers()
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| AppError::AuthError("Missing authorization header".into()))?;
+        .ok_or_else(|| AppError::AuthenticationError("Missing authorization header".to_string()))
+        .and_then(|header| {
+            header
+                .strip_prefix("Bearer ")
+                .map(str::to_string)
+                .ok_or_else(|| AppError::AuthenticationError("Invalid token format".to_string()))
+        });
 
-    let token = auth_header
-        .strip_prefix("Bearer ")
-        .ok_or_else(|| AppError::AuthError("Invalid token format".into()))?;
+    Box::pin(async move {
+        let auth_service = auth_service
+            .ok_or_else(|| AppError::InternalServerError("Auth service not configured".to_string()))?;
+        let verified = auth_service.verify_token(&token?).await?;
+        Ok(AuthUser {
+            user_id: verified.user_id,
+            credential: verified.credential,
+        })
+    })
+}
 
-    let user_id = auth.verify_token(token)?;
+impl FromRequest for AuthUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
-    // Add user_id to request extensions
-    let mut req = req;
-    req.extensions_mut().insert(user_id);
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        authenticate(req)
+    }
+}
+
+/// Authenticated user whose account is flagged as administrator.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminUser(pub Uuid);
+
+impl AdminUser {
+    pub fn id(&self) -> Uuid {
+        self.0
+    }
+}
 
-    Ok(next.run(req).await)
+impl FromRequest for AdminUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let user = authenticate(req);
+        let auth_service = req.app_data::<web::Data<AuthService>>().cloned();
+        Box::pin(async move {
+            let user = user.await?;
+            user.require_session()?;
+            let auth_service = auth_service
+                .ok_or_else(|| AppError::InternalServerError("Auth service not configured".to_string()))?;
+            if !auth_service.is_admin(user.id()).await? {
+                return Err(AppError::Forbidden("Administrator access required".to_string()));
+            }
+            Ok(AdminUser(user.id()))
+        })
+    }
 }
@@ -1 +1,5 @@
+pub mod audit;
+pub mod auth;
 
+pub use audit::Audit;
+pub use auth::{AdminUser, AuthUser};
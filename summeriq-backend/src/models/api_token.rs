@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+/// What a personal API token may be used for. Signed-in sessions have every scope.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiScope {
+    /// Upload archives and create projects and versions.
+    Upload,
+    /// Read projects, files, analyses, findings and usage.
+    Read,
+    /// Generate documentation, changelogs, reviews and chat answers.
+    Docs,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [ApiScope::Upload, ApiScope::Read, ApiScope::Docs];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::Upload => "upload",
+            ApiScope::Read => "read",
+            ApiScope::Docs => "docs",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "upload" => Some(ApiScope::Upload),
+            "read" => Some(ApiScope::Read),
+            "docs" => Some(ApiScope::Docs),
+            _ => None,
+        }
+    }
+}
+
+/// A personal API token as listed to its owner; the secret itself is never shown again.
+#[derive(Debug, Serialize)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    /// First characters of the token, to tell tokens apart.
+    pub token_prefix: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiToken {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub scopes: Vec<ApiScope>,
+    /// Days until the token stops working; it never expires when absent.
+    #[validate(range(min = 1, max = 3650))]
+    pub expires_in_days: Option<i64>,
+}
+
+/// Response to creating a token: the only time `token` is revealed.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// What an audit event records, stored in `audit_events.action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Register,
+    Login,
+    LoginFailed,
+    Logout,
+    LogoutAll,
+    EmailVerified,
+    PasswordResetRequested,
+    PasswordReset,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    UploadCreated,
+    UploadViewed,
+    UploadDeleted,
+    FileAnalyzed,
+    DocsGenerated,
+    ChatMessage,
+    ShareCreated,
+    ShareRevoked,
+    ShareViewed,
+    AuditExported,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Register => "auth.register",
+            AuditAction::Login => "auth.login",
+            AuditAction::LoginFailed => "auth.login_failed",
+            AuditAction::Logout => "auth.logout",
+            AuditAction::LogoutAll => "auth.logout_all",
+            AuditAction::EmailVerified => "auth.email_verified",
+            AuditAction::PasswordResetRequested => "auth.password_reset_requested",
+            AuditAction::PasswordReset => "auth.password_reset",
+            AuditAction::ApiTokenCreated => "api_token.create",
+            AuditAction::ApiTokenRevoked => "api_token.revoke",
+            AuditAction::UploadCreated => "upload.create",
+            AuditAction::UploadViewed => "upload.view",
+            AuditAction::UploadDeleted => "upload.delete",
+            AuditAction::FileAnalyzed => "analysis.file",
+            AuditAction::DocsGenerated => "docs.generate",
+            AuditAction::ChatMessage => "chat.message",
+            AuditAction::ShareCreated => "share.create",
+            AuditAction::ShareRevoked => "share.revoke",
+            AuditAction::ShareViewed => "share.view",
+            AuditAction::AuditExported => "audit.export",
+        }
+    }
+}
+
+/// What an event acted on, stored as `target_type` and `target_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditTarget {
+    User(Uuid),
+    Session(Uuid),
+    ApiToken(Uuid),
+    Upload(Uuid),
+    Conversation(Uuid),
+    ShareLink(Uuid),
+}
+
+impl AuditTarget {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuditTarget::User(_) => "user",
+            AuditTarget::Session(_) => "session",
+            AuditTarget::ApiToken(_) => "api_token",
+            AuditTarget::Upload(_) => "upload",
+            AuditTarget::Conversation(_) => "conversation",
+            AuditTarget::ShareLink(_) => "share_link",
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        match *self {
+            AuditTarget::User(id)
+            | AuditTarget::Session(id)
+            | AuditTarget::ApiToken(id)
+            | AuditTarget::Upload(id)
+            | AuditTarget::Conversation(id)
+            | AuditTarget::ShareLink(id) => id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Value,
+}
+
+/// Filters of the admin audit log query. Every filter is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<Uuid>,
+    /// An action such as `upload.delete`, or a prefix ending in `.` such as `auth.`.
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<i64>,
+    /// Events per page, 100 by default and at most 1000.
+    pub limit: Option<i64>,
+}
+
+/// Events newest first. `next_cursor` is set when older events may follow.
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    pub next_cursor: Option<i64>,
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+
+use crate::services::file_types::FileMetadata;
+
+/// A file or directory of an indexed project.
+#[derive(Debug, Serialize)]
+pub struct TreeEntry {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    /// Bytes of a file, or of every file below a directory.
+    pub size: i64,
+    /// Entries directly below a directory; absent for files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub child_count: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_type: Option<Json<FileMetadata>>,
+    /// Order of the entry in the tree, directories before files; the root is 0.
+    #[serde(skip)]
+    pub position: i32,
+    #[serde(skip)]
+    pub depth: i32,
+    /// Position of the last entry below a directory.
+    #[serde(skip)]
+    pub subtree_end: i32,
+}
+
+/// Query of a project tree listing.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TreeQuery {
+    /// Version of the project, the latest by default.
+    pub version: Option<i32>,
+    /// Directory to list, the project root by default.
+    pub path: Option<String>,
+    /// Levels below the directory to include, 1 by default.
+    pub depth: Option<i32>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<i32>,
+    /// Entries per page, 200 by default and at most 1000.
+    pub limit: Option<i64>,
+}
+
+/// Entries below a directory in tree order, directories before files. `next_cursor` is set
+/// when more entries may follow.
+#[derive(Debug, Serialize)]
+pub struct TreePage {
+    /// Project version listed; absent for listings of a shared upload.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    pub path: String,
+    /// The listed directory itself.
+    pub directory: TreeEntry,
+    pub entries: Vec<TreeEntry>,
+    pub next_cursor: Option<i32>,
+}
@@ -1,8 +1,17 @@
 pub mod user;
+pub mod api_token;
+pub mod audit;
 pub mod file;
+pub mod file_index;
 pub mod upload;
 pub mod session;
 pub mod message;
+pub mod organization;
+pub mod project;
+pub mod review;
+pub mod secret;
+pub mod share;
+pub mod usage;
 
 // Only export what's actually used
 pub use user::User;
@@ -10,3 +19,6 @@ pub use session::*;
 pub use upload::*;
 pub use file::*;
 pub use message::*;
+pub use project::*;
+pub use review::*;
+pub use usage::*;
@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+/// A member's role in an organization, from most to least privileged.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Viewer,
+    Member,
+    Admin,
+    Owner,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Owner => "owner",
+            OrgRole::Admin => "admin",
+            OrgRole::Member => "member",
+            OrgRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "owner" => Some(OrgRole::Owner),
+            "admin" => Some(OrgRole::Admin),
+            "member" => Some(OrgRole::Member),
+            "viewer" => Some(OrgRole::Viewer),
+            _ => None,
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        *self >= permission.min_role()
+    }
+}
+
+/// Something a role may do with an organization or the uploads it owns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Browse files and read stored findings.
+    View,
+    /// Run AI analysis, documentation, review and chat.
+    Analyze,
+    Upload,
+    /// Create public share links.
+    Share,
+    Delete,
+    /// Invite, remove and change the role of members.
+    ManageMembers,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::View => "view",
+            Permission::Analyze => "analyze",
+            Permission::Upload => "upload",
+            Permission::Share => "share",
+            Permission::Delete => "delete",
+            Permission::ManageMembers => "manage members",
+        }
+    }
+
+    fn min_role(&self) -> OrgRole {
+        match self {
+            Permission::View => OrgRole::Viewer,
+            Permission::Analyze | Permission::Upload | Permission::Share => OrgRole::Member,
+            Permission::Delete | Permission::ManageMembers => OrgRole::Admin,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Role of the user the organization was listed for.
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrgMember {
+    pub user_id: Uuid,
+    pub email: String,
+    pub full_name: String,
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrgInvite {
+    pub id: Uuid,
+    pub email: String,
+    pub role: OrgRole,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Response to inviting someone. The token is also emailed to the invitee.
+#[derive(Debug, Serialize)]
+pub struct CreatedOrgInvite {
+    pub token: String,
+    #[serde(flatten)]
+    pub invite: OrgInvite,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateOrganization {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct InviteMember {
+    #[validate(email)]
+    pub email: String,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRole {
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptInvite {
+    pub token: String,
+}
+
+/// An upload shared with an organization, as listed to its members.
+#[derive(Debug, Serialize)]
+pub struct OrgUpload {
+    pub id: Uuid,
+    pub uploaded_by: Uuid,
+    /// Stored name, `{uuid}_{original name}`, used as the project name in file routes.
+    pub filename: String,
+    pub original_filename: String,
+    pub size: i64,
+    pub created_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::upload::extracted_dir_for;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Project {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectVersion {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub upload_id: Uuid,
+    pub version: i32,
+    pub label: Option<String>,
+    /// Stored filename of the upload (`{uuid}_{original}`), used to locate `extracted_{uuid}`.
+    #[serde(skip_serializing)]
+    pub filename: String,
+    pub original_filename: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl ProjectVersion {
+    /// Directory (relative to the storage root) the upload was extracted into.
+    pub fn extracted_dir(&self) -> String {
+        extracted_dir_for(&self.filename)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectWithVersions {
+    #[serde(flatten)]
+    pub project: Project,
+    pub versions: Vec<ProjectVersion>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateProject {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddProjectVersion {
+    pub upload_id: Uuid,
+    #[validate(length(max = 255))]
+    pub label: Option<String>,
+}
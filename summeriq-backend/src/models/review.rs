@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FindingCategory {
+    Bug,
+    Security,
+    Performance,
+    Style,
+}
+
+impl FindingCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FindingCategory::Bug => "bug",
+            FindingCategory::Security => "security",
+            FindingCategory::Performance => "performance",
+            FindingCategory::Style => "style",
+        }
+    }
+}
+
+/// Finding severity, ordered from least to most severe.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// A stored code review finding.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviewFinding {
+    pub id: Uuid,
+    pub upload_id: Uuid,
+    pub file_path: String,
+    pub category: String,
+    pub severity: String,
+    pub line_start: i32,
+    pub line_end: i32,
+    pub explanation: String,
+    pub suggested_fix: Option<String>,
+    /// Provider and model that produced the finding.
+    pub ai_provider: Option<String>,
+    pub ai_model: Option<String>,
+    pub prompt_version: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of a review request.
+#[derive(Debug, Serialize)]
+pub struct ReviewReport {
+    pub findings: Vec<ReviewFinding>,
+    /// Files sent to the AI; unreadable, oversized and failed files are not counted.
+    pub files_reviewed: usize,
+    /// Reviewable source files found in the request's scope.
+    pub files_total: usize,
+    /// True when files were left out because the project exceeds `AI_REVIEW_MAX_FILES`.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    /// Path of a single file inside the upload; the whole project is reviewed when absent.
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FindingFilter {
+    pub category: Option<FindingCategory>,
+    pub severity: Option<Severity>,
+    pub min_severity: Option<Severity>,
+    pub file: Option<String>,
+}
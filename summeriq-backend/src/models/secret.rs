@@ -0,0 +1,17 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A credential found in an uploaded file. Only a masked preview of the secret is kept.
+#[derive(Debug, Serialize)]
+pub struct SecretFinding {
+    pub id: Uuid,
+    pub upload_id: Uuid,
+    pub file_path: String,
+    /// Kind of secret, such as `aws_access_key`, `private_key` or `sensitive_file`.
+    pub rule: String,
+    /// Line the secret starts on; absent for files flagged by name.
+    pub line: Option<i32>,
+    pub preview: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
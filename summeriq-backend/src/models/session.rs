@@ -1,11 +1,91 @@
+use actix_web::{web, HttpRequest};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use uuid::Uuid;
 
+use crate::config::Config;
+
+/// A signed-in device: created at login and kept alive by refreshing its tokens.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Session {
     pub id: Uuid,
     pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
 }
+
+/// Where a login came from, recorded on its session.
+#[derive(Debug, Clone, Default)]
+pub struct SessionMeta {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl SessionMeta {
+    /// Device details of the client sending `req`.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        Self {
+            user_agent: req
+                .headers()
+                .get("User-Agent")
+                .and_then(|h| h.to_str().ok())
+                .map(|agent| agent.chars().take(512).collect()),
+            ip_address: client_ip(req).map(|ip| ip.to_string()),
+        }
+    }
+
+    /// Key for per-address limits; requests without a known address share one.
+    pub fn client_address(&self) -> &str {
+        self.ip_address.as_deref().unwrap_or("unknown")
+    }
+}
+
+/// The address `req` came from. Forwarding headers can be set by anyone, so they are only
+/// believed when the connection comes from a trusted proxy (`TRUSTED_PROXIES`); the client is
+/// then the last `X-Forwarded-For` address that is not itself a trusted proxy.
+fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let trusted: Vec<IpAddr> = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.trusted_proxies.iter().filter_map(|proxy| proxy.parse().ok()).collect())
+        .unwrap_or_default();
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    // Proxies append to the header, so only its right end is trustworthy
+    for address in forwarded.iter().rev() {
+        match address.trim().parse::<IpAddr>() {
+            Ok(ip) if trusted.contains(&ip) => continue,
+            Ok(ip) => return Some(ip),
+            Err(_) => break,
+        }
+    }
+    Some(peer)
+}
+
+/// Tokens handed to a client at login and on every refresh.
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    /// Short-lived JWT for the `Authorization: Bearer` header.
+    pub token: String,
+    /// Single-use token for `POST /api/auth/refresh`.
+    pub refresh_token: String,
+    /// Seconds until `token` expires.
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::file_index::TreePage;
+
+/// What a share link gives access to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareResource {
+    /// The files of an upload.
+    Upload,
+    /// A generated project documentation.
+    Doc,
+    Conversation,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateShareLink {
+    pub resource: ShareResource,
+    pub resource_id: Uuid,
+    /// Hours until the link stops working; `SHARE_LINK_TTL_HOURS` when absent.
+    #[validate(range(min = 1, max = 720))]
+    pub expires_in_hours: Option<i64>,
+    /// Password viewers must send in the `X-Share-Password` header.
+    #[validate(length(min = 8, max = 128))]
+    pub password: Option<String>,
+}
+
+/// A share link as listed to the user who created it.
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    pub id: Uuid,
+    pub resource: ShareResource,
+    pub resource_id: Uuid,
+    pub password_protected: bool,
+    pub view_count: i32,
+    pub last_viewed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Response to creating a share link: the only time its token is revealed.
+#[derive(Debug, Serialize)]
+pub struct CreatedShareLink {
+    pub token: String,
+    /// Page of the web app showing the shared resource.
+    pub url: String,
+    #[serde(flatten)]
+    pub link: ShareLink,
+}
+
+/// What a viewer presents to open a share link.
+#[derive(Debug, Clone, Copy)]
+pub struct ShareViewer<'a> {
+    /// The `X-Share-Password` header.
+    pub password: Option<&'a str>,
+    /// Client address that wrong passwords are counted against.
+    pub address: &'a str,
+}
+
+/// What an anonymous viewer of a share link gets: the shared resource and nothing about
+/// who owns it.
+#[derive(Debug, Serialize)]
+#[serde(tag = "resource", rename_all = "lowercase")]
+pub enum SharedResource {
+    Upload {
+        original_filename: String,
+        size: i64,
+        /// First page of the root directory of an archive, absent for single files. Deeper
+        /// levels are listed by `/api/public/shares/{token}/tree` and contents served from
+        /// `/api/public/shares/{token}/files/{path}`.
+        files: Option<Box<TreePage>>,
+        created_at: Option<DateTime<Utc>>,
+    },
+    Doc {
+        /// The `ProjectDocumentation` as it was generated.
+        document: Value,
+        created_at: DateTime<Utc>,
+    },
+    Conversation {
+        title: Option<String>,
+        messages: Vec<SharedMessage>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct SharedMessage {
+    pub role: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -8,6 +8,8 @@ use crate::services::storage::FileNode;
 pub struct Upload {
     pub id: Uuid,
     pub user_id: Uuid,
+    /// Organization sharing the upload; private to `user_id` when absent.
+    pub organization_id: Option<Uuid>,
     pub filename: String,
     pub original_filename: String,
     pub mime_type: String,
@@ -24,3 +26,9 @@ pub struct CreateUpload {
     pub size: i64,
     pub extracted_files: Option<Value>,
 }
+
+/// Directory (relative to the storage root) an upload stored as `{uuid}_{original}` was extracted into.
+pub fn extracted_dir_for(filename: &str) -> String {
+    let storage_id = filename.split('_').next().unwrap_or(filename);
+    format!("extracted_{}", storage_id)
+}
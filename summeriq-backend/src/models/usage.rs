@@ -0,0 +1,84 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Product feature an AI call is made for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AiFeature {
+    Chat,
+    Analysis,
+    Docs,
+    Review,
+    Changelog,
+    Other,
+}
+
+impl AiFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AiFeature::Chat => "chat",
+            AiFeature::Analysis => "analysis",
+            AiFeature::Docs => "docs",
+            AiFeature::Review => "review",
+            AiFeature::Changelog => "changelog",
+            AiFeature::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// Length of the reporting window in days, ending now (default 30).
+    pub days: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureUsage {
+    pub feature: String,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyUsage {
+    pub date: NaiveDate,
+    pub requests: i64,
+    pub total_tokens: i64,
+}
+
+/// Today's consumption against the daily token budget; `limit` is absent when unlimited.
+#[derive(Debug, Serialize)]
+pub struct BudgetStatus {
+    pub limit: Option<i64>,
+    pub used_today: i64,
+    pub remaining_today: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserUsageReport {
+    pub days: i32,
+    pub budget: BudgetStatus,
+    pub by_feature: Vec<FeatureUsage>,
+    pub daily: Vec<DailyUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserUsageTotal {
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUsageReport {
+    pub days: i32,
+    pub by_user: Vec<UserUsageTotal>,
+    pub by_feature: Vec<FeatureUsage>,
+    pub daily: Vec<DailyUsage>,
+}
@@ -8,14 +8,26 @@ use crate::config::Config;
 use crate::services::StorageService;
 use crate::services::ai::AIService;
 use crate::error::AppError;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::api_token::ApiScope;
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::organization::Permission;
+use crate::models::usage::AiFeature;
+use crate::services::usage::UsageContext;
+use crate::services::OrganizationService;
+use crate::services::rate_limit::user_caller_key;
 
 pub async fn analyze_file(
+    user: AuthUser,
+    audit: Audit,
     path: web::Path<String>,
     analysis_service: web::Data<AnalysisService>,
     storage_service: web::Data<StorageService>,
     ai_service: web::Data<AIService>,
+    organizations: web::Data<OrganizationService>,
     config: web::Data<Config>,
 ) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
     info!("Analyzing file: {}", path);
     
     // Decode the URL-encoded path
@@ -41,22 +53,29 @@ pub async fn analyze_file(
     
     // Get the UUID from the storage service
     let uuid = storage_service.get_file_id(project).await?;
+    let upload_id = organizations.authorize_stored(user.id(), &uuid, Permission::Analyze).await?;
     
-    // Construct the full path with storage directory and UUID
-    let full_path = if inner_path.is_empty() {
-        format!("extracted_{}/{}", uuid, project)
-    } else {
-        format!("extracted_{}/{}", uuid, inner_path)
-    };
-    info!("Full path for analysis: {}", full_path);
+    // Paths are relative to the extracted project directory
+    let extracted_dir = format!("extracted_{}", uuid);
+    let file_path = if inner_path.is_empty() { project } else { inner_path.as_str() };
+    info!("Path for analysis: {}/{}", extracted_dir, file_path);
 
-    // Read the file content
-    let content = storage_service.read_file(&full_path).await?;
-    let file_content = String::from_utf8(content)
-        .map_err(|e| AppError::InternalServerError(format!("Failed to read file content: {}", e)))?;
+    // Read the file content; ignored files are not analyzed
+    let content = storage_service.read_project_file(&extracted_dir, file_path).await?;
 
-    // Analyze the file using the AnalysisService
-    let analysis = analysis_service.analyze_file(&full_path, &file_content).await?;
+    // Analyze the file using the AnalysisService. The AI sees the path within the project,
+    // not where the upload happens to be stored.
+    let context = UsageContext {
+        feature: AiFeature::Analysis,
+        user_id: Some(user.id()),
+        upload_id: Some(upload_id),
+    };
+    let analysis = analysis_service
+        .for_caller(&user_caller_key(user.id()), context)
+        .analyze_content(file_path, &content)
+        .await?;
+    let details = serde_json::json!({ "path": inner_path });
+    audit.record_with(user.id(), AuditAction::FileAnalyzed, AuditTarget::Upload(upload_id), details).await;
 
     Ok(HttpResponse::Ok().json(analysis))
 } 
\ No newline at end of file
@@ -3,6 +3,7 @@ use serde_json::json;
 use tracing::{info, error};
 
 use crate::error::AppError;
+use crate::models::session::SessionMeta;
 use crate::models::user::{CreateUser, LoginUser};
 use crate::services::AuthService;
 
@@ -10,7 +11,7 @@ pub async fn register(
     auth_service: web::Data<AuthService>,
     user_data: web::Json<CreateUser>,
 ) -> Result<impl Responder, AppError> {
-    let user = auth_service.register(user_data.into_inner()).await?;
+    let user = auth_service.register(user_data.into_inner(), &SessionMeta::default()).await?;
     Ok(HttpResponse::Created().json(json!({
         "message": "User registered successfully",
         "user": user
@@ -21,10 +22,12 @@ pub async fn login(
     auth_service: web::Data<AuthService>,
     credentials: web::Json<LoginUser>,
 ) -> Result<impl Responder, AppError> {
-    let (user, token) = auth_service.login(credentials.into_inner()).await?;
+    let (user, tokens) = auth_service.login(credentials.into_inner(), SessionMeta::default()).await?;
     Ok(HttpResponse::Ok().json(json!({
         "message": "Login successful",
         "user": user,
-        "token": token
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in
     })))
 } 
\ No newline at end of file
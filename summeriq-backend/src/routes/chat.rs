@@ -1,9 +1,20 @@
 use actix_web::{web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use tracing::{info, error};
+use uuid::Uuid;
 
 use crate::error::AppError;
-use crate::services::AIService;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::api_token::ApiScope;
+use crate::models::organization::Permission;
+use crate::services::prompts::ChatPrompt;
+use crate::services::providers::ServedBy;
+use crate::models::usage::AiFeature;
+use crate::services::usage::UsageContext;
+use crate::services::rate_limit::user_caller_key;
+use crate::services::conversations::Exchange;
+use crate::services::{AIService, ConversationService, OrganizationService, StorageService};
 
 #[derive(Debug, Deserialize)]
 pub struct ChatRequest {
@@ -11,42 +22,82 @@ pub struct ChatRequest {
     pub project_name: Option<String>,
     pub selected_file_name: Option<String>,
     pub selected_file_path: Option<String>,
+    /// Conversation to continue; a new one is started when absent.
+    pub conversation_id: Option<Uuid>,
+    /// Return the model's reasoning alongside the answer.
+    #[serde(default)]
+    pub include_reasoning: bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ChatResponse {
+    pub conversation_id: Uuid,
     pub response: String,
+    pub served_by: ServedBy,
+    pub prompt_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
 }
 
 pub async fn chat(
+    user: AuthUser,
+    audit: Audit,
     ai_service: web::Data<AIService>,
+    storage_service: web::Data<StorageService>,
+    organizations: web::Data<OrganizationService>,
+    conversations: web::Data<ConversationService>,
     request: web::Json<ChatRequest>,
 ) -> Result<impl Responder, AppError> {
-    // Build context-aware prompt
-    let mut prompt = request.message.clone();
-    
-    if let (Some(project_name), Some(file_name), Some(file_path)) = 
-        (&request.project_name, &request.selected_file_name, &request.selected_file_path) {
-        prompt = format!(
-            "Context: You are an AI assistant helping with the '{}' project. The user is currently viewing the file '{}' located at '{}'.\n\nUser question: {}\n\nPlease provide a helpful, detailed response about this specific file or the project in general. Focus on explaining the code, architecture, best practices, and any relevant insights. Be direct and factual without any thinking process.",
-            project_name, file_name, file_path, request.message
-        );
-    } else if let Some(project_name) = &request.project_name {
-        prompt = format!(
-            "Context: You are an AI assistant helping with the '{}' project.\n\nUser question: {}\n\nPlease provide a helpful, detailed response about this project. If the user is asking about project structure, explain the typical structure of such projects and suggest they select a specific file for more detailed analysis. If they're asking about code, suggest they click on a file to get specific information. Be direct and factual without any thinking process.",
-            project_name, request.message
-        );
-    } else {
-        prompt = format!(
-            "User question: {}\n\nYou are an AI assistant for code analysis. Please provide a helpful response. If the user is asking about project structure, explain that you need more context and suggest they upload a project or select a specific file for detailed analysis. Be direct and factual without any thinking process.",
-            request.message
-        );
+    user.require(ApiScope::Docs)?;
+    if let Some(conversation_id) = request.conversation_id {
+        conversations.ensure_owner(user.id(), conversation_id).await?;
     }
+    // Chatting about an uploaded project needs the right to analyze it. A name that matches
+    // no upload is only context for the model and reveals nothing.
+    let storage_id = match request.project_name.as_deref() {
+        Some(project) => storage_service.get_file_id(project).await.ok(),
+        None => None,
+    };
+    let upload_id = match storage_id {
+        Some(storage_id) => organizations.find_stored(&storage_id).await?,
+        None => None,
+    };
+    if let Some(upload_id) = upload_id {
+        organizations.authorize_upload(user.id(), upload_id, Permission::Analyze).await?;
+    }
+    let prompt = ai_service.prompts().render(&ChatPrompt {
+        message: &request.message,
+        project_name: request.project_name.as_deref(),
+        selected_file_name: request.selected_file_name.as_deref(),
+        selected_file_path: request.selected_file_path.as_deref(),
+    })?;
 
-    let response = ai_service.analyze_text(&prompt)
-        .await
-        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    let response = ai_service
+        .for_caller(&user_caller_key(user.id()))
+        .with_context(UsageContext {
+            feature: AiFeature::Chat,
+            user_id: Some(user.id()),
+            upload_id,
+        })
+        .analyze_text(&prompt)
+        .await?;
 
-    info!("Chat response generated successfully");
-    Ok(HttpResponse::Ok().json(ChatResponse { response }))
+    info!("Chat response generated successfully by {} ({})", response.served_by.provider, response.served_by.model);
+    let conversation_id = conversations
+        .record(user.id(), request.conversation_id, upload_id, Exchange {
+            question: &request.message,
+            answer: &response.value,
+            served_by: &response.served_by,
+            prompt_version: &response.prompt_version,
+        })
+        .await?;
+    let details = serde_json::json!({ "upload_id": upload_id });
+    audit.record_with(user.id(), AuditAction::ChatMessage, AuditTarget::Conversation(conversation_id), details).await;
+    Ok(HttpResponse::Ok().json(ChatResponse {
+        conversation_id,
+        response: response.value,
+        served_by: response.served_by,
+        prompt_version: response.prompt_version,
+        reasoning: response.reasoning.filter(|_| request.include_reasoning),
+    }))
 } 
\ No newline at end of file
@@ -7,23 +7,43 @@ use uuid::Uuid;
 use urlencoding::decode;
 
 use crate::error::AppError;
-use crate::services::StorageService;
+use crate::middleware::{Audit, AuthUser};
+use crate::models::api_token::ApiScope;
+use crate::models::audit::{AuditAction, AuditTarget};
+use crate::models::organization::Permission;
+use crate::services::file_types::{self, FileMetadata};
+use crate::services::storage::is_contained;
+use crate::services::{OrganizationService, StorageService};
 pub use crate::handlers::upload::upload_file;
 
 pub async fn get_file(
+    user: AuthUser,
+    audit: Audit,
     storage_service: web::Data<StorageService>,
+    organizations: web::Data<OrganizationService>,
     file_id: web::Path<String>,
 ) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
+    if !is_contained(&file_id) || file_id.contains('/') {
+        return Err(AppError::BadRequest("Invalid file id".to_string()));
+    }
+    let storage_id = file_id.split('_').next().unwrap_or(&file_id);
+    let upload_id = organizations.authorize_stored(user.id(), storage_id, Permission::View).await?;
     let content = storage_service.read_file(&file_id).await?;
+    audit.record(user.id(), AuditAction::UploadViewed, AuditTarget::Upload(upload_id)).await;
     Ok(HttpResponse::Ok()
         .content_type("application/octet-stream")
         .body(content))
 }
 
 pub async fn get_file_content(
+    user: AuthUser,
+    audit: Audit,
     storage_service: web::Data<StorageService>,
+    organizations: web::Data<OrganizationService>,
     path: web::Path<String>,
 ) -> Result<impl Responder, AppError> {
+    user.require(ApiScope::Read)?;
     // Decode the URL-encoded path
     let decoded_path = match decode(&path) {
         Ok(path) => path,
@@ -47,18 +67,37 @@ pub async fn get_file_content(
     
     // Get the UUID from the storage service
     let uuid = storage_service.get_file_id(project).await?;
+    let upload_id = organizations.authorize_stored(user.id(), &uuid, Permission::View).await?;
     
-    // Construct the full path with storage directory and UUID
-    let full_path = if inner_path.is_empty() {
-        format!("extracted_{}/{}", uuid, project)
-    } else {
-        format!("extracted_{}/{}", uuid, inner_path)
-    };
+    // Paths are relative to the extracted project directory
+    let extracted_dir = format!("extracted_{}", uuid);
+    let file_path = if inner_path.is_empty() { project } else { inner_path.as_str() };
     
-    info!("Reading file from path: {}", full_path);
-    let content = storage_service.read_file(&full_path).await?;
+    info!("Reading file {} from {}", file_path, extracted_dir);
+    let content = storage_service.read_project_file(&extracted_dir, file_path).await?;
+    let details = json!({ "path": inner_path });
+    audit.record_with(user.id(), AuditAction::UploadViewed, AuditTarget::Upload(upload_id), details).await;
     
-    Ok(HttpResponse::Ok()
-        .content_type("text/plain")
-        .body(content))
+    Ok(project_file_response(file_path, content))
+}
+
+/// A file of an extracted project, typed by its contents and never run as a page.
+pub fn project_file_response(path: &str, content: Vec<u8>) -> HttpResponse {
+    let metadata = file_types::detect(path, &content, content.len() as u64);
+    HttpResponse::Ok()
+        .content_type(content_type(&metadata))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        // Uploaded HTML and SVG must not run scripts in the API's origin
+        .insert_header(("Content-Security-Policy", "default-src 'none'; img-src 'self'; style-src 'unsafe-inline'; sandbox"))
+        .body(content)
+}
+
+/// Images and other binary files are served as what they are, text as plain text in its
+/// own encoding so that markup is shown rather than rendered.
+fn content_type(metadata: &FileMetadata) -> String {
+    match &metadata.encoding {
+        _ if metadata.mime_type.starts_with("image/") => metadata.mime_type.clone(),
+        Some(encoding) => format!("text/plain; charset={}", encoding),
+        None => metadata.mime_type.clone(),
+    }
 } 
\ No newline at end of file
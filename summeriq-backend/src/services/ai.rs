@@ -1,183 +1,388 @@
-use reqwest::Client;
-use serde_json::{json, Value};
-use tracing::{info, error, warn};
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use once_cell::sync::Lazy;
-use tokio::time::{sleep, Duration};
-use regex::Regex;
+use tracing::warn;
 
 use crate::error::AppError;
+use crate::services::prompts::{PromptRegistry, RenderedPrompt};
+use crate::services::providers::{ChatRequest, ProviderChain, ProviderError, ProviderErrorKind, ProviderResponse, ServedBy};
+use crate::services::reasoning::split_reasoning;
+use crate::services::secrets::redact_secrets;
+use crate::services::tokens::{count_tokens, TokenBudget};
+use crate::services::usage::{UsageContext, UsageRecord, UsageService};
 
-// Add a global mutex for throttling Groq API requests
-static AI_THROTTLE: Lazy<Arc<Mutex<()>>> = Lazy::new(|| Arc::new(Mutex::new(())));
+// Total number of attempts `complete_json` makes before giving up on malformed output
+const MAX_JSON_ATTEMPTS: usize = 3;
+// Rate limiter queue used for requests not attributed to a user or client
+const ANONYMOUS_CALLER: &str = "anonymous";
+
+const TEXT_SYSTEM_PROMPT: &str = "You are a helpful AI assistant that analyzes code and provides clear, concise responses. You are friendly and conversational, especially when users greet you or ask general questions. Always acknowledge greetings warmly and offer to help with their project. When users ask specific questions about code, provide detailed, factual answers. Focus on explaining code structure, architecture, best practices, and implementation details. If a user asks a vague question, politely ask for more specific details about what they'd like to know. CRITICAL: NEVER include any thinking process, internal monologue, reasoning steps, or meta-commentary in your response. NEVER start with '<think>', '<reasoning>', or any similar markers. NEVER explain your analysis process. NEVER think aloud or explain what you're going to do. NEVER start sentences with 'Alright,' 'Okay,' 'So,' 'First,' 'I need to,' 'I should,' 'Let me,' 'I'll,' 'I remember,' 'I also need,' 'Maybe I'll,' etc. NEVER mention guidelines, thinking processes, or internal reasoning. NEVER explain how you're going to respond. Provide ONLY direct, factual answers without any thinking aloud, process explanation, or meta-commentary.";
+
+const JSON_SYSTEM_PROMPT: &str = "You are a precise code analysis assistant. You answer with a single valid JSON value that conforms to the JSON Schema given by the user. Never wrap the JSON in markdown fences and never add text before or after it.";
+
+/// Per-request knobs for a chat completion.
+struct ChatOptions {
+    system_prompt: &'static str,
+    json_mode: bool,
+    max_tokens: u32,
+}
+
+/// A value produced by the AI, together with the provider and model that produced it.
+#[derive(Debug, Clone)]
+pub struct AiResponse<T> {
+    pub value: T,
+    pub served_by: ServedBy,
+    /// The model's reasoning, kept apart from the answer.
+    pub reasoning: Option<String>,
+    /// Version of the prompt template the request was built from.
+    pub prompt_version: String,
+}
+
+/// Which providers, models and prompt versions went into an artifact built from several AI calls.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    #[serde(default)]
+    pub served_by: Vec<ServedBy>,
+    #[serde(default)]
+    pub prompt_versions: Vec<String>,
+}
+
+impl Provenance {
+    /// Records where `response` came from and returns its value.
+    pub fn record<T>(&mut self, response: AiResponse<T>) -> T {
+        if !self.served_by.contains(&response.served_by) {
+            self.served_by.push(response.served_by);
+        }
+        if !self.prompt_versions.contains(&response.prompt_version) {
+            self.prompt_versions.push(response.prompt_version);
+        }
+        response.value
+    }
+}
 
 #[derive(Clone)]
 pub struct AIService {
-    client: Client,
-    api_key: String,
-    post_request_delay_ms: u64,
+    providers: Arc<ProviderChain>,
+    prompts: Arc<PromptRegistry>,
+    token_budget: TokenBudget,
+    caller: Option<String>,
+    usage: Option<UsageService>,
+    context: UsageContext,
 }
 
 impl AIService {
-    pub fn new(api_key: String, post_request_delay_ms: u64) -> Self {
-        info!("Initializing AIService with API key: {}...", api_key.chars().take(4).collect::<String>());
+    pub fn new(providers: Arc<ProviderChain>, prompts: Arc<PromptRegistry>, token_budget: TokenBudget) -> Self {
+        Self {
+            providers,
+            prompts,
+            token_budget,
+            caller: None,
+            usage: None,
+            context: UsageContext::default(),
+        }
+    }
+
+    /// Records the token usage of every call and enforces daily budgets.
+    pub fn with_usage_tracking(mut self, usage: UsageService) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Returns a handle whose usage is attributed to the given feature, user and upload.
+    pub fn with_context(&self, context: UsageContext) -> Self {
         Self {
-            client: Client::new(),
-            api_key,
-            post_request_delay_ms,
+            context,
+            ..self.clone()
         }
     }
 
-    pub async fn analyze_text(&self, prompt: &str) -> Result<String, AppError> {
-        let _throttle = AI_THROTTLE.lock().await;
-        let client = reqwest::Client::new();
-        let mut retries = 0;
-        loop {
-            let response = client
-                .post("https://api.groq.com/openai/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-                .header("Content-Type", "application/json")
-            .json(&json!({
-                    "model": "deepseek-r1-distill-llama-70b",
-                "messages": [
-                        {"role": "system", "content": "You are a helpful AI assistant that analyzes code and provides clear, concise responses. You are friendly and conversational, especially when users greet you or ask general questions. Always acknowledge greetings warmly and offer to help with their project. When users ask specific questions about code, provide detailed, factual answers. Focus on explaining code structure, architecture, best practices, and implementation details. If a user asks a vague question, politely ask for more specific details about what they'd like to know. CRITICAL: NEVER include any thinking process, internal monologue, reasoning steps, or meta-commentary in your response. NEVER start with '<think>', '<reasoning>', or any similar markers. NEVER explain your analysis process. NEVER think aloud or explain what you're going to do. NEVER start sentences with 'Alright,' 'Okay,' 'So,' 'First,' 'I need to,' 'I should,' 'Let me,' 'I'll,' 'I remember,' 'I also need,' 'Maybe I'll,' etc. NEVER mention guidelines, thinking processes, or internal reasoning. NEVER explain how you're going to respond. Provide ONLY direct, factual answers without any thinking aloud, process explanation, or meta-commentary."},
-                        {"role": "user", "content": prompt}
-                    ],
-                    "temperature": 0.7,
-                    "max_tokens": 1000
-                }))
-                .send()
-                .await
-                .map_err(|e| {
-                    error!("Failed to send request to Groq API: {}", e);
-                    AppError::InternalServerError(format!("Failed to connect to AI service: {}", e))
-                })?;
-            info!("Received response with status: {}", response.status());
-            let status = response.status();
-            if !status.is_success() {
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                error!("API error response: {}", error_text);
-                if status.as_u16() == 429 {
-                    // Parse wait time from error message
-                    let re = Regex::new(r#"try again in ([\d.]+)ms|try again in ([\d.]+)s"#).unwrap();
-                    if let Some(caps) = re.captures(&error_text) {
-                        let wait_ms = if let Some(ms) = caps.get(1) {
-                            ms.as_str().parse::<f64>().unwrap_or(1.0)
-                        } else if let Some(s) = caps.get(2) {
-                            s.as_str().parse::<f64>().unwrap_or(1.0) * 1000.0
-                        } else {
-                            1000.0
-                        };
-                        let wait = std::time::Duration::from_millis(wait_ms as u64);
-                        warn!("Rate limited. Waiting {:?} before retrying... (attempt {}/{})", wait, retries+1, 5);
-                        tokio::time::sleep(wait).await;
-                        retries += 1;
-                        if retries < 5 {
-                            continue;
-                        }
-                    }
-                    return Err(AppError::BadRequest("AI service is rate limited. Please try again later.".to_string()));
+    /// Returns a handle whose requests are queued under `caller` (a user id or client
+    /// address), so the rate limiter can share capacity fairly between callers.
+    pub fn for_caller(&self, caller: &str) -> Self {
+        Self {
+            caller: Some(caller.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Prompt templates used to build requests.
+    pub fn prompts(&self) -> &PromptRegistry {
+        &self.prompts
+    }
+
+    /// Per-request token limits of the configured model.
+    pub fn token_budget(&self) -> TokenBudget {
+        self.token_budget
+    }
+
+    pub async fn analyze_text(&self, prompt: &RenderedPrompt) -> Result<AiResponse<String>, AppError> {
+        let options = ChatOptions {
+            system_prompt: TEXT_SYSTEM_PROMPT,
+            json_mode: false,
+            max_tokens: 1000,
+        };
+        self.check_budget().await?;
+        let (response, served_by) = self.chat_completion(&prompt.text, &prompt.version, &options).await?;
+        let output = split_reasoning(&response.content);
+        if output.answer.is_empty() {
+            return Err(AppError::InternalServerError(
+                "AI service stopped before giving an answer. Please try again.".to_string(),
+            ));
+        }
+        Ok(AiResponse {
+            value: output.answer,
+            served_by,
+            reasoning: response.reasoning.or(output.reasoning),
+            prompt_version: prompt.version.clone(),
+        })
+    }
+
+    /// Asks the model for a JSON answer and deserializes it into `T`.
+    ///
+    /// The JSON Schema of `T` is appended to the prompt and JSON mode is requested from
+    /// providers that support it. `T` should be an object type, since JSON mode only allows objects.
+    pub async fn complete_json<T>(&self, prompt: &RenderedPrompt) -> Result<AiResponse<T>, AppError>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        self.complete_json_with(prompt, |_: &T| Ok(())).await
+    }
+
+    /// Like [`AIService::complete_json`], with extra semantic checks on the parsed value.
+    ///
+    /// Output that cannot be repaired into a valid `T`, or that `validate` rejects, is sent
+    /// back to the model together with the error, up to `MAX_JSON_ATTEMPTS` attempts in total.
+    pub async fn complete_json_with<T, F>(&self, prompt: &RenderedPrompt, validate: F) -> Result<AiResponse<T>, AppError>
+    where
+        T: DeserializeOwned + JsonSchema,
+        F: Fn(&T) -> Result<(), String>,
+    {
+        let schema = serde_json::to_string(&schema_for!(T))
+            .map_err(|e| AppError::InternalServerError(format!("Failed to build JSON schema: {}", e)))?;
+        let base_prompt = format!(
+            "{}\n\nRespond with ONLY a JSON value that conforms to this JSON Schema:\n{}",
+            prompt.text, schema
+        );
+        let options = ChatOptions {
+            system_prompt: JSON_SYSTEM_PROMPT,
+            json_mode: true,
+            max_tokens: 2000,
+        };
+
+        self.check_budget().await?;
+        let mut request_prompt = base_prompt.clone();
+        let mut last_error = String::new();
+        for attempt in 1..=MAX_JSON_ATTEMPTS {
+            let result = match self.chat_completion(&request_prompt, &prompt.version, &options).await {
+                Ok((response, served_by)) => {
+                    let output = split_reasoning(&response.content);
+                    parse_json::<T>(&output.answer)
+                        .and_then(|value| validate(&value).map(|_| value))
+                        .map(|value| AiResponse {
+                            value,
+                            served_by,
+                            reasoning: response.reasoning.or(output.reasoning),
+                            prompt_version: prompt.version.clone(),
+                        })
                 }
-                if status.as_u16() == 413 {
-                    return Err(AppError::BadRequest("AI request too large. Please reduce the size of your request.".to_string()));
+                Err(e) if e.kind == ProviderErrorKind::InvalidJson => Err(e.message),
+                Err(e) => return Err(e.into()),
+            };
+            match result {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    warn!("Invalid JSON from AI service (attempt {}/{}): {}", attempt, MAX_JSON_ATTEMPTS, e);
+                    request_prompt = format!(
+                        "{}\n\nYour previous answer was rejected: {}. Answer again with ONLY the corrected JSON.",
+                        base_prompt, e
+                    );
+                    last_error = e;
                 }
-                let error_message = match status.as_u16() {
-                    503 => "AI service is temporarily unavailable. Please try again in a few moments.".to_string(),
-                    401 => "Invalid API key. Please check your configuration.".to_string(),
-                    _ => format!("AI service error: {} - {}", status, error_text)
-                };
-                return Err(AppError::InternalServerError(error_message));
             }
-            let response_body = response.json::<Value>().await.map_err(|e| {
-                error!("Failed to parse API response: {}", e);
-                AppError::InternalServerError("Failed to parse AI service response".to_string())
-            })?;
-            let content = response_body["choices"][0]["message"]["content"]
-                .as_str()
-                .ok_or_else(|| {
-                    error!("Invalid response format from AI service");
-                    AppError::InternalServerError("Invalid response from AI service".to_string())
-                })?;
-            
-            // Clean up the response to remove any thinking process markers
-            let cleaned_content = {
-                let mut lines: Vec<&str> = content.lines().collect();
-                
-                // Remove thinking process markers
-                lines.retain(|line| {
-                    let trimmed = line.trim();
-                    !trimmed.starts_with("<think>") &&
-                    !trimmed.starts_with("</think>") &&
-                    !trimmed.starts_with("<reasoning>") &&
-                    !trimmed.starts_with("</reasoning>") &&
-                    !trimmed.starts_with("<analysis>") &&
-                    !trimmed.starts_with("</analysis>")
-                });
-                
-                // Remove internal monologue patterns
-                lines.retain(|line| {
-                    let trimmed = line.trim();
-                    !trimmed.starts_with("Alright,") &&
-                    !trimmed.starts_with("Okay,") &&
-                    !trimmed.starts_with("So,") &&
-                    !trimmed.starts_with("First,") &&
-                    !trimmed.starts_with("I need to") &&
-                    !trimmed.starts_with("I should") &&
-                    !trimmed.starts_with("Let me") &&
-                    !trimmed.starts_with("I'll") &&
-                    !trimmed.starts_with("I think") &&
-                    !trimmed.starts_with("I should make sure") &&
-                    !trimmed.starts_with("It's important to") &&
-                    !trimmed.starts_with("Just a") &&
-                    !trimmed.starts_with("I remember") &&
-                    !trimmed.starts_with("I also need") &&
-                    !trimmed.starts_with("Maybe I'll") &&
-                    !trimmed.starts_with("I need to keep") &&
-                    !trimmed.starts_with("I should offer") &&
-                    !trimmed.starts_with("I also need to make") &&
-                    !trimmed.contains("I should respond") &&
-                    !trimmed.contains("I need to make sure") &&
-                    !trimmed.contains("I should acknowledge") &&
-                    !trimmed.contains("I should ask") &&
-                    !trimmed.contains("I'll keep it") &&
-                    !trimmed.contains("I should avoid") &&
-                    !trimmed.contains("Just a simple") &&
-                    !trimmed.contains("guidelines say") &&
-                    !trimmed.contains("thinking process") &&
-                    !trimmed.contains("internal monologue") &&
-                    !trimmed.contains("keep it straightforward") &&
-                    !trimmed.contains("concise and clear") &&
-                    !trimmed.contains("without any unnecessary") &&
-                    !trimmed.contains("give them an idea") &&
-                    !trimmed.contains("more tailored and helpful") &&
-                    !trimmed.is_empty()
-                });
-                
-                // Additional cleanup: remove any line that contains thinking patterns
-                lines.retain(|line| {
-                    let trimmed = line.trim().to_lowercase();
-                    !trimmed.contains("i remember") &&
-                    !trimmed.contains("guidelines say") &&
-                    !trimmed.contains("thinking process") &&
-                    !trimmed.contains("internal monologue") &&
-                    !trimmed.contains("keep it straightforward") &&
-                    !trimmed.contains("concise and clear") &&
-                    !trimmed.contains("without any unnecessary") &&
-                    !trimmed.contains("give them an idea") &&
-                    !trimmed.contains("more tailored and helpful") &&
-                    !trimmed.contains("i need to keep") &&
-                    !trimmed.contains("i should offer") &&
-                    !trimmed.contains("maybe i'll") &&
-                    !trimmed.contains("i also need to make")
-                });
-                
-                lines.join("\n").trim().to_string()
+        }
+
+        Err(AppError::InternalServerError(format!(
+            "AI service returned invalid JSON after {} attempts: {}",
+            MAX_JSON_ATTEMPTS, last_error
+        )))
+    }
+
+    fn caller(&self) -> &str {
+        self.caller.as_deref().unwrap_or(ANONYMOUS_CALLER)
+    }
+
+    async fn check_budget(&self) -> Result<(), AppError> {
+        match &self.usage {
+            Some(usage) => usage.check_budget(self.caller(), self.context.user_id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Sends one chat completion through the provider chain and records its token usage.
+    async fn chat_completion(
+        &self,
+        prompt: &str,
+        prompt_version: &str,
+        options: &ChatOptions,
+    ) -> Result<(ProviderResponse, ServedBy), ProviderError> {
+        let request = ChatRequest {
+            system_prompt: options.system_prompt.to_string(),
+            // Uploaded files can hold credentials, which must never reach a third-party provider
+            prompt: redact_secrets(prompt).into_owned(),
+            json_mode: options.json_mode,
+            max_tokens: options.max_tokens,
+            temperature: if options.json_mode { 0.2 } else { 0.7 },
+            caller: self.caller().to_string(),
+        };
+        let (response, served_by) = self.providers.complete(&request).await?;
+
+        if let Some(usage) = &self.usage {
+            // Providers that do not report usage are accounted with our own token estimate
+            let (prompt_tokens, completion_tokens) = match response.usage {
+                Some(reported) => (reported.prompt_tokens, reported.completion_tokens),
+                None => (
+                    (count_tokens(&request.system_prompt) + count_tokens(&request.prompt)) as u32,
+                    count_tokens(&response.content) as u32,
+                ),
             };
-            
-            // Add a configurable delay after every successful AI call
-            tokio::time::sleep(std::time::Duration::from_millis(self.post_request_delay_ms)).await;
-            return Ok(cleaned_content);
+            let record = UsageRecord {
+                caller: self.caller(),
+                context: self.context,
+                served_by: &served_by,
+                prompt_version: Some(prompt_version),
+                prompt_tokens,
+                completion_tokens,
+            };
+            if let Err(e) = usage.record(record).await {
+                warn!("Failed to record AI usage: {}", e);
+            }
+        }
+        Ok((response, served_by))
+    }
+}
+
+/// Parses the answer part of model output as `T`, repairing common formatting slips (markdown
+/// fences, surrounding prose, trailing commas) before giving up.
+fn parse_json<T: DeserializeOwned>(content: &str) -> Result<T, String> {
+    let content = content.trim();
+
+    let first_error = match serde_json::from_str::<T>(content) {
+        Ok(value) => return Ok(value),
+        Err(e) => e.to_string(),
+    };
+
+    let start = content.find(['{', '[']);
+    let end = content.rfind(['}', ']']);
+    let candidate = match (start, end) {
+        (Some(start), Some(end)) if end > start => &content[start..=end],
+        _ => return Err(format!("no JSON value found ({})", first_error)),
+    };
+    let repaired = strip_trailing_commas(candidate);
+
+    serde_json::from_str::<T>(&repaired).map_err(|e| e.to_string())
+}
+
+/// `text` without the commas directly before a closing `}` or `]`. Commas inside strings are
+/// kept.
+fn strip_trailing_commas(text: &str) -> String {
+    let mut repaired = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' && text[index + 1..].trim_start().starts_with(['}', ']']) {
+            continue;
         }
+        repaired.push(c);
     }
-} 
\ No newline at end of file
+    repaired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::providers::{BreakerSettings, MockProvider};
+    use std::time::Duration;
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Answer {
+        name: String,
+        tags: Vec<String>,
+    }
+
+    fn service(provider: MockProvider) -> (AIService, Arc<MockProvider>) {
+        let provider = Arc::new(provider);
+        let chain = ProviderChain::new(vec![provider.clone()], BreakerSettings {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(60),
+        });
+        let prompts = PromptRegistry::load(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")).unwrap();
+        (AIService::new(Arc::new(chain), Arc::new(prompts), TokenBudget::new(8_000)), provider)
+    }
+
+    fn prompt() -> RenderedPrompt {
+        RenderedPrompt { text: "Name a thing.".to_string(), version: "test@v1".to_string() }
+    }
+
+    #[test]
+    fn repairs_fences_prose_and_trailing_commas() {
+        let expected = Answer { name: "a".to_string(), tags: vec!["b".to_string()] };
+        let fenced = "Here you go:\n```json\n{\"name\": \"a\", \"tags\": [\"b\",],\n}\n```\nHope that helps.";
+        assert_eq!(parse_json::<Answer>(fenced).unwrap(), expected);
+        assert!(parse_json::<Answer>("no json here").is_err());
+    }
+
+    #[test]
+    fn keeps_commas_inside_strings() {
+        let answer: Answer = parse_json(r#"{"name": "a, }", "tags": ["x,]", "say \",]\"",],}"#).unwrap();
+        assert_eq!(answer.name, "a, }");
+        assert_eq!(answer.tags, ["x,]", "say \",]\""]);
+    }
+
+    #[tokio::test]
+    async fn invalid_answers_are_sent_back_with_the_error() {
+        let (ai, provider) = service(
+            MockProvider::new("mock")
+                .respond("I cannot answer in JSON")
+                .respond(r#"{"name": "", "tags": []}"#)
+                .respond(r#"{"name": "thing", "tags": []}"#),
+        );
+
+        let response = ai
+            .complete_json_with(&prompt(), |answer: &Answer| {
+                if answer.name.is_empty() {
+                    return Err("`name` must not be empty".to_string());
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response.value.name, "thing");
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(!requests[0].prompt.contains("previous answer was rejected"));
+        assert!(requests[1].prompt.contains("Your previous answer was rejected: no JSON value found"));
+        assert!(requests[2].prompt.contains("`name` must not be empty"));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (ai, provider) = service(MockProvider::new("mock").otherwise("still not JSON"));
+
+        let result = ai.complete_json::<Answer>(&prompt()).await;
+
+        assert!(matches!(result, Err(AppError::InternalServerError(message)) if message.contains("after 3 attempts")));
+        assert_eq!(provider.calls(), MAX_JSON_ATTEMPTS);
+    }
+}
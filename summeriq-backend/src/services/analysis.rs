@@ -7,11 +7,17 @@ use reqwest::Client;
 use serde_json::json;
 use std::fs;
 use crate::services::StorageService;
-use crate::services::ai::AIService;
-use dashmap::DashMap;
-use once_cell::sync::Lazy;
+use crate::services::ai::{AIService, AiResponse, Provenance};
+use crate::services::providers::ServedBy;
+use crate::services::file_types::{self, FileMetadata};
+use crate::services::prompts::{ChunkPart, FileInsightsPrompt, FilePurposeMergePrompt};
+use crate::services::tokens::{count_tokens, split_into_chunks, truncate_to_tokens};
+use crate::services::usage::UsageContext;
 use sha2::{Sha256, Digest};
-use regex;
+use schemars::JsonSchema;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileAnalysis {
@@ -20,150 +26,326 @@ pub struct FileAnalysis {
     pub dependencies: Vec<String>,
     pub analysis_time: String,
     pub contents: String,
+    /// Providers, models and prompt versions that produced the analysis.
+    #[serde(flatten, default)]
+    pub provenance: Provenance,
+    /// Size, type, encoding and language detected from the file itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_type: Option<FileMetadata>,
+    /// Why the file was not sent to the AI, for binary and generated files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
 }
 
+#[derive(Clone)]
 pub struct AnalysisService {
+    pool: PgPool,
     client: Client,
     api_key: String,
     storage_service: StorageService,
     ai_service: AIService,
+    /// Upload whose files are analyzed; analyses are only stored and reused within one.
+    upload_id: Option<Uuid>,
 }
 
-// Global cache: key = hash(file_path + content), value = FileAnalysis
-static FILE_ANALYSIS_CACHE: Lazy<DashMap<String, FileAnalysis>> = Lazy::new(DashMap::new);
-
-// Helper function to clean up AI responses by removing thinking process markers
-fn clean_ai_response(response: &str) -> String {
-    let mut cleaned = response.to_string();
-    
-    // Remove <think>...</think> blocks
-    let think_pattern = regex::Regex::new(r#"<think>.*?</think>"#).unwrap();
-    cleaned = think_pattern.replace_all(&cleaned, "").to_string();
-    
-    // Remove <reasoning>...</reasoning> blocks
-    let reasoning_pattern = regex::Regex::new(r#"<reasoning>.*?</reasoning>"#).unwrap();
-    cleaned = reasoning_pattern.replace_all(&cleaned, "").to_string();
-    
-    // Remove <analysis>...</analysis> blocks
-    let analysis_pattern = regex::Regex::new(r#"<analysis>.*?</analysis>"#).unwrap();
-    cleaned = analysis_pattern.replace_all(&cleaned, "").to_string();
-    
-    // Remove any remaining thinking markers at the start
-    let thinking_start_pattern = regex::Regex::new(r#"^<[^>]*>.*?</[^>]*>\s*"#).unwrap();
-    cleaned = thinking_start_pattern.replace_all(&cleaned, "").to_string();
-    
-    // Clean up extra whitespace and newlines
-    cleaned = cleaned.trim().to_string();
-    
-    // Remove any leading/trailing whitespace and normalize newlines
-    cleaned = cleaned.lines()
-        .map(|line| line.trim())
-        .filter(|line| !line.is_empty())
-        .collect::<Vec<_>>()
-        .join("\n");
-    
-    cleaned
+/// Structured answer requested from the AI for a single file.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct FileInsights {
+    /// What the file does in the context of the project, in a few sentences.
+    purpose: String,
+    /// Every import, require, use or include in the file.
+    dependencies: Vec<DependencyInsight>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct DependencyInsight {
+    /// Module, crate or package name as written in the file.
+    name: String,
+    /// Short note on what it is used for, when not obvious from the name.
+    purpose: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PurposeSummary {
+    /// What the whole file does in the context of the project, in a few sentences.
+    purpose: String,
 }
 
 impl AnalysisService {
-    pub fn new(api_key: String, storage_service: StorageService, ai_service: AIService) -> Self {
+    pub fn new(pool: PgPool, api_key: String, storage_service: StorageService, ai_service: AIService) -> Self {
         info!("Initializing AnalysisService");
         Self {
+            pool,
             client: Client::new(),
             api_key,
             storage_service,
             ai_service,
+            upload_id: None,
+        }
+    }
+
+    /// Returns a handle whose AI requests are queued under `caller` by the rate limiter and
+    /// whose usage is attributed to `context`. Analyses are stored for the context's upload.
+    pub fn for_caller(&self, caller: &str, context: UsageContext) -> Self {
+        Self {
+            upload_id: context.upload_id,
+            ai_service: self.ai_service.for_caller(caller).with_context(context),
+            ..self.clone()
         }
     }
 
+    /// Analyzes a file as stored: text in any supported encoding is decoded first, while
+    /// binary and generated files (minified bundles, lockfiles, generated code) are described
+    /// without asking the AI.
+    pub async fn analyze_content(&self, file_path: &str, content: &[u8]) -> Result<FileAnalysis, AppError> {
+        let metadata = file_types::detect(file_path, content, content.len() as u64);
+        let skipped = if metadata.binary {
+            Some(format!("binary file ({})", metadata.mime_type))
+        } else if metadata.generated {
+            Some("generated or minified file".to_string())
+        } else {
+            None
+        };
+        let text = metadata.encoding.as_deref().and_then(|encoding| file_types::decode(content, encoding));
+        let mut analysis = match (skipped, text) {
+            (None, Some(text)) => self.analyze_file(file_path, &text).await?,
+            (skipped, _) => {
+                let reason = skipped.unwrap_or_else(|| "text could not be decoded".to_string());
+                info!("Not analyzing {}: {}", file_path, reason);
+                FileAnalysis {
+                    language: extension_language(file_path),
+                    file_purpose: String::new(),
+                    dependencies: Vec::new(),
+                    analysis_time: Utc::now().to_rfc3339(),
+                    contents: String::new(),
+                    provenance: Provenance::default(),
+                    file_type: None,
+                    skipped: Some(reason),
+                }
+            }
+        };
+        analysis.file_type = Some(metadata);
+        Ok(analysis)
+    }
+
     pub async fn analyze_file(&self, file_path: &str, content: &str) -> Result<FileAnalysis, AppError> {
-        // Compute hash of file_path + content for cache key
+        // Compute hash of prompt versions + file_path + content for cache key, so editing a
+        // prompt does not serve analyses made with the old one
+        let prompts = self.ai_service.prompts();
         let mut hasher = Sha256::new();
+        hasher.update(prompts.version::<FileInsightsPrompt>().as_bytes());
+        hasher.update(prompts.version::<FilePurposeMergePrompt>().as_bytes());
         hasher.update(file_path.as_bytes());
         hasher.update(content.as_bytes());
         let hash = format!("{:x}", hasher.finalize());
-        // Check cache
-        if let Some(cached) = FILE_ANALYSIS_CACHE.get(&hash) {
+        if let Some(stored) = self.find_stored(file_path, &hash, content).await? {
             info!("Cache hit for file analysis: {}", file_path);
-            return Ok(cached.clone());
+            return Ok(stored);
         }
         info!("Cache miss for file analysis: {}", file_path);
         info!("Starting file analysis for: {}", file_path);
         info!("Content length: {} bytes", content.len());
         
-        // Generate file purpose analysis
-        let purpose_prompt = format!(
-            "Analyze this code file and provide a brief explanation of its main purpose and contents. \
-             Focus on explaining what the file does in the context of the project. \
-             Keep the explanation concise but informative. \
-             CRITICAL: Provide ONLY a direct, factual answer. Do NOT include any thinking process, internal monologue, reasoning steps, or meta-commentary. \
-             Do NOT start with '<think>', '<reasoning>', or any similar markers. \
-             Do NOT explain your analysis process. \
-             Simply state what the file is and what it does. \
-             Example format: 'This is a configuration file that...' or 'This file implements...'\n\
-             \nFile content:\n{}",
-            content
-        );
-
-        info!("Sending purpose analysis request");
-        let file_purpose = self.ai_service
-            .analyze_text(&purpose_prompt)
-            .await?;
-        info!("Received purpose analysis: {}", file_purpose);
-
-        // Generate dependencies analysis
-        let deps_prompt = format!(
-            "Analyze this code file and list all its dependencies (imports, requires, etc.). \
-             If there are no dependencies, just say 'No dependencies found.' \
-             Format each dependency on a new line, starting with a dash (-). \
-             For each dependency, include its purpose if it's not obvious from the name. \
-             CRITICAL: Provide ONLY a direct, factual answer. Do NOT include any thinking process, internal monologue, reasoning steps, or meta-commentary. \
-             Do NOT start with '<think>', '<reasoning>', or any similar markers. \
-             Do NOT explain your analysis process. \
-             Simply list the dependencies or state 'No dependencies found.' \
-             Example format:\n\
-             - react: Frontend UI library\n\
-             - express: Web server framework\n\
-             No dependencies found\n\
-             \nFile content:\n{}",
-            content
-        );
-
-        info!("Sending dependencies analysis request");
-        let dependencies_text = self.ai_service
-            .analyze_text(&deps_prompt)
-            .await?;
-        info!("Received dependencies analysis: {}", dependencies_text);
-
-        // Clean the dependencies response and parse into a vector
-        let cleaned_deps_text = clean_ai_response(&dependencies_text);
-        let dependencies: Vec<String> = cleaned_deps_text
-            .lines()
-            .filter(|line| line.trim().starts_with('-'))
-            .map(|line| line.trim_start_matches('-').trim().to_string())
+        let budget = self.ai_service.token_budget();
+        let mut provenance = Provenance::default();
+        let insights = if budget.fits(content) {
+            provenance.record(self.request_insights(file_path, content, None).await?)
+        } else {
+            let chunks = split_into_chunks(content, budget.content_tokens());
+            info!("File {} exceeds the token budget, analyzing it in {} chunks", file_path, chunks.len());
+            let mut partials = Vec::with_capacity(chunks.len());
+            for (index, chunk) in chunks.iter().enumerate() {
+                let part = ChunkPart {
+                    index: index + 1,
+                    total: chunks.len(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                };
+                partials.push(provenance.record(self.request_insights(file_path, &chunk.text, Some(part)).await?));
+            }
+            self.merge_insights(file_path, partials, &mut provenance).await?
+        };
+        info!("Received file analysis: {:?}", insights);
+
+        let dependencies: Vec<String> = insights
+            .dependencies
+            .into_iter()
+            .map(|dep| match dep.purpose {
+                Some(purpose) if !purpose.trim().is_empty() => format!("{}: {}", dep.name, purpose.trim()),
+                _ => dep.name,
+            })
             .collect();
 
         info!("Parsed {} dependencies from the file", dependencies.len());
 
         // Detect language from file extension
-        let language = Path::new(file_path)
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("unknown")
-            .to_string();
+        let language = extension_language(file_path);
 
         info!("Detected language: {}", language);
 
         let analysis = FileAnalysis {
             language,
-            file_purpose: clean_ai_response(&file_purpose),
+            file_purpose: insights.purpose.trim().to_string(),
             dependencies,
             analysis_time: Utc::now().to_rfc3339(),
             contents: content.to_string(),
+            provenance,
+            file_type: None,
+            skipped: None,
         };
-        // Store in cache
-        FILE_ANALYSIS_CACHE.insert(hash, analysis.clone());
+        self.store(file_path, &hash, &analysis).await?;
         info!("Analysis complete: {:?}", analysis);
         Ok(analysis)
     }
-} 
\ No newline at end of file
+
+    /// The analysis stored for the same prompt versions, path and content of this upload.
+    async fn find_stored(&self, file_path: &str, hash: &str, content: &str) -> Result<Option<FileAnalysis>, AppError> {
+        let Some(upload_id) = self.upload_id else {
+            return Ok(None);
+        };
+        let row = sqlx::query!(
+            r#"
+            SELECT language, file_purpose, dependencies as "dependencies: Json<Vec<String>>",
+                   served_by as "served_by: Json<Vec<ServedBy>>", prompt_versions as "prompt_versions: Json<Vec<String>>",
+                   created_at
+            FROM file_analyses
+            WHERE upload_id = $1 AND file_path = $2 AND content_hash = $3
+            "#,
+            upload_id,
+            file_path,
+            hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| FileAnalysis {
+            language: row.language,
+            file_purpose: row.file_purpose,
+            dependencies: row.dependencies.0,
+            analysis_time: row.created_at.to_rfc3339(),
+            contents: content.to_string(),
+            provenance: Provenance {
+                served_by: row.served_by.0,
+                prompt_versions: row.prompt_versions.0,
+            },
+            file_type: None,
+            skipped: None,
+        }))
+    }
+
+    async fn store(&self, file_path: &str, hash: &str, analysis: &FileAnalysis) -> Result<(), AppError> {
+        let Some(upload_id) = self.upload_id else {
+            return Ok(());
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO file_analyses (upload_id, file_path, content_hash, language, file_purpose, dependencies, served_by, prompt_versions)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (upload_id, file_path, content_hash) DO NOTHING
+            "#,
+            upload_id,
+            file_path,
+            hash,
+            analysis.language,
+            analysis.file_purpose,
+            json!(analysis.dependencies),
+            json!(analysis.provenance.served_by),
+            json!(analysis.provenance.prompt_versions)
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn request_insights(&self, file_path: &str, content: &str, part: Option<ChunkPart>) -> Result<AiResponse<FileInsights>, AppError> {
+        let prompt = self.ai_service.prompts().render(&FileInsightsPrompt {
+            file_path,
+            content,
+            part,
+        })?;
+
+        info!("Sending file analysis request");
+        self.ai_service
+            .complete_json_with(&prompt, |insights: &FileInsights| {
+                if insights.purpose.trim().is_empty() {
+                    return Err("`purpose` must not be empty".to_string());
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    /// Combines the analyses of a file's chunks: dependencies are de-duplicated by name and
+    /// the per-chunk purposes are summarized into one, in several rounds if they do not fit
+    /// into a single request.
+    async fn merge_insights(
+        &self,
+        file_path: &str,
+        partials: Vec<FileInsights>,
+        provenance: &mut Provenance,
+    ) -> Result<FileInsights, AppError> {
+        let mut dependencies: Vec<DependencyInsight> = Vec::new();
+        let mut purposes = Vec::with_capacity(partials.len());
+        for partial in partials {
+            purposes.push(partial.purpose);
+            for dep in partial.dependencies {
+                match dependencies.iter_mut().find(|d| d.name == dep.name) {
+                    Some(existing) => {
+                        if existing.purpose.is_none() {
+                            existing.purpose = dep.purpose;
+                        }
+                    }
+                    None => dependencies.push(dep),
+                }
+            }
+        }
+
+        // Purposes are cut to half the budget so that every batch merges at least two of
+        // them, and each round shrinks the list
+        let budget = self.ai_service.token_budget();
+        let max_purpose_tokens = budget.content_tokens() / 2;
+        while purposes.len() > 1 {
+            let mut batches: Vec<Vec<String>> = vec![Vec::new()];
+            let mut batch_tokens = 0;
+            for purpose in purposes {
+                let purpose = truncate_to_tokens(&purpose, max_purpose_tokens);
+                let tokens = count_tokens(&purpose);
+                let current = batches.last_mut().unwrap();
+                if current.len() >= 2 && batch_tokens + tokens > budget.content_tokens() {
+                    batches.push(Vec::new());
+                    batch_tokens = 0;
+                }
+                batch_tokens += tokens;
+                batches.last_mut().unwrap().push(purpose);
+            }
+
+            let mut merged = Vec::with_capacity(batches.len());
+            for batch in batches {
+                if batch.len() == 1 {
+                    merged.extend(batch);
+                    continue;
+                }
+                let prompt = self.ai_service.prompts().render(&FilePurposeMergePrompt {
+                    file_path,
+                    purposes: &batch,
+                })?;
+                let summary = self.ai_service.complete_json::<PurposeSummary>(&prompt).await?;
+                merged.push(provenance.record(summary).purpose);
+            }
+            purposes = merged;
+        }
+
+        Ok(FileInsights {
+            purpose: purposes.pop().unwrap_or_default(),
+            dependencies,
+        })
+    }
+}
+
+fn extension_language(file_path: &str) -> String {
+    Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
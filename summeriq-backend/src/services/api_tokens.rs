@@ -0,0 +1,140 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::api_token::{ApiScope, ApiToken, CreateApiToken, CreatedApiToken};
+use crate::services::auth::{hash_token, new_secret_token};
+
+/// Marks a bearer token as a personal API token rather than a session JWT.
+pub const API_TOKEN_PREFIX: &str = "siq_";
+// Characters of the token kept in clear, enough to recognise it in a listing
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// Long-lived, scoped tokens users create for scripts and CI.
+#[derive(Clone)]
+pub struct ApiTokenService {
+    pool: PgPool,
+}
+
+struct ApiTokenRow {
+    id: Uuid,
+    name: String,
+    token_prefix: String,
+    scopes: Vec<String>,
+    created_at: chrono::DateTime<Utc>,
+    expires_at: Option<chrono::DateTime<Utc>>,
+    last_used_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl From<ApiTokenRow> for ApiToken {
+    fn from(row: ApiTokenRow) -> Self {
+        ApiToken {
+            id: row.id,
+            name: row.name,
+            token_prefix: row.token_prefix,
+            scopes: parse_scopes(&row.scopes),
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+        }
+    }
+}
+
+fn parse_scopes(scopes: &[String]) -> Vec<ApiScope> {
+    scopes.iter().filter_map(|scope| ApiScope::parse(scope)).collect()
+}
+
+impl ApiTokenService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, user_id: Uuid, request: CreateApiToken) -> Result<CreatedApiToken, AppError> {
+        let token = format!("{}{}", API_TOKEN_PREFIX, new_secret_token());
+        let scopes: Vec<String> = ApiScope::ALL
+            .iter()
+            .filter(|scope| request.scopes.contains(scope))
+            .map(|scope| scope.as_str().to_string())
+            .collect();
+
+        let row = sqlx::query_as!(
+            ApiTokenRow,
+            r#"
+            INSERT INTO api_tokens (user_id, name, token_prefix, token_hash, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, name, token_prefix, scopes, created_at, expires_at, last_used_at
+            "#,
+            user_id,
+            request.name,
+            &token[..DISPLAY_PREFIX_LEN],
+            hash_token(&token),
+            &scopes,
+            request.expires_in_days.map(|days| Utc::now() + Duration::days(days))
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        info!("User {} created API token {} with scopes {:?}", user_id, row.id, scopes);
+        Ok(CreatedApiToken { token, api_token: row.into() })
+    }
+
+    /// The user's tokens that have not been revoked, newest first.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<ApiToken>, AppError> {
+        let rows = sqlx::query_as!(
+            ApiTokenRow,
+            r#"
+            SELECT id, name, token_prefix, scopes, created_at, expires_at, last_used_at
+            FROM api_tokens
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(ApiToken::from).collect())
+    }
+
+    pub async fn revoke(&self, user_id: Uuid, token_id: Uuid) -> Result<(), AppError> {
+        let result = sqlx::query!(
+            "UPDATE api_tokens SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            token_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("API token not found".to_string()));
+        }
+        info!("User {} revoked API token {}", user_id, token_id);
+        Ok(())
+    }
+
+    /// Resolves an active token to its user, id and scopes, and records that it was used.
+    pub async fn verify(&self, token: &str) -> Result<(Uuid, Uuid, Vec<ApiScope>), AppError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id, user_id, scopes, expires_at, last_used_at
+            FROM api_tokens
+            WHERE token_hash = $1 AND revoked_at IS NULL
+            "#,
+            hash_token(token)
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::AuthenticationError("Invalid API token".to_string()))?;
+        if row.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(AppError::AuthenticationError("API token has expired".to_string()));
+        }
+
+        // A busy CI job should not turn every request into a write
+        if row.last_used_at.is_none_or(|used| Utc::now() - used >= Duration::minutes(1)) {
+            sqlx::query!("UPDATE api_tokens SET last_used_at = NOW() WHERE id = $1", row.id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok((row.user_id, row.id, parse_scopes(&row.scopes)))
+    }
+}
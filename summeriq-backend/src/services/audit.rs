@@ -0,0 +1,122 @@
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream};
+use serde_json::Value;
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::audit::{AuditAction, AuditEvent, AuditPage, AuditQuery, AuditTarget};
+use crate::models::session::SessionMeta;
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+/// An event to append to the audit log.
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    /// `None` for anonymous requests.
+    pub actor_id: Option<Uuid>,
+    pub action: AuditAction,
+    pub target: Option<AuditTarget>,
+    /// Client the request came from.
+    pub client: SessionMeta,
+    pub details: Value,
+}
+
+/// Append-only log of security-relevant events and data access.
+#[derive(Clone)]
+pub struct AuditService {
+    pool: PgPool,
+}
+
+impl AuditService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Appends `event`. A failure is logged rather than returned, so the audit log being
+    /// unavailable does not fail the request it describes.
+    pub async fn record(&self, event: NewAuditEvent) {
+        let details = if event.details.is_null() { Value::Object(Default::default()) } else { event.details };
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO audit_events (actor_id, action, target_type, target_id, ip_address, user_agent, details)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            event.actor_id,
+            event.action.as_str(),
+            event.target.map(|target| target.kind()),
+            event.target.map(|target| target.id().to_string()),
+            event.client.ip_address,
+            event.client.user_agent,
+            details
+        )
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            error!("Failed to record audit event {}: {}", event.action.as_str(), e);
+        }
+    }
+
+    /// One page of the events matching `query`, newest first.
+    pub async fn query(&self, query: &AuditQuery) -> Result<AuditPage, AppError> {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let events = sqlx::query_as!(
+            AuditEvent,
+            r#"
+            SELECT id, occurred_at, actor_id, action, target_type, target_id, ip_address, user_agent, details
+            FROM audit_events
+            WHERE ($1::uuid IS NULL OR actor_id = $1)
+              AND ($2::text IS NULL OR action = $2 OR (right($2, 1) = '.' AND starts_with(action, $2)))
+              AND ($3::text IS NULL OR target_type = $3)
+              AND ($4::text IS NULL OR target_id = $4)
+              AND ($5::timestamptz IS NULL OR occurred_at >= $5)
+              AND ($6::timestamptz IS NULL OR occurred_at < $6)
+              AND ($7::bigint IS NULL OR id < $7)
+            ORDER BY id DESC
+            LIMIT $8
+            "#,
+            query.actor_id,
+            query.action,
+            query.target_type,
+            query.target_id,
+            query.since,
+            query.until,
+            query.cursor,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let next_cursor = match events.last() {
+            Some(last) if events.len() as i64 == limit => Some(last.id),
+            _ => None,
+        };
+        Ok(AuditPage { events, next_cursor })
+    }
+
+    /// Every event matching `query` as newline-delimited JSON, read a page at a time so
+    /// exports of any size stream in constant memory.
+    pub fn export(&self, query: AuditQuery) -> impl Stream<Item = Result<Bytes, AppError>> {
+        let service = self.clone();
+        let first = AuditQuery { limit: Some(MAX_PAGE_SIZE), ..query };
+        stream::try_unfold(Some(first), move |query| {
+            let service = service.clone();
+            async move {
+                let Some(mut query) = query else {
+                    return Ok(None);
+                };
+                let page = service.query(&query).await?;
+                let mut lines = Vec::new();
+                for event in &page.events {
+                    serde_json::to_writer(&mut lines, event)
+                        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+                    lines.push(b'\n');
+                }
+                query.cursor = page.next_cursor;
+                Ok(Some((Bytes::from(lines), page.next_cursor.map(|_| query))))
+            }
+        })
+    }
+}
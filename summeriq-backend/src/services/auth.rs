@@ -1,34 +1,154 @@
-use actix_web::web;
 use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
-use tracing::{info, error};
+use std::future::Future;
+use std::sync::Arc;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::config::Config;
 use crate::error::AppError;
+use crate::models::api_token::ApiScope;
+use crate::models::session::{Session, SessionMeta, TokenPair};
 use crate::models::user::{CreateUser, LoginUser, User};
+use crate::services::api_tokens::{ApiTokenService, API_TOKEN_PREFIX};
+use crate::services::auth_limiter::AuthLimiter;
+use crate::services::mailer::{Email, Mailer};
+use crate::services::oidc::{OidcClient, OidcIdentity};
+
+// How long a single sign-on login at the identity provider may take
+const OIDC_LOGIN_TTL_MINUTES: i64 = 10;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
     sub: String,
+    /// Session the token was issued for; the token stops working once it is revoked.
+    sid: String,
     exp: usize,
 }
 
+/// How a request proved who it acts for.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// Access token of a signed-in session, allowed everything the user may do.
+    Session(Uuid),
+    /// Personal API token, limited to its scopes.
+    ApiToken { id: Uuid, scopes: Vec<ApiScope> },
+}
+
+/// User a bearer token acts for, and the credential it is.
+#[derive(Debug, Clone)]
+pub struct VerifiedToken {
+    pub user_id: Uuid,
+    pub credential: Credential,
+}
+
+/// Why a session was ended, stored in `sessions.revoked_reason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevokeReason {
+    Logout,
+    LogoutAll,
+    RefreshTokenReuse,
+    PasswordReset,
+    IdentityClaimed,
+}
+
+impl RevokeReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevokeReason::Logout => "logout",
+            RevokeReason::LogoutAll => "logout_all",
+            RevokeReason::RefreshTokenReuse => "refresh_token_reuse",
+            RevokeReason::PasswordReset => "password_reset",
+            RevokeReason::IdentityClaimed => "identity_claimed",
+        }
+    }
+}
+
+/// What a token sent by email lets its holder do, stored in `email_tokens.purpose`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    PasswordReset,
+}
+
+impl EmailTokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTokenPurpose::VerifyEmail => "verify_email",
+            EmailTokenPurpose::PasswordReset => "password_reset",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthSettings {
+    pub jwt_secret: String,
+    pub access_token_ttl: Duration,
+    pub refresh_token_ttl: Duration,
+    pub email_verification_ttl: Duration,
+    pub password_reset_ttl: Duration,
+    pub require_email_verification: bool,
+    /// Base URL of the web app the emailed links point to.
+    pub public_url: String,
+}
+
+impl AuthSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            jwt_secret: config.jwt_secret.clone(),
+            access_token_ttl: Duration::minutes(config.access_token_ttl_minutes),
+            refresh_token_ttl: Duration::days(config.refresh_token_ttl_days),
+            email_verification_ttl: Duration::hours(config.email_verification_ttl_hours),
+            password_reset_ttl: Duration::minutes(config.password_reset_ttl_minutes),
+            require_email_verification: config.require_email_verification,
+            public_url: config.public_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct AuthService {
     pub pool: PgPool,
-    jwt_secret: String,
+    settings: AuthSettings,
+    mailer: Arc<dyn Mailer>,
+    api_tokens: ApiTokenService,
+    limiter: AuthLimiter,
+    oidc: Option<Arc<OidcClient>>,
 }
 
 impl AuthService {
-    pub fn new(pool: PgPool, jwt_secret: String) -> Self {
+    pub fn new(pool: PgPool, settings: AuthSettings, mailer: Arc<dyn Mailer>, limiter: AuthLimiter) -> Self {
         Self {
+            api_tokens: ApiTokenService::new(pool.clone()),
             pool,
-            jwt_secret,
+            settings,
+            mailer,
+            limiter,
+            oidc: None,
         }
     }
 
-    pub async fn register(&self, user_data: CreateUser) -> Result<User, AppError> {
+    /// Lets users sign in through the OpenID Connect provider of `oidc`.
+    pub fn with_oidc(mut self, oidc: OidcClient) -> Self {
+        self.oidc = Some(Arc::new(oidc));
+        self
+    }
+
+    pub fn api_tokens(&self) -> &ApiTokenService {
+        &self.api_tokens
+    }
+
+    /// Whether users must confirm their email address before they can sign in.
+    pub fn requires_email_verification(&self) -> bool {
+        self.settings.require_email_verification
+    }
+
+    pub async fn register(&self, user_data: CreateUser, meta: &SessionMeta) -> Result<User, AppError> {
+        self.limiter.check_register(meta.client_address()).await?;
         let password_hash = hash(user_data.password.as_bytes(), DEFAULT_COST)
             .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
 
@@ -37,7 +157,7 @@ impl AuthService {
             r#"
             INSERT INTO users (email, password_hash, full_name)
             VALUES ($1, $2, $3)
-            RETURNING id, email, password_hash, full_name, created_at, updated_at
+            RETURNING id, email, password_hash, full_name, email_verified_at, created_at, updated_at
             "#,
             user_data.email,
             password_hash,
@@ -47,14 +167,208 @@ impl AuthService {
         .await?;
 
         info!("User registered successfully: {}", user.email);
+        // The account exists either way; a lost email can be requested again
+        if let Err(e) = self.send_verification_email(&user).await {
+            error!("Failed to send verification email to {}: {}", user.email, e);
+        }
+        Ok(user)
+    }
+
+    /// Signs a user in. Attempts are rate limited per client address and account, and
+    /// repeated failures lock the account for increasingly long periods.
+    pub async fn login(&self, credentials: LoginUser, meta: SessionMeta) -> Result<(User, TokenPair), AppError> {
+        self.limiter.check_login(meta.client_address(), &credentials.email).await?;
+        let user = match self.check_credentials(&credentials).await {
+            Ok(user) => user,
+            Err(e @ AppError::AuthenticationError(_)) => {
+                self.limiter.login_failed(&credentials.email).await?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        self.limiter.login_succeeded(&credentials.email).await?;
+        if self.settings.require_email_verification && user.email_verified_at.is_none() {
+            return Err(AppError::Forbidden("Email address has not been verified".to_string()));
+        }
+
+        let tokens = self.start_session(&user, meta).await?;
+        info!("User logged in successfully: {}", user.email);
+        Ok((user, tokens))
+    }
+
+    /// Starts a single sign-on login, returning the identity provider's login page to send
+    /// the user to. The provider sends them back to the configured redirect URL.
+    pub async fn oidc_login_url(&self) -> Result<String, AppError> {
+        let request = self.oidc()?.authorization_request().await?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM oidc_login_states WHERE expires_at <= NOW()")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO oidc_login_states (state_hash, nonce, code_verifier, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            hash_token(&request.state),
+            request.nonce,
+            request.code_verifier,
+            Utc::now() + Duration::minutes(OIDC_LOGIN_TTL_MINUTES)
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(request.url)
+    }
+
+    /// Completes a single sign-on login with the code and state the identity provider sent
+    /// the user back with, and signs in the user the identity is linked to. Each state
+    /// works once.
+    pub async fn oidc_login(&self, code: &str, state: &str, meta: SessionMeta) -> Result<(User, TokenPair), AppError> {
+        let oidc = self.oidc()?;
+        let login = sqlx::query!(
+            r#"
+            DELETE FROM oidc_login_states
+            WHERE state_hash = $1 AND expires_at > NOW()
+            RETURNING nonce, code_verifier
+            "#,
+            hash_token(state)
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired login state".to_string()))?;
+
+        let identity = oidc.authenticate(code, &login.code_verifier, &login.nonce).await?;
+        let user = self.link_identity(oidc.issuer(), identity).await?;
+        let tokens = self.start_session(&user, meta).await?;
+        info!("User signed in through single sign-on: {}", user.email);
+        Ok((user, tokens))
+    }
+
+    /// The user an identity is linked to. A new identity is linked to the account with the
+    /// email address the provider verified, which is created when there is none. An account
+    /// that never verified the address may have been registered by someone else to take it
+    /// over, so its password, sessions and API tokens stop working once the identity claims it.
+    async fn link_identity(&self, issuer: &str, identity: OidcIdentity) -> Result<User, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let linked = sqlx::query_scalar!(
+            r#"
+            UPDATE user_identities SET last_login_at = NOW()
+            WHERE issuer = $1 AND subject = $2
+            RETURNING user_id
+            "#,
+            issuer,
+            identity.subject
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let user_id = match linked {
+            Some(user_id) => user_id,
+            None => {
+                // An unverified address could be anyone's, and would hand over their account
+                let email = identity.email.filter(|_| identity.email_verified).ok_or_else(|| {
+                    AppError::Forbidden("The identity provider has not verified an email address for this account".to_string())
+                })?;
+                let existing = sqlx::query!(
+                    "SELECT id, email_verified_at FROM users WHERE lower(email) = lower($1)",
+                    email
+                )
+                .fetch_optional(&mut *tx)
+                .await?;
+                // Nobody knows this password; a password reset sets one the user can use
+                let password_hash = hash(new_secret_token().as_bytes(), DEFAULT_COST)
+                    .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+                let user_id = match existing {
+                    Some(user) if user.email_verified_at.is_some() => user.id,
+                    Some(user) => {
+                        sqlx::query!(
+                            "UPDATE users SET password_hash = $2, updated_at = NOW() WHERE id = $1",
+                            user.id,
+                            password_hash
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        sqlx::query!(
+                            "UPDATE sessions SET revoked_at = NOW(), revoked_reason = $2 WHERE user_id = $1 AND revoked_at IS NULL",
+                            user.id,
+                            RevokeReason::IdentityClaimed.as_str()
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        sqlx::query!(
+                            "UPDATE api_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+                            user.id
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        warn!("Unverified account {} was claimed by a {} identity; its credentials were reset", user.id, issuer);
+                        user.id
+                    }
+                    None => {
+                        let full_name = identity
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+                        sqlx::query_scalar!(
+                            r#"
+                            INSERT INTO users (email, password_hash, full_name, email_verified_at)
+                            VALUES ($1, $2, $3, NOW())
+                            RETURNING id
+                            "#,
+                            email,
+                            password_hash,
+                            full_name
+                        )
+                        .fetch_one(&mut *tx)
+                        .await?
+                    }
+                };
+                sqlx::query!(
+                    "INSERT INTO user_identities (issuer, subject, user_id, email) VALUES ($1, $2, $3, $4)",
+                    issuer,
+                    identity.subject,
+                    user_id,
+                    email
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1",
+                    user_id
+                )
+                .execute(&mut *tx)
+                .await?;
+                info!("Linked {} identity {} to user {}", issuer, identity.subject, user_id);
+                user_id
+            }
+        };
+
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT id, email, password_hash, full_name, email_verified_at, created_at, updated_at
+            FROM users
+            WHERE id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
         Ok(user)
     }
 
-    pub async fn login(&self, credentials: LoginUser) -> Result<(User, String), AppError> {
+    fn oidc(&self) -> Result<&OidcClient, AppError> {
+        self.oidc
+            .as_deref()
+            .ok_or_else(|| AppError::NotFound("Single sign-on is not configured".to_string()))
+    }
+
+    async fn check_credentials(&self, credentials: &LoginUser) -> Result<User, AppError> {
         let user = sqlx::query_as!(
             User,
             r#"
-            SELECT id, email, password_hash, full_name, created_at, updated_at
+            SELECT id, email, password_hash, full_name, email_verified_at, created_at, updated_at
             FROM users
             WHERE email = $1
             "#,
@@ -64,45 +378,446 @@ impl AuthService {
         .await?
         .ok_or_else(|| AppError::AuthenticationError("Invalid credentials".to_string()))?;
 
-        if !verify(credentials.password, &user.password_hash)
+        if !verify(&credentials.password, &user.password_hash)
             .map_err(|e| AppError::AuthenticationError(e.to_string()))?
         {
             return Err(AppError::AuthenticationError("Invalid credentials".to_string()));
         }
+        Ok(user)
+    }
 
-        let token = self.generate_token(&user)?;
-        info!("User logged in successfully: {}", user.email);
-        Ok((user, token))
+    /// Opens a new session for `user` and issues its first token pair.
+    pub async fn start_session(&self, user: &User, meta: SessionMeta) -> Result<TokenPair, AppError> {
+        let refresh_token = new_secret_token();
+        let mut tx = self.pool.begin().await?;
+        let session_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO sessions (user_id, user_agent, ip_address, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            user.id,
+            meta.user_agent,
+            meta.ip_address,
+            Utc::now() + self.settings.refresh_token_ttl
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO refresh_tokens (session_id, token_hash) VALUES ($1, $2)",
+            session_id,
+            hash_token(&refresh_token)
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.token_pair(user.id, session_id, refresh_token)
+    }
+
+    /// Exchanges a refresh token for a new token pair. Each refresh token works once;
+    /// presenting one that was already exchanged means it leaked, so the whole session is
+    /// revoked.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let token = sqlx::query!(
+            r#"
+            SELECT rt.id, rt.used_at, s.id AS session_id, s.user_id, s.expires_at, s.revoked_at
+            FROM refresh_tokens rt
+            JOIN sessions s ON s.id = rt.session_id
+            WHERE rt.token_hash = $1
+            FOR UPDATE OF rt, s
+            "#,
+            hash_token(refresh_token)
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::AuthenticationError("Invalid refresh token".to_string()))?;
+
+        if token.revoked_at.is_some() {
+            return Err(AppError::AuthenticationError("Session has been revoked".to_string()));
+        }
+        if token.used_at.is_some() {
+            revoke_session(&mut tx, token.session_id, RevokeReason::RefreshTokenReuse).await?;
+            tx.commit().await?;
+            warn!(
+                "Refresh token reuse detected for user {}; revoked session {}",
+                token.user_id, token.session_id
+            );
+            return Err(AppError::AuthenticationError(
+                "Refresh token was already used; the session has been revoked".to_string(),
+            ));
+        }
+        if token.expires_at <= Utc::now() {
+            return Err(AppError::AuthenticationError("Session has expired".to_string()));
+        }
+
+        let next_token = new_secret_token();
+        sqlx::query!("UPDATE refresh_tokens SET used_at = NOW() WHERE id = $1", token.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO refresh_tokens (session_id, token_hash) VALUES ($1, $2)",
+            token.session_id,
+            hash_token(&next_token)
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE sessions SET last_used_at = NOW(), expires_at = $2 WHERE id = $1",
+            token.session_id,
+            Utc::now() + self.settings.refresh_token_ttl
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.token_pair(token.user_id, token.session_id, next_token)
+    }
+
+    /// Ends one session; its access and refresh tokens stop working immediately.
+    pub async fn logout(&self, user_id: Uuid, session_id: Uuid) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            UPDATE sessions SET revoked_at = NOW(), revoked_reason = $3
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+            session_id,
+            user_id,
+            RevokeReason::Logout.as_str()
+        )
+        .execute(&self.pool)
+        .await?;
+        info!("User {} logged out of session {}", user_id, session_id);
+        Ok(())
+    }
+
+    /// Ends every session of `user_id`, returning how many were still active.
+    pub async fn logout_all(&self, user_id: Uuid) -> Result<u64, AppError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions SET revoked_at = NOW(), revoked_reason = $2
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+            user_id,
+            RevokeReason::LogoutAll.as_str()
+        )
+        .execute(&self.pool)
+        .await?;
+        info!("User {} logged out of {} sessions", user_id, result.rows_affected());
+        Ok(result.rows_affected())
+    }
+
+    /// Active sessions of `user_id`, most recently used first.
+    pub async fn list_sessions(&self, user_id: Uuid) -> Result<Vec<Session>, AppError> {
+        let sessions = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT id, user_id, user_agent, ip_address, created_at, last_used_at, expires_at
+            FROM sessions
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY last_used_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    /// Emails `user` a link confirming their address. Earlier links stop working.
+    pub async fn send_verification_email(&self, user: &User) -> Result<(), AppError> {
+        let token = self
+            .issue_email_token(user.id, EmailTokenPurpose::VerifyEmail, self.settings.email_verification_ttl)
+            .await?;
+        self.mailer
+            .send(&Email {
+                to: user.email.clone(),
+                subject: "Confirm your SummerIQ email address".to_string(),
+                body: format!(
+                    "Hi {},\n\nConfirm your email address by opening this link:\n\n{}/verify-email?token={}\n\n\
+                     The link expires in {} hours. If you did not create a SummerIQ account, ignore this email.",
+                    user.full_name,
+                    self.settings.public_url,
+                    token,
+                    self.settings.email_verification_ttl.num_hours()
+                ),
+            })
+            .await
+    }
+
+    /// Sends a new verification link to `email` if it belongs to an unverified account.
+    /// Requests are throttled per client address and email address. The account is looked up
+    /// and mailed in the background, so every request is answered alike and callers cannot
+    /// probe which addresses are registered.
+    pub async fn resend_verification_email(&self, email: &str, meta: &SessionMeta) -> Result<(), AppError> {
+        self.limiter.check_email_request(meta.client_address(), email).await?;
+        let email = email.to_string();
+        self.detach("verification email", |service| async move {
+            match service.find_by_email(&email).await? {
+                Some(user) if user.email_verified_at.is_none() => service.send_verification_email(&user).await,
+                _ => Ok(()),
+            }
+        });
+        Ok(())
+    }
+
+    /// Marks the email address of the token's user as verified, returning the user.
+    pub async fn verify_email(&self, token: &str) -> Result<Uuid, AppError> {
+        let user_id = self.consume_email_token(token, EmailTokenPurpose::VerifyEmail).await?;
+        sqlx::query!(
+            "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+        info!("User {} verified their email address", user_id);
+        Ok(user_id)
+    }
+
+    /// Emails a password reset link to `email` if it belongs to an account. Throttled and
+    /// answered like [`AuthService::resend_verification_email`].
+    pub async fn request_password_reset(&self, email: &str, meta: &SessionMeta) -> Result<(), AppError> {
+        self.limiter.check_email_request(meta.client_address(), email).await?;
+        let email = email.to_string();
+        self.detach("password reset email", |service| async move { service.send_password_reset(&email).await });
+        Ok(())
+    }
+
+    async fn send_password_reset(&self, email: &str) -> Result<(), AppError> {
+        let Some(user) = self.find_by_email(email).await? else {
+            info!("Password reset requested for unknown email");
+            return Ok(());
+        };
+        let token = self
+            .issue_email_token(user.id, EmailTokenPurpose::PasswordReset, self.settings.password_reset_ttl)
+            .await?;
+        self.mailer
+            .send(&Email {
+                to: user.email.clone(),
+                subject: "Reset your SummerIQ password".to_string(),
+                body: format!(
+                    "Hi {},\n\nChoose a new password by opening this link:\n\n{}/reset-password?token={}\n\n\
+                     The link expires in {} minutes. If you did not ask for a password reset, ignore this email.",
+                    user.full_name,
+                    self.settings.public_url,
+                    token,
+                    self.settings.password_reset_ttl.num_minutes()
+                ),
+            })
+            .await
+    }
+
+    /// Sets a new password and signs the user out everywhere, returning the user. Following
+    /// the emailed link also proves the address, so it is marked verified.
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> Result<Uuid, AppError> {
+        let password_hash = hash(new_password.as_bytes(), DEFAULT_COST)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        let user_id = self.consume_email_token(token, EmailTokenPurpose::PasswordReset).await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $2, email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW()
+            WHERE id = $1
+            "#,
+            user_id,
+            password_hash
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW(), revoked_reason = $2 WHERE user_id = $1 AND revoked_at IS NULL",
+            user_id,
+            RevokeReason::PasswordReset.as_str()
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("User {} reset their password", user_id);
+        Ok(user_id)
+    }
+
+    /// Runs `task` on its own after the request is answered, logging how it failed.
+    fn detach<F, Fut>(&self, what: &'static str, task: F)
+    where
+        F: FnOnce(AuthService) -> Fut,
+        Fut: Future<Output = Result<(), AppError>> + Send + 'static,
+    {
+        let task = task(self.clone());
+        tokio::spawn(async move {
+            if let Err(e) = task.await {
+                error!("Failed to send {}: {}", what, e);
+            }
+        });
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT id, email, password_hash, full_name, email_verified_at, created_at, updated_at
+            FROM users
+            WHERE email = $1
+            "#,
+            email
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// Stores a new token for `purpose`, invalidating the user's outstanding ones.
+    async fn issue_email_token(
+        &self,
+        user_id: Uuid,
+        purpose: EmailTokenPurpose,
+        ttl: Duration,
+    ) -> Result<String, AppError> {
+        let token = new_secret_token();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "UPDATE email_tokens SET used_at = NOW() WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL",
+            user_id,
+            purpose.as_str()
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO email_tokens (user_id, purpose, token_hash, expires_at) VALUES ($1, $2, $3, $4)",
+            user_id,
+            purpose.as_str(),
+            hash_token(&token),
+            Utc::now() + ttl
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(token)
+    }
+
+    /// Marks an unused, unexpired token as used and returns its user.
+    async fn consume_email_token(&self, token: &str, purpose: EmailTokenPurpose) -> Result<Uuid, AppError> {
+        sqlx::query_scalar!(
+            r#"
+            UPDATE email_tokens SET used_at = NOW()
+            WHERE token_hash = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            "#,
+            hash_token(token),
+            purpose.as_str()
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired token".to_string()))
     }
 
-    pub fn generate_token(&self, user: &User) -> Result<String, AppError> {
-        let expiration = chrono::Utc::now()
-            .checked_add_signed(chrono::Duration::hours(24))
-            .expect("valid timestamp")
-            .timestamp() as usize;
+    fn token_pair(&self, user_id: Uuid, session_id: Uuid, refresh_token: String) -> Result<TokenPair, AppError> {
+        Ok(TokenPair {
+            token: self.generate_token(user_id, session_id)?,
+            refresh_token,
+            expires_in: self.settings.access_token_ttl.num_seconds(),
+        })
+    }
 
+    fn generate_token(&self, user_id: Uuid, session_id: Uuid) -> Result<String, AppError> {
         let claims = Claims {
-            sub: user.id.to_string(),
-            exp: expiration,
+            sub: user_id.to_string(),
+            sid: session_id.to_string(),
+            exp: (Utc::now() + self.settings.access_token_ttl).timestamp() as usize,
         };
 
         encode(
             &Header::default(),
             &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
+            &EncodingKey::from_secret(self.settings.jwt_secret.as_bytes()),
         )
         .map_err(|e| AppError::AuthenticationError(e.to_string()))
     }
 
-    pub fn verify_token(&self, token: &str) -> Result<Uuid, AppError> {
+    /// Checks a bearer token: personal API tokens against their stored hash, session access
+    /// tokens by signature and expiry and that their session is still active.
+    pub async fn verify_token(&self, token: &str) -> Result<VerifiedToken, AppError> {
+        if token.starts_with(API_TOKEN_PREFIX) {
+            let (user_id, id, scopes) = self.api_tokens.verify(token).await?;
+            return Ok(VerifiedToken { user_id, credential: Credential::ApiToken { id, scopes } });
+        }
+
         let token_data = decode::<Claims>(
             token,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
+            &DecodingKey::from_secret(self.settings.jwt_secret.as_bytes()),
             &Validation::default(),
         )
         .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
 
-        Uuid::parse_str(&token_data.claims.sub)
-            .map_err(|e| AppError::AuthenticationError(e.to_string()))
+        let user_id = Uuid::parse_str(&token_data.claims.sub)
+            .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
+        let session_id = Uuid::parse_str(&token_data.claims.sid)
+            .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
+
+        let session = sqlx::query!(
+            "SELECT user_id, expires_at, revoked_at FROM sessions WHERE id = $1",
+            session_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .filter(|session| session.user_id == user_id)
+        .ok_or_else(|| AppError::AuthenticationError("Unknown session".to_string()))?;
+        if session.revoked_at.is_some() {
+            return Err(AppError::AuthenticationError("Session has been revoked".to_string()));
+        }
+        if session.expires_at <= Utc::now() {
+            return Err(AppError::AuthenticationError("Session has expired".to_string()));
+        }
+
+        Ok(VerifiedToken { user_id, credential: Credential::Session(session_id) })
+    }
+
+    pub async fn is_admin(&self, user_id: Uuid) -> Result<bool, AppError> {
+        let is_admin = sqlx::query_scalar!("SELECT is_admin FROM users WHERE id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(is_admin.unwrap_or(false))
     }
-} 
\ No newline at end of file
+}
+
+async fn revoke_session(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    session_id: Uuid,
+    reason: RevokeReason,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW(), revoked_reason = $2 WHERE id = $1 AND revoked_at IS NULL",
+        session_id,
+        reason.as_str()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// 244 random bits from two v4 UUIDs, hex encoded. Used for refresh and emailed tokens.
+pub(crate) fn new_secret_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Tokens are stored hashed, so a database leak does not hand out live sessions or links.
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_tokens_are_unique_and_hashed() {
+        let (a, b) = (new_secret_token(), new_secret_token());
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, b);
+        assert_eq!(hash_token(&a), hash_token(&a));
+        assert_ne!(hash_token(&a), a);
+        assert_eq!(hash_token(&a).len(), 64);
+    }
+}
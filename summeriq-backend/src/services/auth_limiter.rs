@@ -0,0 +1,492 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use sqlx::PgPool;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+// How long failed logins count towards a lockout
+const FAILURE_MEMORY_HOURS: i64 = 24;
+// Longest email kept in a limiter key
+const MAX_ACCOUNT_KEY_LEN: usize = 254;
+// How often a store drops the expired events of every key
+const SWEEP_INTERVAL_SECS: i64 = 300;
+
+/// Timestamps of attempts, grouped by key, for sliding-window limits.
+#[async_trait]
+pub trait LimiterStore: Send + Sync {
+    /// Adds an event for `key` at `at`.
+    async fn record(&self, key: &str, at: DateTime<Utc>) -> Result<(), AppError>;
+    /// Events of `key` after `since`, oldest first.
+    async fn events_since(&self, key: &str, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, AppError>;
+    /// Forgets every event of `key`.
+    async fn clear(&self, key: &str) -> Result<(), AppError>;
+    /// Forgets the events of every key that happened before `before`.
+    async fn sweep(&self, before: DateTime<Utc>) -> Result<(), AppError>;
+}
+
+/// When a store is next due to sweep, so that keys nobody uses again do not pile up.
+struct SweepSchedule {
+    next: AtomicI64,
+}
+
+impl SweepSchedule {
+    fn new() -> Self {
+        Self { next: AtomicI64::new(0) }
+    }
+
+    /// Whether a sweep is due at `at`; only one of several concurrent callers is told so.
+    fn claim(&self, at: DateTime<Utc>) -> bool {
+        let next = self.next.load(Ordering::Relaxed);
+        at.timestamp() >= next
+            && self
+                .next
+                .compare_exchange(next, at.timestamp() + SWEEP_INTERVAL_SECS, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
+}
+
+/// Keeps events in process memory; limits are per server instance and reset on restart.
+pub struct MemoryLimiterStore {
+    events: DashMap<String, VecDeque<DateTime<Utc>>>,
+    retention: Duration,
+    sweeps: SweepSchedule,
+}
+
+impl MemoryLimiterStore {
+    /// Events older than `retention` are dropped as new ones arrive, and keys left without
+    /// events every few minutes.
+    pub fn new(retention: Duration) -> Self {
+        Self { events: DashMap::new(), retention, sweeps: SweepSchedule::new() }
+    }
+}
+
+#[async_trait]
+impl LimiterStore for MemoryLimiterStore {
+    async fn record(&self, key: &str, at: DateTime<Utc>) -> Result<(), AppError> {
+        if self.sweeps.claim(at) {
+            self.sweep(at - self.retention).await?;
+        }
+        let mut events = self.events.entry(key.to_string()).or_default();
+        while events.front().is_some_and(|oldest| *oldest < at - self.retention) {
+            events.pop_front();
+        }
+        events.push_back(at);
+        Ok(())
+    }
+
+    async fn events_since(&self, key: &str, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, AppError> {
+        Ok(self
+            .events
+            .get(key)
+            .map(|events| events.iter().filter(|at| **at > since).copied().collect())
+            .unwrap_or_default())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        self.events.remove(key);
+        Ok(())
+    }
+
+    async fn sweep(&self, before: DateTime<Utc>) -> Result<(), AppError> {
+        self.events.retain(|_, events| {
+            while events.front().is_some_and(|oldest| *oldest < before) {
+                events.pop_front();
+            }
+            !events.is_empty()
+        });
+        Ok(())
+    }
+}
+
+/// Keeps events in the `auth_rate_events` table, so limits hold across server instances.
+pub struct PgLimiterStore {
+    pool: PgPool,
+    retention: Duration,
+    sweeps: SweepSchedule,
+}
+
+impl PgLimiterStore {
+    /// Events older than `retention` are deleted every few minutes by whichever instance
+    /// records an event then.
+    pub fn new(pool: PgPool, retention: Duration) -> Self {
+        Self { pool, retention, sweeps: SweepSchedule::new() }
+    }
+}
+
+#[async_trait]
+impl LimiterStore for PgLimiterStore {
+    async fn record(&self, key: &str, at: DateTime<Utc>) -> Result<(), AppError> {
+        if self.sweeps.claim(at) {
+            self.sweep(at - self.retention).await?;
+        }
+        sqlx::query!("INSERT INTO auth_rate_events (key, occurred_at) VALUES ($1, $2)", key, at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn events_since(&self, key: &str, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, AppError> {
+        let events = sqlx::query_scalar!(
+            "SELECT occurred_at FROM auth_rate_events WHERE key = $1 AND occurred_at > $2 ORDER BY occurred_at",
+            key,
+            since
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(events)
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query!("DELETE FROM auth_rate_events WHERE key = $1", key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn sweep(&self, before: DateTime<Utc>) -> Result<(), AppError> {
+        let result = sqlx::query!("DELETE FROM auth_rate_events WHERE occurred_at < $1", before)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() > 0 {
+            info!("Swept {} expired rate limit events", result.rows_affected());
+        }
+        Ok(())
+    }
+}
+
+/// At most `limit` attempts in any `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowLimit {
+    pub limit: u32,
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AuthLimitSettings {
+    pub login_per_ip: WindowLimit,
+    pub login_per_account: WindowLimit,
+    pub register_per_ip: WindowLimit,
+    pub email_per_ip: WindowLimit,
+    pub email_per_account: WindowLimit,
+    /// Consecutive failed logins after which an account is locked.
+    pub lockout_threshold: u32,
+    /// First lockout; each further failure doubles it, up to `lockout_max`.
+    pub lockout_base: Duration,
+    pub lockout_max: Duration,
+}
+
+impl AuthLimitSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            login_per_ip: WindowLimit {
+                limit: config.login_attempts_per_ip,
+                window: Duration::seconds(config.login_window_secs as i64),
+            },
+            login_per_account: WindowLimit {
+                limit: config.login_attempts_per_account,
+                window: Duration::seconds(config.login_window_secs as i64),
+            },
+            register_per_ip: WindowLimit {
+                limit: config.registrations_per_ip,
+                window: Duration::seconds(config.registration_window_secs as i64),
+            },
+            email_per_ip: WindowLimit {
+                limit: config.email_requests_per_ip,
+                window: Duration::seconds(config.email_request_window_secs as i64),
+            },
+            email_per_account: WindowLimit {
+                limit: config.email_requests_per_account,
+                window: Duration::seconds(config.email_request_window_secs as i64),
+            },
+            lockout_threshold: config.lockout_threshold,
+            lockout_base: Duration::seconds(config.lockout_base_secs as i64),
+            lockout_max: Duration::seconds(config.lockout_max_secs as i64),
+        }
+    }
+
+    /// How long the stores must remember events for these limits.
+    pub fn retention(&self) -> Duration {
+        [
+            self.login_per_ip.window,
+            self.login_per_account.window,
+            self.register_per_ip.window,
+            self.email_per_ip.window,
+            self.email_per_account.window,
+        ]
+        .into_iter()
+            .fold(Duration::hours(FAILURE_MEMORY_HOURS), Duration::max)
+    }
+
+    /// Lockout after `failures` consecutive failed logins, if any.
+    fn lockout_for(&self, failures: u32) -> Option<Duration> {
+        let extra = failures.checked_sub(self.lockout_threshold)?;
+        let factor = 2i32.checked_pow(extra.min(30)).unwrap_or(i32::MAX);
+        Some((self.lockout_base * factor).min(self.lockout_max))
+    }
+}
+
+/// Throttles logins, registrations and emailed links per client address and per account, and
+/// locks an account for exponentially growing periods after repeated failed logins.
+#[derive(Clone)]
+pub struct AuthLimiter {
+    store: Arc<dyn LimiterStore>,
+    settings: AuthLimitSettings,
+}
+
+impl AuthLimiter {
+    pub fn new(store: Arc<dyn LimiterStore>, settings: AuthLimitSettings) -> Self {
+        Self { store, settings }
+    }
+
+    /// Admits a login attempt from `ip` for `email`, or explains how long to wait.
+    pub async fn check_login(&self, ip: &str, email: &str) -> Result<(), AppError> {
+        self.check_login_at(ip, email, Utc::now()).await
+    }
+
+    /// Counts a failed login towards the account's lockout.
+    pub async fn login_failed(&self, email: &str) -> Result<(), AppError> {
+        self.store.record(&failures_key(email), Utc::now()).await
+    }
+
+    /// Resets the account's failure count.
+    pub async fn login_succeeded(&self, email: &str) -> Result<(), AppError> {
+        self.store.clear(&failures_key(email)).await
+    }
+
+    /// Admits a registration from `ip`, or explains how long to wait.
+    pub async fn check_register(&self, ip: &str) -> Result<(), AppError> {
+        self.check_register_at(ip, Utc::now()).await
+    }
+
+    /// Admits a request from `ip` to email a link to `email`, or explains how long to wait.
+    /// Every request counts, whether or not the address belongs to an account.
+    pub async fn check_email_request(&self, ip: &str, email: &str) -> Result<(), AppError> {
+        self.check_email_request_at(ip, email, Utc::now()).await
+    }
+
+    /// Admits a share link password from `ip`, or explains how long to wait. Viewers send the
+    /// password with every request, so only wrong ones count, with the login limits.
+    pub async fn check_share_password(&self, ip: &str, link_id: Uuid) -> Result<(), AppError> {
+        self.check_share_password_at(ip, link_id, Utc::now()).await
+    }
+
+    /// Counts a wrong share link password against `ip` and the link.
+    pub async fn share_password_failed(&self, ip: &str, link_id: Uuid) -> Result<(), AppError> {
+        let now = Utc::now();
+        self.store.record(&format!("share:ip:{}", ip), now).await?;
+        self.store.record(&format!("share:link:{}", link_id), now).await
+    }
+
+    async fn check_login_at(&self, ip: &str, email: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+        let failures = self
+            .store
+            .events_since(&failures_key(email), now - Duration::hours(FAILURE_MEMORY_HOURS))
+            .await?;
+        if let (Some(lockout), Some(last_failure)) = (self.settings.lockout_for(failures.len() as u32), failures.last()) {
+            let locked_until = *last_failure + lockout;
+            if locked_until > now {
+                warn!("Login for a locked account refused after {} failed attempts", failures.len());
+                return Err(rate_limited(
+                    "Too many failed login attempts; the account is temporarily locked",
+                    locked_until - now,
+                ));
+            }
+        }
+
+        let ip_key = format!("login:ip:{}", ip);
+        let account_key = format!("login:account:{}", normalize_email(email));
+        self.check_window(&ip_key, self.settings.login_per_ip, now, "Too many login attempts from this address")
+            .await?;
+        self.check_window(&account_key, self.settings.login_per_account, now, "Too many login attempts for this account")
+            .await?;
+        self.store.record(&ip_key, now).await?;
+        self.store.record(&account_key, now).await
+    }
+
+    async fn check_register_at(&self, ip: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+        let key = format!("register:ip:{}", ip);
+        self.check_window(&key, self.settings.register_per_ip, now, "Too many registrations from this address")
+            .await?;
+        self.store.record(&key, now).await
+    }
+
+    async fn check_email_request_at(&self, ip: &str, email: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+        let ip_key = format!("email:ip:{}", ip);
+        let account_key = format!("email:account:{}", normalize_email(email));
+        self.check_window(&ip_key, self.settings.email_per_ip, now, "Too many email requests from this address")
+            .await?;
+        self.check_window(&account_key, self.settings.email_per_account, now, "Too many emails requested for this account")
+            .await?;
+        self.store.record(&ip_key, now).await?;
+        self.store.record(&account_key, now).await
+    }
+
+    async fn check_share_password_at(&self, ip: &str, link_id: Uuid, now: DateTime<Utc>) -> Result<(), AppError> {
+        let message = "Too many wrong share link passwords";
+        self.check_window(&format!("share:ip:{}", ip), self.settings.login_per_ip, now, message)
+            .await?;
+        self.check_window(&format!("share:link:{}", link_id), self.settings.login_per_account, now, message)
+            .await
+    }
+
+    /// Fails when `key` already used up `limit` within the window ending `now`; the caller
+    /// may retry once the oldest of those attempts leaves the window.
+    async fn check_window(&self, key: &str, limit: WindowLimit, now: DateTime<Utc>, message: &str) -> Result<(), AppError> {
+        let events = self.store.events_since(key, now - limit.window).await?;
+        if events.len() < limit.limit as usize {
+            return Ok(());
+        }
+        let oldest_counted = events[events.len() - limit.limit as usize];
+        Err(rate_limited(message, oldest_counted + limit.window - now))
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase().chars().take(MAX_ACCOUNT_KEY_LEN).collect()
+}
+
+fn failures_key(email: &str) -> String {
+    format!("login:failures:{}", normalize_email(email))
+}
+
+fn rate_limited(message: &str, wait: Duration) -> AppError {
+    AppError::RateLimited {
+        message: message.to_string(),
+        // Round up, so retrying right on time succeeds
+        retry_after_secs: (wait.num_milliseconds().max(0) as u64).div_ceil(1000).max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> AuthLimiter {
+        let settings = AuthLimitSettings {
+            login_per_ip: WindowLimit { limit: 5, window: Duration::minutes(10) },
+            login_per_account: WindowLimit { limit: 3, window: Duration::minutes(10) },
+            register_per_ip: WindowLimit { limit: 2, window: Duration::hours(1) },
+            email_per_ip: WindowLimit { limit: 4, window: Duration::hours(1) },
+            email_per_account: WindowLimit { limit: 2, window: Duration::hours(1) },
+            lockout_threshold: 2,
+            lockout_base: Duration::seconds(30),
+            lockout_max: Duration::minutes(2),
+        };
+        AuthLimiter::new(Arc::new(MemoryLimiterStore::new(settings.retention())), settings)
+    }
+
+    fn retry_after(result: Result<(), AppError>) -> u64 {
+        match result {
+            Err(AppError::RateLimited { retry_after_secs, .. }) => retry_after_secs,
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn windows_slide_per_address_and_account() {
+        let limiter = limiter();
+        let start = Utc::now();
+        let at = |secs: i64| start + Duration::seconds(secs);
+
+        for (i, email) in ["a@example.com", "b@example.com", "c@example.com"].iter().enumerate() {
+            limiter.check_login_at("10.0.0.1", email, at(i as i64 * 60)).await.unwrap();
+        }
+        limiter.check_login_at("10.0.0.1", "A@Example.com", at(180)).await.unwrap();
+        limiter.check_login_at("10.0.0.1", "a@example.com", at(240)).await.unwrap();
+        // The address used its 5 attempts; the first one leaves the window after 10 minutes
+        assert_eq!(retry_after(limiter.check_login_at("10.0.0.1", "d@example.com", at(300)).await), 300);
+        limiter.check_login_at("10.0.0.1", "d@example.com", at(600)).await.unwrap();
+
+        // Account a@example.com used its 3 attempts from the first address, so other addresses wait too
+        assert_eq!(retry_after(limiter.check_login_at("10.0.0.2", "a@example.com", at(590)).await), 10);
+
+        limiter.check_register_at("10.0.0.1", at(0)).await.unwrap();
+        limiter.check_register_at("10.0.0.1", at(1)).await.unwrap();
+        assert_eq!(retry_after(limiter.check_register_at("10.0.0.1", at(2)).await), 3598);
+        limiter.check_register_at("10.0.0.2", at(2)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lockout_doubles_with_each_failure_and_resets_on_success() {
+        let limiter = limiter();
+        let now = Utc::now();
+        let email = "dev@example.com";
+        let fail = |count: usize| {
+            let limiter = limiter.clone();
+            async move {
+                for _ in 0..count {
+                    limiter.store.record(&failures_key(email), now).await.unwrap();
+                }
+            }
+        };
+        fail(1).await;
+        limiter.check_login_at("10.0.0.1", email, now).await.unwrap();
+        fail(1).await;
+        assert_eq!(retry_after(limiter.check_login_at("10.0.0.1", email, now).await), 30);
+        fail(1).await;
+        assert_eq!(retry_after(limiter.check_login_at("10.0.0.1", email, now).await), 60);
+        fail(5).await;
+        assert_eq!(retry_after(limiter.check_login_at("10.0.0.1", email, now).await), 120);
+        // Lockouts are per account
+        limiter.check_login_at("10.0.0.1", "other@example.com", now).await.unwrap();
+
+        limiter.login_succeeded(email).await.unwrap();
+        limiter.check_login_at("10.0.0.1", email, now).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_wrong_share_passwords_are_throttled() {
+        let limiter = limiter();
+        let now = Utc::now();
+        let link = Uuid::new_v4();
+        for _ in 0..10 {
+            limiter.check_share_password_at("10.0.0.1", link, now).await.unwrap();
+        }
+        for ip in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            limiter.share_password_failed(ip, link).await.unwrap();
+        }
+        // The link used its 3 wrong passwords, whatever address tries next
+        assert!((599..=600).contains(&retry_after(limiter.check_share_password_at("10.0.0.4", link, now).await)));
+        limiter.check_share_password_at("10.0.0.1", Uuid::new_v4(), now).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn email_requests_are_limited_per_address_and_recipient() {
+        let limiter = limiter();
+        let now = Utc::now();
+        limiter.check_email_request_at("10.0.0.1", "dev@example.com", now).await.unwrap();
+        limiter.check_email_request_at("10.0.0.2", "Dev@Example.com", now).await.unwrap();
+        // The recipient got its 2 emails, whoever asks next
+        assert_eq!(retry_after(limiter.check_email_request_at("10.0.0.3", "dev@example.com", now).await), 3600);
+
+        limiter.check_email_request_at("10.0.0.1", "a@example.com", now).await.unwrap();
+        limiter.check_email_request_at("10.0.0.1", "b@example.com", now).await.unwrap();
+        limiter.check_email_request_at("10.0.0.1", "c@example.com", now).await.unwrap();
+        // The address used its 4 requests, whatever it asks for
+        assert_eq!(retry_after(limiter.check_email_request_at("10.0.0.1", "d@example.com", now).await), 3600);
+    }
+
+    #[tokio::test]
+    async fn idle_keys_are_swept_from_memory() {
+        let store = MemoryLimiterStore::new(Duration::minutes(10));
+        let start = Utc::now();
+        for ip in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            store.record(&format!("login:ip:{}", ip), start).await.unwrap();
+        }
+        // The first record swept; the next sweep waits a few minutes
+        store.record("login:ip:10.0.0.4", start + Duration::minutes(4)).await.unwrap();
+        assert_eq!(store.events.len(), 4);
+
+        // Keys whose events all left the retention window are gone
+        store.record("login:ip:10.0.0.4", start + Duration::minutes(11)).await.unwrap();
+        assert_eq!(store.events.len(), 1);
+        assert_eq!(store.events.get("login:ip:10.0.0.4").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,124 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::share::SharedMessage;
+use crate::services::providers::ServedBy;
+
+// Characters of the first question kept as a conversation's title
+const TITLE_LEN: usize = 80;
+
+/// Chat threads and their messages, so they can be continued and shared.
+#[derive(Clone)]
+pub struct ConversationService {
+    pool: PgPool,
+}
+
+/// One question and the AI's answer to it.
+pub struct Exchange<'a> {
+    pub question: &'a str,
+    pub answer: &'a str,
+    pub served_by: &'a ServedBy,
+    pub prompt_version: &'a str,
+}
+
+impl ConversationService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Fails unless the conversation exists and belongs to `user_id`.
+    pub async fn ensure_owner(&self, user_id: Uuid, conversation_id: Uuid) -> Result<(), AppError> {
+        sqlx::query_scalar!(
+            "SELECT id FROM conversations WHERE id = $1 AND user_id = $2",
+            conversation_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Conversation not found".to_string()))?;
+        Ok(())
+    }
+
+    /// Appends `exchange` to the user's conversation, or starts a new one titled after the
+    /// question when `conversation_id` is `None`. Returns the conversation's id.
+    pub async fn record(
+        &self,
+        user_id: Uuid,
+        conversation_id: Option<Uuid>,
+        upload_id: Option<Uuid>,
+        exchange: Exchange<'_>,
+    ) -> Result<Uuid, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let conversation_id = match conversation_id {
+            Some(id) => sqlx::query_scalar!(
+                "UPDATE conversations SET updated_at = NOW() WHERE id = $1 AND user_id = $2 RETURNING id",
+                id,
+                user_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Conversation not found".to_string()))?,
+            None => {
+                let title: String = exchange.question.trim().chars().take(TITLE_LEN).collect();
+                sqlx::query_scalar!(
+                    "INSERT INTO conversations (user_id, upload_id, title) VALUES ($1, $2, $3) RETURNING id",
+                    user_id,
+                    upload_id,
+                    title
+                )
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+
+        // clock_timestamp() rather than NOW(), which is fixed for the transaction, keeps
+        // the question ordered before its answer
+        sqlx::query!(
+            r#"
+            INSERT INTO conversation_messages (conversation_id, role, content, created_at)
+            VALUES ($1, 'user', $2, clock_timestamp())
+            "#,
+            conversation_id,
+            exchange.question
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO conversation_messages (conversation_id, role, content, ai_provider, ai_model, prompt_version, created_at)
+            VALUES ($1, 'assistant', $2, $3, $4, $5, clock_timestamp())
+            "#,
+            conversation_id,
+            exchange.answer,
+            exchange.served_by.provider,
+            exchange.served_by.model,
+            exchange.prompt_version
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(conversation_id)
+    }
+
+    /// Title and messages of a conversation, oldest first.
+    pub async fn transcript(&self, conversation_id: Uuid) -> Result<(Option<String>, Vec<SharedMessage>), AppError> {
+        let title = sqlx::query_scalar!("SELECT title FROM conversations WHERE id = $1", conversation_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Conversation not found".to_string()))?;
+        let messages = sqlx::query_as!(
+            SharedMessage,
+            r#"
+            SELECT role, content, created_at
+            FROM conversation_messages
+            WHERE conversation_id = $1
+            ORDER BY created_at
+            "#,
+            conversation_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok((title, messages))
+    }
+}
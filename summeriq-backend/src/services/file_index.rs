@@ -0,0 +1,201 @@
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::file_index::{TreeEntry, TreePage, TreeQuery};
+use crate::models::project::ProjectVersion;
+use crate::services::file_types::FileMetadata;
+use crate::services::storage::FileNode;
+use crate::services::StorageService;
+
+const DEFAULT_PAGE_SIZE: i64 = 200;
+const MAX_PAGE_SIZE: i64 = 1000;
+// Rows written per INSERT when indexing an upload
+const INSERT_BATCH: usize = 1000;
+
+/// A row of `file_index` before it is written.
+struct IndexRow {
+    path: String,
+    name: String,
+    is_dir: bool,
+    depth: i32,
+    subtree_end: i32,
+    size: i64,
+    child_count: Option<i32>,
+    metadata: Option<Value>,
+}
+
+/// Persisted index of the files of extracted uploads, so large trees are listed a directory
+/// at a time without walking the disk. Callers check that the user may access the upload.
+#[derive(Clone)]
+pub struct FileIndexService {
+    pool: PgPool,
+    storage_service: StorageService,
+}
+
+impl FileIndexService {
+    pub fn new(pool: PgPool, storage_service: StorageService) -> Self {
+        Self { pool, storage_service }
+    }
+
+    /// Stores the tree listed for an upload, replacing an earlier index.
+    pub async fn index_upload(&self, upload_id: Uuid, nodes: &[FileNode]) -> Result<(), AppError> {
+        let mut rows = vec![IndexRow {
+            path: String::new(),
+            name: String::new(),
+            is_dir: true,
+            depth: 0,
+            subtree_end: 0,
+            size: 0,
+            child_count: Some(nodes.len() as i32),
+            metadata: None,
+        }];
+        rows[0].size = flatten(nodes, 1, &mut rows);
+        rows[0].subtree_end = rows.len() as i32 - 1;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM file_index WHERE upload_id = $1", upload_id)
+            .execute(&mut *tx)
+            .await?;
+        for (batch, chunk) in rows.chunks(INSERT_BATCH).enumerate() {
+            let first = (batch * INSERT_BATCH) as i32;
+            let positions: Vec<i32> = (first..first + chunk.len() as i32).collect();
+            sqlx::query!(
+                r#"
+                INSERT INTO file_index (upload_id, position, path, name, is_dir, depth, subtree_end, size, child_count, metadata)
+                SELECT $1, * FROM UNNEST($2::int4[], $3::text[], $4::text[], $5::bool[], $6::int4[], $7::int4[], $8::int8[], $9::int4[], $10::jsonb[])
+                ON CONFLICT DO NOTHING
+                "#,
+                upload_id,
+                &positions,
+                &chunk.iter().map(|row| row.path.clone()).collect::<Vec<_>>(),
+                &chunk.iter().map(|row| row.name.clone()).collect::<Vec<_>>(),
+                &chunk.iter().map(|row| row.is_dir).collect::<Vec<_>>(),
+                &chunk.iter().map(|row| row.depth).collect::<Vec<_>>(),
+                &chunk.iter().map(|row| row.subtree_end).collect::<Vec<_>>(),
+                &chunk.iter().map(|row| row.size).collect::<Vec<_>>(),
+                &chunk.iter().map(|row| row.child_count).collect::<Vec<_>>() as &[Option<i32>],
+                &chunk.iter().map(|row| row.metadata.clone()).collect::<Vec<_>>() as &[Option<Value>]
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!("Indexed {} entries of upload {}", rows.len() - 1, upload_id);
+        Ok(())
+    }
+
+    /// One page of the entries below `query.path` in a project version, up to `query.depth`
+    /// levels deep.
+    pub async fn list_tree(&self, version: &ProjectVersion, query: &TreeQuery) -> Result<TreePage, AppError> {
+        let mut page = self.list_upload(version.upload_id, &version.extracted_dir(), query).await?;
+        page.version = Some(version.version);
+        Ok(page)
+    }
+
+    /// One page of the entries below `query.path` in an upload extracted to `extracted_dir`;
+    /// `query.version` is ignored. Uploads from before the index existed are indexed on first use.
+    pub async fn list_upload(&self, upload_id: Uuid, extracted_dir: &str, query: &TreeQuery) -> Result<TreePage, AppError> {
+        let path = query.path.as_deref().unwrap_or("").trim_matches('/').to_string();
+        let depth = query.depth.unwrap_or(1).max(1);
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+        let directory = match self.find_entry(upload_id, &path).await? {
+            Some(entry) => entry,
+            None if self.find_entry(upload_id, "").await?.is_none() => {
+                let nodes = self.storage_service.list_files(extracted_dir).await?;
+                self.index_upload(upload_id, &nodes).await?;
+                self.find_entry(upload_id, &path).await?.ok_or_else(directory_not_found)?
+            }
+            None => return Err(directory_not_found()),
+        };
+        if !directory.is_dir {
+            return Err(AppError::BadRequest(format!("{} is not a directory", path)));
+        }
+
+        let entries = sqlx::query_as!(
+            TreeEntry,
+            r#"
+            SELECT name, path, is_dir, size, child_count, metadata as "file_type: Json<FileMetadata>", position, depth, subtree_end
+            FROM file_index
+            WHERE upload_id = $1
+              AND depth BETWEEN $2 AND $3
+              AND position > GREATEST($4, $5::int4)
+              AND position <= $6
+            ORDER BY position
+            LIMIT $7
+            "#,
+            upload_id,
+            directory.depth + 1,
+            directory.depth.saturating_add(depth),
+            directory.position,
+            query.cursor,
+            directory.subtree_end,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let next_cursor = match entries.last() {
+            Some(last) if entries.len() as i64 == limit => Some(last.position),
+            _ => None,
+        };
+        Ok(TreePage {
+            version: None,
+            path,
+            directory,
+            entries,
+            next_cursor,
+        })
+    }
+
+    async fn find_entry(&self, upload_id: Uuid, path: &str) -> Result<Option<TreeEntry>, AppError> {
+        let entry = sqlx::query_as!(
+            TreeEntry,
+            r#"
+            SELECT name, path, is_dir, size, child_count, metadata as "file_type: Json<FileMetadata>", position, depth, subtree_end
+            FROM file_index
+            WHERE upload_id = $1 AND path = $2
+            "#,
+            upload_id,
+            path
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(entry)
+    }
+}
+
+fn directory_not_found() -> AppError {
+    AppError::NotFound("Directory not found".to_string())
+}
+
+/// Appends `nodes` and everything below them to `rows` in tree order, returning their size.
+fn flatten(nodes: &[FileNode], depth: i32, rows: &mut Vec<IndexRow>) -> i64 {
+    let mut total = 0;
+    for node in nodes {
+        let index = rows.len();
+        rows.push(IndexRow {
+            path: node.path.clone(),
+            name: node.name.clone(),
+            is_dir: node.is_dir,
+            depth,
+            subtree_end: index as i32,
+            size: node.metadata.as_ref().map_or(0, |metadata| metadata.size as i64),
+            child_count: None,
+            metadata: node.metadata.as_ref().and_then(|metadata| serde_json::to_value(metadata).ok()),
+        });
+        if node.is_dir {
+            let children = node.children.as_deref().unwrap_or_default();
+            rows[index].size = flatten(children, depth + 1, rows);
+            rows[index].subtree_end = rows.len() as i32 - 1;
+            rows[index].child_count = Some(children.len() as i32);
+        }
+        total += rows[index].size;
+    }
+    total
+}
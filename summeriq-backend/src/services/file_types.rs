@@ -0,0 +1,339 @@
+use std::io::Read;
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// Files up to this size are read whole to count lines; larger ones only get sniffed
+const MAX_INSPECT_BYTES: u64 = 4 * 1024 * 1024;
+// Bytes looked at to tell text from binary, as git does
+const SNIFF_BYTES: usize = 8000;
+// Minified JavaScript and CSS have long lines; linguist uses the same threshold
+const MINIFIED_AVERAGE_LINE_LENGTH: usize = 110;
+
+/// What a file holds, detected from its name and content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    pub size: u64,
+    pub mime_type: String,
+    pub binary: bool,
+    /// `utf-8`, `utf-16le`, `utf-16be` or `iso-8859-1`; absent for binary files.
+    pub encoding: Option<String>,
+    /// Absent for binary files and for text files too large to count.
+    pub line_count: Option<u64>,
+    pub language: Option<String>,
+    /// Produced by a tool: minified bundles, source maps, lockfiles, generated code.
+    pub generated: bool,
+    /// Third-party code copied into the project.
+    pub vendored: bool,
+}
+
+impl FileMetadata {
+    /// Whether the file is the project's own text, worth showing to the AI.
+    pub fn is_source(&self) -> bool {
+        !self.binary && !self.generated && !self.vendored
+    }
+}
+
+/// Reads enough of the file at `path` to describe it. `relative` is its path in the project.
+pub fn inspect(path: &Path, relative: &str) -> std::io::Result<FileMetadata> {
+    let size = std::fs::metadata(path)?.len();
+    let mut content = Vec::new();
+    let limit = if size <= MAX_INSPECT_BYTES { size } else { SNIFF_BYTES as u64 };
+    std::fs::File::open(path)?.take(limit).read_to_end(&mut content)?;
+    Ok(detect(relative, &content, size))
+}
+
+/// Describes a file of `size` bytes from its path and the first bytes of its content, or all
+/// of it. Lines are only counted when `content` is the whole file.
+pub fn detect(path: &str, content: &[u8], size: u64) -> FileMetadata {
+    let encoding = detect_encoding(content);
+    let binary = encoding.is_none();
+    let complete = content.len() as u64 == size;
+    let text = match encoding {
+        Some(encoding) if complete => decode(content, encoding),
+        _ => None,
+    };
+    let line_count = text.as_deref().map(count_lines);
+    let sniff_text = text.clone().or_else(|| encoding.and_then(|e| decode(&content[..content.len().min(SNIFF_BYTES)], e)));
+
+    let guessed = mime_guess::from_path(path).first().map(|mime| mime.essence_str().to_string());
+    let mime_type = if binary {
+        sniff_mime(content)
+            .map(str::to_string)
+            .or(guessed)
+            .unwrap_or_else(|| "application/octet-stream".to_string())
+    } else {
+        // Extensions shared with binary formats (`.ts` is also MPEG video) do not count for text
+        guessed.filter(|mime| is_text_mime(mime)).unwrap_or_else(|| "text/plain".to_string())
+    };
+    let language = if binary { None } else { detect_language(path, sniff_text.as_deref().unwrap_or("")) };
+    let generated = is_generated(path, text.as_deref().or(sniff_text.as_deref()).unwrap_or(""), language);
+
+    FileMetadata {
+        size,
+        mime_type,
+        binary,
+        encoding: encoding.map(str::to_string),
+        line_count,
+        language: language.map(str::to_string),
+        generated,
+        vendored: is_vendored(path),
+    }
+}
+
+/// The text of a file in `encoding`, as returned in [`FileMetadata::encoding`].
+pub fn decode(content: &[u8], encoding: &str) -> Option<String> {
+    match encoding {
+        "utf-8" => {
+            let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+            // A sniffed prefix may end inside a character
+            match std::str::from_utf8(content) {
+                Ok(text) => Some(text.to_string()),
+                Err(e) if e.error_len().is_none() => Some(String::from_utf8_lossy(&content[..e.valid_up_to()]).into_owned()),
+                Err(_) => None,
+            }
+        }
+        "utf-16le" | "utf-16be" => {
+            let content = content.get(2..)?;
+            let units: Vec<u16> = content
+                .chunks_exact(2)
+                .map(|pair| match encoding {
+                    "utf-16le" => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        "iso-8859-1" => Some(content.iter().map(|&b| b as char).collect()),
+        _ => None,
+    }
+}
+
+/// `None` for binary content.
+fn detect_encoding(content: &[u8]) -> Option<&'static str> {
+    if content.starts_with(b"\xFF\xFE") {
+        return Some("utf-16le");
+    }
+    if content.starts_with(b"\xFE\xFF") {
+        return Some("utf-16be");
+    }
+    let sniffed = &content[..content.len().min(SNIFF_BYTES)];
+    if sniffed.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(sniffed) {
+        Ok(_) => Some("utf-8"),
+        Err(e) if e.error_len().is_none() => Some("utf-8"),
+        Err(_) => {
+            // Legacy 8-bit text has few control characters besides whitespace
+            let controls = sniffed
+                .iter()
+                .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0C))
+                .count();
+            (controls * 100 < sniffed.len()).then_some("iso-8859-1")
+        }
+    }
+}
+
+fn sniff_mime(content: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xFF\xD8\xFF", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1F\x8B", "application/gzip"),
+        (b"\x00asm", "application/wasm"),
+        (b"\x7FELF", "application/x-elf"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+    ];
+    if content.len() >= 12 && &content[..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| content.starts_with(signature))
+        .map(|(_, mime)| *mime)
+}
+
+fn is_text_mime(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime,
+            "application/json" | "application/xml" | "application/javascript" | "application/x-javascript"
+                | "application/toml" | "application/x-yaml" | "application/sql" | "application/x-sh"
+        )
+}
+
+fn count_lines(text: &str) -> u64 {
+    let newlines = text.matches('\n').count() as u64;
+    if text.is_empty() || text.ends_with('\n') {
+        newlines
+    } else {
+        newlines + 1
+    }
+}
+
+const LANGUAGES_BY_EXTENSION: &[(&str, &str)] = &[
+    ("rs", "Rust"), ("ts", "TypeScript"), ("tsx", "TypeScript"), ("mts", "TypeScript"), ("cts", "TypeScript"),
+    ("js", "JavaScript"), ("jsx", "JavaScript"), ("mjs", "JavaScript"), ("cjs", "JavaScript"),
+    ("py", "Python"), ("pyi", "Python"), ("go", "Go"), ("java", "Java"), ("kt", "Kotlin"), ("kts", "Kotlin"),
+    ("rb", "Ruby"), ("php", "PHP"), ("c", "C"), ("cc", "C++"), ("cpp", "C++"), ("cxx", "C++"), ("hpp", "C++"),
+    ("hh", "C++"), ("cs", "C#"), ("swift", "Swift"), ("scala", "Scala"), ("dart", "Dart"), ("ex", "Elixir"),
+    ("exs", "Elixir"), ("erl", "Erlang"), ("hs", "Haskell"), ("lua", "Lua"), ("r", "R"), ("pl", "Perl"),
+    ("sh", "Shell"), ("bash", "Shell"), ("zsh", "Shell"), ("ps1", "PowerShell"), ("sql", "SQL"),
+    ("html", "HTML"), ("htm", "HTML"), ("css", "CSS"), ("scss", "SCSS"), ("sass", "Sass"), ("less", "Less"),
+    ("vue", "Vue"), ("svelte", "Svelte"), ("json", "JSON"), ("yaml", "YAML"), ("yml", "YAML"), ("toml", "TOML"),
+    ("xml", "XML"), ("svg", "SVG"), ("md", "Markdown"), ("markdown", "Markdown"), ("proto", "Protocol Buffer"),
+    ("graphql", "GraphQL"), ("gql", "GraphQL"), ("tf", "HCL"), ("ini", "INI"), ("txt", "Text"),
+];
+
+const LANGUAGES_BY_FILENAME: &[(&str, &str)] = &[
+    ("dockerfile", "Dockerfile"), ("makefile", "Makefile"), ("gnumakefile", "Makefile"),
+    ("cmakelists.txt", "CMake"), ("gemfile", "Ruby"), ("rakefile", "Ruby"), ("jenkinsfile", "Groovy"),
+    ("cargo.lock", "TOML"), ("go.mod", "Go Module"),
+];
+
+const LANGUAGES_BY_INTERPRETER: &[(&str, &str)] = &[
+    ("python", "Python"), ("node", "JavaScript"), ("deno", "TypeScript"), ("bash", "Shell"), ("sh", "Shell"),
+    ("zsh", "Shell"), ("ruby", "Ruby"), ("perl", "Perl"), ("php", "PHP"),
+];
+
+static CPP_HEADER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?m)^\s*(?:class\s+\w+|namespace\s+\w+|template\s*<|#include\s*<(?:iostream|string|vector|memory)>)|std::")
+        .expect("invalid C++ pattern")
+});
+static OBJECTIVE_C: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^\s*(?:@interface|@implementation|@import|#import)").expect("invalid Objective-C pattern"));
+
+/// The language of a text file, linguist-style: known file names first, then the extension,
+/// with the content deciding between languages sharing one, then a shebang line.
+fn detect_language(path: &str, content: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path).to_ascii_lowercase();
+    if let Some((_, language)) = LANGUAGES_BY_FILENAME.iter().find(|(file, _)| *file == name) {
+        return Some(language);
+    }
+    if name.starts_with("dockerfile") {
+        return Some("Dockerfile");
+    }
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+    match extension {
+        "h" => return Some(if CPP_HEADER.is_match(content) { "C++" } else if OBJECTIVE_C.is_match(content) { "Objective-C" } else { "C" }),
+        "m" => return Some(if OBJECTIVE_C.is_match(content) { "Objective-C" } else { "MATLAB" }),
+        _ => {}
+    }
+    if let Some((_, language)) = LANGUAGES_BY_EXTENSION.iter().find(|(ext, _)| *ext == extension) {
+        return Some(language);
+    }
+    let shebang = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = shebang.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    LANGUAGES_BY_INTERPRETER
+        .iter()
+        .find(|(name, _)| interpreter == *name || interpreter.strip_prefix(name).is_some_and(|v| v.chars().all(|c| c.is_ascii_digit() || c == '.')))
+        .map(|(_, language)| *language)
+}
+
+const GENERATED_SUFFIXES: &[&str] = &[
+    ".min.js", ".min.css", "-min.js", ".bundle.js", ".js.map", ".css.map", ".pb.go", "_pb2.py", "_pb2_grpc.py",
+    ".pb.cc", ".pb.h", ".g.dart", ".freezed.dart", ".designer.cs", ".generated.ts", ".generated.js",
+];
+const GENERATED_FILES: &[&str] = &[
+    "package-lock.json", "yarn.lock", "pnpm-lock.yaml", "cargo.lock", "poetry.lock", "pipfile.lock", "gemfile.lock",
+    "composer.lock", "go.sum",
+];
+static GENERATED_MARKER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)@generated|code generated .* do not edit|auto-?generated|this file (?:is|was) generated|generated by (?:the )?[\w.-]+ (?:compiler|generator|tool)")
+        .expect("invalid generated marker pattern")
+});
+
+fn is_generated(path: &str, content: &str, language: Option<&str>) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path).to_ascii_lowercase();
+    if GENERATED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) || GENERATED_FILES.contains(&name.as_str()) {
+        return true;
+    }
+    // Generators announce themselves at the top of the file
+    let header: String = content.lines().take(5).collect::<Vec<_>>().join("\n");
+    if GENERATED_MARKER.is_match(&header) {
+        return true;
+    }
+    let lines = content.lines().count();
+    matches!(language, Some("JavaScript" | "CSS")) && lines > 0 && content.len() / lines > MINIFIED_AVERAGE_LINE_LENGTH
+}
+
+const VENDORED_DIRECTORIES: &[&str] = &[
+    "node_modules", "bower_components", "vendor", "vendors", "third_party", "third-party", "thirdparty",
+    "external", "deps", "Godeps", ".yarn",
+];
+static VENDORED_LIBRARY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^(?:jquery|bootstrap|angular|react|react-dom|vue|lodash|underscore|moment|d3|three)(?:[.-]\d[\w.-]*)?(?:\.min)?\.(?:js|css)$")
+        .expect("invalid vendored library pattern")
+});
+
+fn is_vendored(path: &str) -> bool {
+    let mut components: Vec<&str> = path.split('/').collect();
+    let name = components.pop().unwrap_or(path);
+    components.iter().any(|dir| VENDORED_DIRECTORIES.contains(dir)) || VENDORED_LIBRARY.is_match(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_all(path: &str, content: &[u8]) -> FileMetadata {
+        detect(path, content, content.len() as u64)
+    }
+
+    #[test]
+    fn tells_text_encodings_from_binary() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        let image = detect_all("assets/logo.png", png);
+        assert!(image.binary);
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!((image.encoding, image.line_count, image.language), (None, None, None));
+
+        let latin1 = detect_all("src/legacy.c", b"/* caf\xE9 */\nint main(void) { return 0; }\n");
+        assert_eq!(latin1.encoding.as_deref(), Some("iso-8859-1"));
+        assert_eq!(latin1.line_count, Some(2));
+        assert_eq!(latin1.language.as_deref(), Some("C"));
+
+        let utf16 = detect_all("notes.txt", b"\xFF\xFEa\x00\n\x00b\x00");
+        assert_eq!(utf16.encoding.as_deref(), Some("utf-16le"));
+        assert_eq!(utf16.line_count, Some(2));
+    }
+
+    #[test]
+    fn detects_language_by_name_extension_content_and_shebang() {
+        assert_eq!(detect_language("Dockerfile", ""), Some("Dockerfile"));
+        assert_eq!(detect_language("src/main.rs", ""), Some("Rust"));
+        assert_eq!(detect_language("include/vec.h", "namespace geo {\n"), Some("C++"));
+        assert_eq!(detect_language("include/list.h", "struct list;\n"), Some("C"));
+        assert_eq!(detect_language("bin/deploy", "#!/usr/bin/env python3\nprint()\n"), Some("Python"));
+        assert_eq!(detect_language("LICENSE", "MIT License\n"), None);
+    }
+
+    #[test]
+    fn flags_generated_and_vendored_files() {
+        let bundle = format!("{}\n", "var a=1;".repeat(40));
+        assert!(detect_all("dist/app.js", bundle.as_bytes()).generated);
+        assert!(detect_all("api/user.pb.go", b"package api\n").generated);
+        assert!(detect_all("api/user.go", b"// Code generated by protoc-gen-go. DO NOT EDIT.\npackage api\n").generated);
+        assert!(!detect_all("src/app.js", b"const a = 1;\n").generated);
+        assert_eq!(detect_all("src/app.ts", b"export {};\n").mime_type, "text/plain");
+
+        assert!(is_vendored("vendor/github.com/pkg/errors/errors.go"));
+        assert!(is_vendored("static/jquery-3.7.1.min.js"));
+        assert!(!is_vendored("src/vendors.ts"));
+    }
+}
@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::path::PathBuf;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// A plain-text email.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers emails to users.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), AppError>;
+}
+
+/// Sends emails through an SMTP relay.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// `url` takes the form `smtp[s]://user:password@host:port`; `smtp://` upgrades with
+    /// STARTTLS only when given `?tls=required`.
+    pub fn new(url: &str, from: &str) -> Result<Self, AppError> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| AppError::InternalServerError(format!("Invalid SMTP URL: {}", e)))?
+            .build();
+        let from = from
+            .parse()
+            .map_err(|e| AppError::InternalServerError(format!("Invalid sender address '{}': {}", from, e)))?;
+        Ok(Self { transport, from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let to: Mailbox = email
+            .to
+            .parse()
+            .map_err(|e| AppError::BadRequest(format!("Invalid email address '{}': {}", email.to, e)))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&email.subject)
+            .body(email.body.clone())
+            .map_err(|e| AppError::InternalServerError(format!("Failed to build email: {}", e)))?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Failed to send email: {}", e)))?;
+        info!("Sent \"{}\" to {}", email.subject, email.to);
+        Ok(())
+    }
+}
+
+/// Writes each email to a text file in `dir` instead of sending it, for local development
+/// and tests.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self
+            .dir
+            .join(format!("{}_{}.txt", Utc::now().format("%Y%m%dT%H%M%S%.9f"), Uuid::new_v4().simple()));
+        let content = format!("To: {}\nSubject: {}\n\n{}\n", email.to, email.subject, email.body);
+        tokio::fs::write(&path, content).await?;
+        info!("Wrote \"{}\" for {} to {}", email.subject, email.to, path.display());
+        Ok(())
+    }
+}
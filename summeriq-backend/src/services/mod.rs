@@ -1,9 +1,37 @@
+pub mod api_tokens;
+pub mod audit;
 pub mod auth;
+pub mod auth_limiter;
+pub mod conversations;
+pub mod file_index;
+pub mod file_types;
+pub mod mailer;
+pub mod oidc;
+pub mod organizations;
 pub mod storage;
 pub mod ai;
 pub mod analysis;
+pub mod project;
+pub mod prompts;
+pub mod providers;
+pub mod rate_limit;
+pub mod reasoning;
+pub mod review;
+pub mod secrets;
+pub mod shares;
+pub mod tokens;
+pub mod usage;
 
+pub use audit::AuditService;
 pub use auth::AuthService;
 pub use storage::StorageService;
 pub use ai::AIService;
-pub use analysis::AnalysisService; 
\ No newline at end of file
+pub use analysis::AnalysisService;
+pub use conversations::ConversationService;
+pub use file_index::FileIndexService;
+pub use organizations::OrganizationService;
+pub use project::ProjectService;
+pub use review::ReviewService;
+pub use secrets::SecretService;
+pub use shares::ShareService;
+pub use usage::UsageService; 
\ No newline at end of file
@@ -0,0 +1,335 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Client;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::sync::{OnceCell, RwLock};
+use tracing::{error, info};
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::services::auth::new_secret_token;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct OidcSettings {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where the provider sends users back to with the authorization code.
+    pub redirect_url: String,
+    pub scopes: Vec<String>,
+}
+
+impl OidcSettings {
+    /// `None` unless an issuer is configured.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if config.oidc_issuer.is_empty() {
+            return None;
+        }
+        let redirect_url = if config.oidc_redirect_url.is_empty() {
+            format!("{}/auth/callback", config.public_url.trim_end_matches('/'))
+        } else {
+            config.oidc_redirect_url.clone()
+        };
+        Some(Self {
+            issuer: config.oidc_issuer.trim_end_matches('/').to_string(),
+            client_id: config.oidc_client_id.clone(),
+            client_secret: config.oidc_client_secret.clone(),
+            redirect_url,
+            scopes: config.oidc_scopes.clone(),
+        })
+    }
+}
+
+/// The parts of the provider's discovery document used here.
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    access_token: String,
+}
+
+/// Claims of an ID token or userinfo response that identify the user.
+#[derive(Debug, Deserialize)]
+struct IdentityClaims {
+    sub: String,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    email_verified: bool,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Who the provider says signed in.
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    /// Whether the provider vouches that `email` belongs to the user.
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// A login to send the user's browser off with. `state`, `nonce` and `code_verifier` must
+/// be kept until the provider sends the user back.
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// Relying party of the authorization code flow with PKCE against one OpenID Connect
+/// provider. The discovery document is fetched on first use and signing keys whenever an
+/// ID token names one that is not known yet.
+pub struct OidcClient {
+    http: Client,
+    settings: OidcSettings,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings) -> Self {
+        info!("Single sign-on enabled with OpenID Connect issuer {}", settings.issuer);
+        Self {
+            http: Client::builder().timeout(HTTP_TIMEOUT).build().unwrap_or_default(),
+            settings,
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(JwkSet { keys: Vec::new() }),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.settings.issuer
+    }
+
+    /// A fresh login at the provider's authorization endpoint.
+    pub async fn authorization_request(&self) -> Result<AuthorizationRequest, AppError> {
+        let metadata = self.metadata().await?;
+        let (state, nonce, code_verifier) = (new_secret_token(), new_secret_token(), new_secret_token());
+        let mut url = url::Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| provider_error(format!("invalid authorization endpoint: {}", e)))?;
+        let scope = std::iter::once("openid")
+            .chain(self.settings.scopes.iter().map(String::as_str).filter(|scope| *scope != "openid"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.settings.redirect_url)
+            .append_pair("scope", &scope)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok(AuthorizationRequest { url: url.into(), state, nonce, code_verifier })
+    }
+
+    /// Redeems an authorization code and returns the identity its ID token vouches for.
+    /// The email comes from the userinfo endpoint when the ID token does not carry one.
+    pub async fn authenticate(&self, code: &str, code_verifier: &str, nonce: &str) -> Result<OidcIdentity, AppError> {
+        let tokens = self.exchange(code, code_verifier).await?;
+        let mut claims = self.verify_id_token(&tokens.id_token, nonce).await?;
+        if claims.email.is_none() {
+            if let Some(info) = self.userinfo(&tokens.access_token).await? {
+                // Userinfo about someone else must not lend its email to this login
+                if info.sub == claims.sub {
+                    claims.email = info.email;
+                    claims.email_verified = info.email_verified;
+                    claims.name = claims.name.or(info.name);
+                }
+            }
+        }
+        Ok(OidcIdentity {
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified,
+            name: claims.name,
+        })
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, AppError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.settings.issuer);
+                let metadata: ProviderMetadata = self.get_json(&url, None).await?;
+                if metadata.issuer.trim_end_matches('/') != self.settings.issuer {
+                    return Err(provider_error(format!(
+                        "discovery document names issuer {} instead of {}",
+                        metadata.issuer, self.settings.issuer
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    async fn exchange(&self, code: &str, code_verifier: &str) -> Result<TokenResponse, AppError> {
+        let metadata = self.metadata().await?;
+        let mut request = self.http.post(&metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.settings.redirect_url),
+            ("client_id", &self.settings.client_id),
+            ("code_verifier", code_verifier),
+        ]);
+        if !self.settings.client_secret.is_empty() {
+            request = request.basic_auth(&self.settings.client_id, Some(&self.settings.client_secret));
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| provider_error(format!("token request failed: {}", e)))?;
+        let status = response.status();
+        if status.is_client_error() {
+            let body = response.text().await.unwrap_or_default();
+            info!("Identity provider rejected an authorization code: {} {}", status, body);
+            return Err(AppError::AuthenticationError("The identity provider rejected the login".to_string()));
+        }
+        if !status.is_success() {
+            return Err(provider_error(format!("token endpoint answered {}", status)));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| provider_error(format!("invalid token response: {}", e)))
+    }
+
+    /// Checks the ID token's signature, issuer, audience, expiry and nonce. Tokens signed
+    /// with HMAC are checked against the client secret, all others against the provider's
+    /// published keys.
+    async fn verify_id_token(&self, id_token: &str, nonce: &str) -> Result<IdentityClaims, AppError> {
+        let invalid = |reason: String| {
+            info!("Rejected ID token: {}", reason);
+            AppError::AuthenticationError("Invalid ID token".to_string())
+        };
+        let header = decode_header(id_token).map_err(|e| invalid(e.to_string()))?;
+        let key = match header.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                if self.settings.client_secret.is_empty() {
+                    return Err(invalid("HMAC-signed token without a client secret".to_string()));
+                }
+                DecodingKey::from_secret(self.settings.client_secret.as_bytes())
+            }
+            _ => {
+                let jwk = self.signing_key(header.kid.as_deref()).await?.ok_or_else(|| {
+                    invalid(format!("unknown signing key {:?}", header.kid))
+                })?;
+                DecodingKey::from_jwk(&jwk).map_err(|e| invalid(e.to_string()))?
+            }
+        };
+
+        let metadata = self.metadata().await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.settings.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<IdentityClaims>(id_token, &key, &validation)
+            .map_err(|e| invalid(e.to_string()))?
+            .claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("nonce does not match the login".to_string()));
+        }
+        Ok(claims)
+    }
+
+    /// The provider's key `kid`, or its only key when the token names none. Keys are
+    /// fetched again once when the key is not known, as providers rotate them.
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Option<Jwk>, AppError> {
+        let find = |jwks: &JwkSet| match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+            None => None,
+        };
+        if let Some(key) = find(&*self.jwks.read().await) {
+            return Ok(Some(key));
+        }
+        let metadata = self.metadata().await?;
+        let jwks: JwkSet = self.get_json(&metadata.jwks_uri, None).await?;
+        let key = find(&jwks);
+        *self.jwks.write().await = jwks;
+        Ok(key)
+    }
+
+    async fn userinfo(&self, access_token: &str) -> Result<Option<IdentityClaims>, AppError> {
+        match &self.metadata().await?.userinfo_endpoint {
+            Some(endpoint) => Ok(Some(self.get_json(endpoint, Some(access_token)).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str, bearer: Option<&str>) -> Result<T, AppError> {
+        let mut request = self.http.get(url);
+        if let Some(token) = bearer {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| provider_error(format!("request to {} failed: {}", url, e)))?;
+        response
+            .json()
+            .await
+            .map_err(|e| provider_error(format!("invalid response from {}: {}", url, e)))
+    }
+}
+
+fn provider_error(message: String) -> AppError {
+    error!("OpenID Connect provider error: {}", message);
+    AppError::InternalServerError("The identity provider could not be reached".to_string())
+}
+
+/// PKCE `S256` challenge for `verifier`: its SHA-256 hash, base64url encoded without padding.
+pub(crate) fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// `email_verified` as a boolean, or as the string some providers send instead.
+fn lenient_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Bool(value) => value,
+        Value::String(value) => value.eq_ignore_ascii_case("true"),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_is_unpadded_base64url_sha256() {
+        assert_eq!(
+            pkce_challenge("0123456789abcdef0123456789abcdef0123456789abcdef"),
+            "NMJuFUurX_VE8p-HR6aR0P7dO4pRZV8n0PWFsbJ1OXA"
+        );
+    }
+
+    #[test]
+    fn reads_email_verified_strings() {
+        let claims: IdentityClaims =
+            serde_json::from_value(serde_json::json!({ "sub": "1", "email_verified": "true" })).unwrap();
+        assert!(claims.email_verified);
+        let claims: IdentityClaims = serde_json::from_value(serde_json::json!({ "sub": "1" })).unwrap();
+        assert!(!claims.email_verified);
+    }
+}
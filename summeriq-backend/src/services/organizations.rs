@@ -0,0 +1,527 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::organization::{
+    CreateOrganization, CreatedOrgInvite, InviteMember, OrgInvite, OrgMember, OrgRole, OrgUpload, Organization,
+    Permission,
+};
+use crate::services::mailer::{Email, Mailer};
+
+// Audience of invitation tokens, so they can never pass for access tokens signed with the same secret
+const INVITE_AUDIENCE: &str = "summeriq:org-invite";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InviteClaims {
+    /// The invitation; the token stops working once it is accepted or revoked.
+    sub: String,
+    org: String,
+    aud: String,
+    exp: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrganizationSettings {
+    pub jwt_secret: String,
+    pub invite_ttl: Duration,
+    /// Base URL of the web app the emailed invitation links point to.
+    pub public_url: String,
+}
+
+impl OrganizationSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            jwt_secret: config.jwt_secret.clone(),
+            invite_ttl: Duration::days(config.invite_ttl_days),
+            public_url: config.public_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+/// Organizations, their members and invitations, and who may do what with an upload.
+///
+/// An upload that belongs to an organization is available to its members according to
+/// their role. Any other upload is private to the user who uploaded it, who may do
+/// everything with it. Users without access are told the upload does not exist.
+#[derive(Clone)]
+pub struct OrganizationService {
+    pool: PgPool,
+    settings: OrganizationSettings,
+    mailer: Arc<dyn Mailer>,
+}
+
+impl OrganizationService {
+    pub fn new(pool: PgPool, settings: OrganizationSettings, mailer: Arc<dyn Mailer>) -> Self {
+        Self { pool, settings, mailer }
+    }
+
+    /// Role `user_id` acts with on the upload, or `None` when it is not theirs to see.
+    pub async fn upload_role(&self, user_id: Uuid, upload_id: Uuid) -> Result<Option<OrgRole>, AppError> {
+        let upload = sqlx::query!(
+            r#"
+            SELECT u.user_id, u.organization_id, m.role AS "role?"
+            FROM uploads u
+            LEFT JOIN organization_members m ON m.organization_id = u.organization_id AND m.user_id = $2
+            WHERE u.id = $1
+            "#,
+            upload_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(upload.and_then(|upload| match upload.organization_id {
+            Some(_) => upload.role.as_deref().and_then(OrgRole::parse),
+            None if upload.user_id == user_id => Some(OrgRole::Owner),
+            None => None,
+        }))
+    }
+
+    /// Fails unless `user_id` may do `permission` with the upload.
+    pub async fn authorize_upload(&self, user_id: Uuid, upload_id: Uuid, permission: Permission) -> Result<(), AppError> {
+        let role = self
+            .upload_role(user_id, upload_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Upload not found".to_string()))?;
+        require(role, permission)
+    }
+
+    /// Like [`authorize_upload`](Self::authorize_upload) for the upload stored as
+    /// `{storage_id}_{original name}`, returning its id.
+    pub async fn authorize_stored(&self, user_id: Uuid, storage_id: &str, permission: Permission) -> Result<Uuid, AppError> {
+        let upload_id = self
+            .find_stored(storage_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Upload not found".to_string()))?;
+        self.authorize_upload(user_id, upload_id, permission).await?;
+        Ok(upload_id)
+    }
+
+    /// Looks up the upload row of an extracted project by its storage id (the `{uuid}` in
+    /// `{uuid}_{name}`). The expression matches the unique `idx_uploads_storage_id` index.
+    pub async fn find_stored(&self, storage_id: &str) -> Result<Option<Uuid>, AppError> {
+        let upload_id = sqlx::query_scalar!(
+            "SELECT id FROM uploads WHERE split_part(filename, '_', 1) = $1",
+            storage_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(upload_id)
+    }
+
+    /// Fails unless `user_id` is a member of the organization whose role allows `permission`.
+    /// Non-members are told the organization does not exist.
+    pub async fn authorize(&self, user_id: Uuid, org_id: Uuid, permission: Permission) -> Result<OrgRole, AppError> {
+        let role = sqlx::query_scalar!(
+            "SELECT role FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+            org_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .as_deref()
+        .and_then(OrgRole::parse)
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+        require(role, permission)?;
+        Ok(role)
+    }
+
+    /// Creates an organization owned by `user_id`.
+    pub async fn create(&self, user_id: Uuid, request: CreateOrganization) -> Result<Organization, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let org = sqlx::query!(
+            "INSERT INTO organizations (name, created_by) VALUES ($1, $2) RETURNING id, name, created_at",
+            request.name,
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)",
+            org.id,
+            user_id,
+            OrgRole::Owner.as_str()
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!("User {} created organization {}", user_id, org.id);
+        Ok(Organization { id: org.id, name: org.name, created_at: org.created_at, role: OrgRole::Owner })
+    }
+
+    /// Organizations `user_id` belongs to, by name.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Organization>, AppError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT o.id, o.name, o.created_at, m.role
+            FROM organizations o
+            JOIN organization_members m ON m.organization_id = o.id
+            WHERE m.user_id = $1
+            ORDER BY o.name, o.created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(Organization {
+                    id: row.id,
+                    name: row.name,
+                    created_at: row.created_at,
+                    role: OrgRole::parse(&row.role)?,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn get(&self, user_id: Uuid, org_id: Uuid) -> Result<Organization, AppError> {
+        let role = self.authorize(user_id, org_id, Permission::View).await?;
+        let org = sqlx::query!("SELECT id, name, created_at FROM organizations WHERE id = $1", org_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Organization { id: org.id, name: org.name, created_at: org.created_at, role })
+    }
+
+    pub async fn members(&self, user_id: Uuid, org_id: Uuid) -> Result<Vec<OrgMember>, AppError> {
+        self.authorize(user_id, org_id, Permission::View).await?;
+        let rows = sqlx::query!(
+            r#"
+            SELECT m.user_id, u.email, u.full_name, m.role, m.created_at
+            FROM organization_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.organization_id = $1
+            ORDER BY m.created_at
+            "#,
+            org_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(OrgMember {
+                    user_id: row.user_id,
+                    email: row.email,
+                    full_name: row.full_name,
+                    role: OrgRole::parse(&row.role)?,
+                    joined_at: row.created_at,
+                })
+            })
+            .collect())
+    }
+
+    /// Uploads owned by the organization, newest first.
+    pub async fn uploads(&self, user_id: Uuid, org_id: Uuid) -> Result<Vec<OrgUpload>, AppError> {
+        self.authorize(user_id, org_id, Permission::View).await?;
+        let uploads = sqlx::query_as!(
+            OrgUpload,
+            r#"
+            SELECT id, user_id AS uploaded_by, filename, original_filename, size, created_at
+            FROM uploads
+            WHERE organization_id = $1
+            ORDER BY created_at DESC
+            "#,
+            org_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(uploads)
+    }
+
+    /// Changes a member's role. Only owners may grant or take away ownership, and an
+    /// organization always keeps at least one owner.
+    pub async fn update_member_role(&self, actor_id: Uuid, org_id: Uuid, member_id: Uuid, role: OrgRole) -> Result<(), AppError> {
+        let actor_role = self.authorize(actor_id, org_id, Permission::ManageMembers).await?;
+        let mut tx = self.pool.begin().await?;
+        let current = member_roles_for_update(&mut tx, org_id, member_id).await?;
+        if (current.role == OrgRole::Owner || role == OrgRole::Owner) && actor_role != OrgRole::Owner {
+            return Err(AppError::Forbidden("Only owners can grant or revoke ownership".to_string()));
+        }
+        if current.role == OrgRole::Owner && role != OrgRole::Owner && current.owners == 1 {
+            return Err(AppError::BadRequest("An organization needs at least one owner".to_string()));
+        }
+
+        sqlx::query!(
+            "UPDATE organization_members SET role = $3 WHERE organization_id = $1 AND user_id = $2",
+            org_id,
+            member_id,
+            role.as_str()
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("User {} made {} a {} of organization {}", actor_id, member_id, role.as_str(), org_id);
+        Ok(())
+    }
+
+    /// Removes a member. Anyone may leave; removing others needs permission to manage
+    /// members, and only owners may remove owners.
+    pub async fn remove_member(&self, actor_id: Uuid, org_id: Uuid, member_id: Uuid) -> Result<(), AppError> {
+        let actor_role = if actor_id == member_id {
+            self.authorize(actor_id, org_id, Permission::View).await?
+        } else {
+            self.authorize(actor_id, org_id, Permission::ManageMembers).await?
+        };
+        let mut tx = self.pool.begin().await?;
+        let current = member_roles_for_update(&mut tx, org_id, member_id).await?;
+        if current.role == OrgRole::Owner && actor_role != OrgRole::Owner {
+            return Err(AppError::Forbidden("Only owners can remove an owner".to_string()));
+        }
+        if current.role == OrgRole::Owner && current.owners == 1 {
+            return Err(AppError::BadRequest("An organization needs at least one owner".to_string()));
+        }
+
+        sqlx::query!(
+            "DELETE FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+            org_id,
+            member_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("User {} removed {} from organization {}", actor_id, member_id, org_id);
+        Ok(())
+    }
+
+    /// Records an invitation and emails its signed token to the invitee.
+    pub async fn invite(&self, actor_id: Uuid, org_id: Uuid, request: InviteMember) -> Result<CreatedOrgInvite, AppError> {
+        let actor_role = self.authorize(actor_id, org_id, Permission::ManageMembers).await?;
+        if request.role == OrgRole::Owner && actor_role != OrgRole::Owner {
+            return Err(AppError::Forbidden("Only owners can invite owners".to_string()));
+        }
+
+        let email = request.email.trim().to_lowercase();
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO organization_invites (organization_id, email, role, invited_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, created_at, expires_at
+            "#,
+            org_id,
+            email,
+            request.role.as_str(),
+            actor_id,
+            Utc::now() + self.settings.invite_ttl
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let invite = OrgInvite {
+            id: row.id,
+            email,
+            role: request.role,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+        };
+        let token = self.sign_invite(org_id, &invite)?;
+
+        let org_name = sqlx::query_scalar!("SELECT name FROM organizations WHERE id = $1", org_id)
+            .fetch_one(&self.pool)
+            .await?;
+        self.mailer
+            .send(&Email {
+                to: invite.email.clone(),
+                subject: format!("You are invited to join {} on SummerIQ", org_name),
+                body: format!(
+                    "Hi,\n\nYou have been invited to join {} on SummerIQ as {}. Accept the invitation by \
+                     opening this link and signing in with this email address:\n\n{}/invites/accept?token={}\n\n\
+                     The invitation expires in {} days.",
+                    org_name,
+                    invite.role.as_str(),
+                    self.settings.public_url,
+                    token,
+                    self.settings.invite_ttl.num_days()
+                ),
+            })
+            .await?;
+
+        info!("User {} created invitation {} to organization {}", actor_id, invite.id, org_id);
+        Ok(CreatedOrgInvite { token, invite })
+    }
+
+    /// Invitations that can still be accepted, newest first.
+    pub async fn invites(&self, actor_id: Uuid, org_id: Uuid) -> Result<Vec<OrgInvite>, AppError> {
+        self.authorize(actor_id, org_id, Permission::ManageMembers).await?;
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, email, role, created_at, expires_at
+            FROM organization_invites
+            WHERE organization_id = $1 AND accepted_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY created_at DESC
+            "#,
+            org_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(OrgInvite {
+                    id: row.id,
+                    email: row.email,
+                    role: OrgRole::parse(&row.role)?,
+                    created_at: row.created_at,
+                    expires_at: row.expires_at,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn revoke_invite(&self, actor_id: Uuid, org_id: Uuid, invite_id: Uuid) -> Result<(), AppError> {
+        self.authorize(actor_id, org_id, Permission::ManageMembers).await?;
+        let result = sqlx::query!(
+            r#"
+            UPDATE organization_invites SET revoked_at = NOW()
+            WHERE id = $1 AND organization_id = $2 AND accepted_at IS NULL AND revoked_at IS NULL
+            "#,
+            invite_id,
+            org_id
+        )
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("Invitation not found".to_string()));
+        }
+        info!("User {} revoked invitation {}", actor_id, invite_id);
+        Ok(())
+    }
+
+    /// Makes `user_id` a member with the invited role. The invitation must be addressed to
+    /// the user's email. Members who are already in the organization keep their role.
+    pub async fn accept_invite(&self, user_id: Uuid, token: &str) -> Result<Organization, AppError> {
+        let invalid = || AppError::BadRequest("Invalid or expired invitation".to_string());
+        let mut validation = Validation::default();
+        validation.set_audience(&[INVITE_AUDIENCE]);
+        let claims = decode::<InviteClaims>(
+            token,
+            &DecodingKey::from_secret(self.settings.jwt_secret.as_bytes()),
+            &validation,
+        )
+        .map_err(|_| invalid())?
+        .claims;
+        let invite_id = Uuid::parse_str(&claims.sub).map_err(|_| invalid())?;
+
+        let mut tx = self.pool.begin().await?;
+        let invite = sqlx::query!(
+            r#"
+            SELECT organization_id, email, role
+            FROM organization_invites
+            WHERE id = $1 AND accepted_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            FOR UPDATE
+            "#,
+            invite_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|invite| invite.organization_id.to_string() == claims.org)
+        .ok_or_else(invalid)?;
+        let email = sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if !email.eq_ignore_ascii_case(&invite.email) {
+            return Err(AppError::Forbidden("This invitation was sent to a different email address".to_string()));
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)
+            ON CONFLICT (organization_id, user_id) DO NOTHING
+            "#,
+            invite.organization_id,
+            user_id,
+            invite.role
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("UPDATE organization_invites SET accepted_at = NOW() WHERE id = $1", invite_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("User {} accepted invitation {}", user_id, invite_id);
+        self.get(user_id, invite.organization_id).await
+    }
+
+    fn sign_invite(&self, org_id: Uuid, invite: &OrgInvite) -> Result<String, AppError> {
+        let claims = InviteClaims {
+            sub: invite.id.to_string(),
+            org: org_id.to_string(),
+            aud: INVITE_AUDIENCE.to_string(),
+            exp: invite.expires_at.timestamp() as usize,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.settings.jwt_secret.as_bytes()),
+        )
+        .map_err(|e| AppError::InternalServerError(e.to_string()))
+    }
+}
+
+fn require(role: OrgRole, permission: Permission) -> Result<(), AppError> {
+    if role.allows(permission) {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden(format!(
+            "The {} role does not allow you to {}",
+            role.as_str(),
+            permission.as_str()
+        )))
+    }
+}
+
+struct MemberRole {
+    role: OrgRole,
+    /// Owners of the organization, including this member.
+    owners: usize,
+}
+
+/// Locks the organization's memberships for a change to `member_id`, so two concurrent
+/// changes cannot remove the last owner.
+async fn member_roles_for_update(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    org_id: Uuid,
+    member_id: Uuid,
+) -> Result<MemberRole, AppError> {
+    let members = sqlx::query!(
+        "SELECT user_id, role FROM organization_members WHERE organization_id = $1 FOR UPDATE",
+        org_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    let role = members
+        .iter()
+        .find(|member| member.user_id == member_id)
+        .and_then(|member| OrgRole::parse(&member.role))
+        .ok_or_else(|| AppError::NotFound("Member not found".to_string()))?;
+    let owners = members.iter().filter(|member| member.role == OrgRole::Owner.as_str()).count();
+    Ok(MemberRole { role, owners })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_grant_increasing_permissions() {
+        use Permission::*;
+        let allowed = |role: OrgRole| {
+            [View, Analyze, Upload, Share, Delete, ManageMembers]
+                .into_iter()
+                .filter(|permission| role.allows(*permission))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(allowed(OrgRole::Viewer), vec![View]);
+        assert_eq!(allowed(OrgRole::Member), vec![View, Analyze, Upload, Share]);
+        assert_eq!(allowed(OrgRole::Admin), vec![View, Analyze, Upload, Share, Delete, ManageMembers]);
+        assert_eq!(allowed(OrgRole::Owner), allowed(OrgRole::Admin));
+    }
+}
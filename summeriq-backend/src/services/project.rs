@@ -0,0 +1,444 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use sqlx::PgPool;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use tokio::io::AsyncReadExt;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::project::{AddProjectVersion, CreateProject, Project, ProjectVersion, ProjectWithVersions};
+use crate::services::ai::AIService;
+use crate::services::prompts::ChangelogPrompt;
+use crate::services::providers::ServedBy;
+use crate::services::file_types::FileMetadata;
+use crate::services::storage::FileNode;
+use crate::services::StorageService;
+
+// Upper bound on the amount of diff text sent to the AI when building a changelog
+const CHANGELOG_DIFF_BUDGET: usize = 12_000;
+// Files larger than this are compared by digest instead of being read and diffed
+const MAX_DIFF_FILE_BYTES: u64 = 512 * 1024;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub change: ChangeKind,
+    pub binary: bool,
+    /// Larger than the diff limit, so only compared by content.
+    pub too_large: bool,
+    pub additions: usize,
+    pub deletions: usize,
+    /// Unified diff of the file, `None` for binary and too large files.
+    pub diff: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectDiff {
+    pub project_id: Uuid,
+    pub from: i32,
+    pub to: i32,
+    pub added: Vec<FileDiff>,
+    pub removed: Vec<FileDiff>,
+    pub modified: Vec<FileDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectChangelog {
+    pub project_id: Uuid,
+    pub from: i32,
+    pub to: i32,
+    pub files_added: usize,
+    pub files_removed: usize,
+    pub files_modified: usize,
+    pub summary: String,
+    pub served_by: ServedBy,
+    pub prompt_version: String,
+}
+
+/// A file of a version as a diff sees it.
+enum VersionFile {
+    /// Text small enough to diff line by line.
+    Text(Vec<u8>),
+    /// Binary or too large to diff; compared by the SHA-256 of its content.
+    Opaque { digest: Vec<u8>, binary: bool },
+}
+
+impl VersionFile {
+    const EMPTY: VersionFile = VersionFile::Text(Vec::new());
+
+    fn same_content(&self, other: &VersionFile) -> bool {
+        match (self, other) {
+            (VersionFile::Text(a), VersionFile::Text(b)) => a == b,
+            (VersionFile::Opaque { digest: a, .. }, VersionFile::Opaque { digest: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProjectService {
+    pool: PgPool,
+    storage_service: StorageService,
+}
+
+impl ProjectService {
+    pub fn new(pool: PgPool, storage_service: StorageService) -> Self {
+        Self { pool, storage_service }
+    }
+
+    pub async fn create_project(&self, user_id: Uuid, data: CreateProject) -> Result<Project, AppError> {
+        let project = sqlx::query_as!(
+            Project,
+            r#"
+            INSERT INTO projects (user_id, name, description)
+            VALUES ($1, $2, $3)
+            RETURNING id, user_id, name, description, created_at, updated_at
+            "#,
+            user_id,
+            data.name,
+            data.description
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        info!("Project created: {} ({})", project.name, project.id);
+        Ok(project)
+    }
+
+    pub async fn list_projects(&self, user_id: Uuid) -> Result<Vec<Project>, AppError> {
+        let projects = sqlx::query_as!(
+            Project,
+            r#"
+            SELECT id, user_id, name, description, created_at, updated_at
+            FROM projects
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(projects)
+    }
+
+    pub async fn get_project(&self, user_id: Uuid, project_id: Uuid) -> Result<ProjectWithVersions, AppError> {
+        let project = self.find_project(user_id, project_id).await?;
+        let versions = sqlx::query_as!(
+            ProjectVersion,
+            r#"
+            SELECT v.id, v.project_id, v.upload_id, v.version, v.label,
+                   u.filename, u.original_filename, v.created_at
+            FROM project_versions v
+            JOIN uploads u ON u.id = v.upload_id
+            WHERE v.project_id = $1
+            ORDER BY v.version
+            "#,
+            project.id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ProjectWithVersions { project, versions })
+    }
+
+    /// Appends an upload owned by the user as the next version of the project.
+    pub async fn add_version(&self, user_id: Uuid, project_id: Uuid, data: AddProjectVersion) -> Result<ProjectVersion, AppError> {
+        let project = self.find_project(user_id, project_id).await?;
+
+        let mut tx = self.pool.begin().await?;
+        // Versions are numbered one at a time per project
+        sqlx::query!("SELECT id FROM projects WHERE id = $1 FOR UPDATE", project.id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let upload = sqlx::query!(
+            r#"SELECT id, filename, original_filename FROM uploads WHERE id = $1 AND user_id = $2"#,
+            data.upload_id,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Upload not found".to_string()))?;
+
+        let rec = sqlx::query!(
+            r#"
+            INSERT INTO project_versions (project_id, upload_id, version, label)
+            VALUES ($1, $2, (SELECT COALESCE(MAX(version), 0) + 1 FROM project_versions WHERE project_id = $1), $3)
+            RETURNING id, version, created_at
+            "#,
+            project.id,
+            upload.id,
+            data.label
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.constraint() == Some("project_versions_project_id_upload_id_key") => {
+                AppError::BadRequest("Upload is already a version of this project".to_string())
+            }
+            e => e.into(),
+        })?;
+        tx.commit().await?;
+
+        info!("Added upload {} as version {} of project {}", upload.id, rec.version, project.id);
+        Ok(ProjectVersion {
+            id: rec.id,
+            project_id: project.id,
+            upload_id: upload.id,
+            version: rec.version,
+            label: data.label,
+            filename: upload.filename,
+            original_filename: upload.original_filename,
+            created_at: rec.created_at,
+        })
+    }
+
+    /// A version of the project, the latest when `number` is `None`.
+    pub async fn find_version(&self, user_id: Uuid, project_id: Uuid, number: Option<i32>) -> Result<ProjectVersion, AppError> {
+        let project = self.get_project(user_id, project_id).await?;
+        let version = match number {
+            Some(number) => project.versions.into_iter().find(|v| v.version == number),
+            None => project.versions.into_iter().last(),
+        };
+        version.ok_or_else(|| match number {
+            Some(number) => AppError::NotFound(format!("Version {} not found", number)),
+            None => AppError::NotFound("Project has no versions".to_string()),
+        })
+    }
+
+    /// Computes added, removed and modified files between two versions of a project.
+    pub async fn diff_versions(&self, user_id: Uuid, project_id: Uuid, from: i32, to: i32) -> Result<ProjectDiff, AppError> {
+        let project = self.get_project(user_id, project_id).await?;
+        let find_version = |number: i32| {
+            project.versions
+                .iter()
+                .find(|v| v.version == number)
+                .ok_or_else(|| AppError::NotFound(format!("Version {} not found", number)))
+        };
+        let from_version = find_version(from)?;
+        let to_version = find_version(to)?;
+
+        let old_files = self.collect_files(&from_version.extracted_dir()).await?;
+        let new_files = self.collect_files(&to_version.extracted_dir()).await?;
+
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut modified = Vec::new();
+
+        for (path, new_file) in &new_files {
+            match old_files.get(path) {
+                None => added.push(diff_file(path, ChangeKind::Added, &VersionFile::EMPTY, new_file)),
+                Some(old_file) if !old_file.same_content(new_file) => {
+                    modified.push(diff_file(path, ChangeKind::Modified, old_file, new_file))
+                }
+                Some(_) => {}
+            }
+        }
+        for (path, old_file) in &old_files {
+            if !new_files.contains_key(path) {
+                removed.push(diff_file(path, ChangeKind::Removed, old_file, &VersionFile::EMPTY));
+            }
+        }
+
+        info!(
+            "Diff of project {} v{}..v{}: {} added, {} removed, {} modified",
+            project_id, from, to, added.len(), removed.len(), modified.len()
+        );
+        Ok(ProjectDiff {
+            project_id,
+            from,
+            to,
+            added,
+            removed,
+            modified,
+        })
+    }
+
+    /// Asks the AI for a human readable summary of what changed between two versions.
+    pub async fn changelog(&self, ai_service: &AIService, user_id: Uuid, project_id: Uuid, from: i32, to: i32) -> Result<ProjectChangelog, AppError> {
+        let diff = self.diff_versions(user_id, project_id, from, to).await?;
+
+        let mut changes = String::new();
+        let mut omitted_count = 0;
+        for file in diff.added.iter().chain(&diff.removed).chain(&diff.modified) {
+            let header = format!(
+                "### {} ({:?}, +{} -{})\n",
+                file.path, file.change, file.additions, file.deletions
+            );
+            let body = match &file.diff {
+                Some(diff) if file.change == ChangeKind::Modified => format!("```diff\n{}\n```\n\n", diff),
+                _ => "\n".to_string(),
+            };
+            if changes.len() + header.len() + body.len() < CHANGELOG_DIFF_BUDGET {
+                changes.push_str(&header);
+                changes.push_str(&body);
+            } else if changes.len() + header.len() < CHANGELOG_DIFF_BUDGET {
+                changes.push_str(&header);
+                changes.push('\n');
+            } else {
+                omitted_count += 1;
+            }
+        }
+        if omitted_count > 0 {
+            changes.push_str(&format!("\n--- Some changes omitted due to size limits ({} omitted). ---\n", omitted_count));
+        }
+
+        let prompt = ai_service.prompts().render(&ChangelogPrompt {
+            from,
+            to,
+            changes: &changes,
+        })?;
+        let summary = ai_service.analyze_text(&prompt).await?;
+
+        Ok(ProjectChangelog {
+            project_id,
+            from,
+            to,
+            files_added: diff.added.len(),
+            files_removed: diff.removed.len(),
+            files_modified: diff.modified.len(),
+            summary: summary.value,
+            served_by: summary.served_by,
+            prompt_version: summary.prompt_version,
+        })
+    }
+
+    async fn find_project(&self, user_id: Uuid, project_id: Uuid) -> Result<Project, AppError> {
+        sqlx::query_as!(
+            Project,
+            r#"
+            SELECT id, user_id, name, description, created_at, updated_at
+            FROM projects
+            WHERE id = $1 AND user_id = $2
+            "#,
+            project_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Project not found".to_string()))
+    }
+
+    /// Every file of an extracted upload, keyed by its path relative to the project root.
+    /// Only small text files are read into memory; the others are compared by digest.
+    async fn collect_files(&self, extracted_dir: &str) -> Result<BTreeMap<String, VersionFile>, AppError> {
+        let mut nodes = self.storage_service.list_files(extracted_dir).await?;
+        let mut prefix = extracted_dir.to_string();
+
+        // Archives usually wrap everything in a single folder whose name changes between
+        // versions (e.g. `app-1.0/` vs `app-1.1/`), so compare relative to its contents.
+        if nodes.len() == 1 && nodes[0].is_dir {
+            let root = nodes.remove(0);
+            prefix = format!("{}/{}", prefix, root.path);
+            nodes = root.children.unwrap_or_default();
+        }
+
+        let mut paths = Vec::new();
+        collect_paths(&nodes, "", &mut paths);
+
+        let mut files = BTreeMap::new();
+        for (path, metadata) in paths {
+            let stored_path = format!("{}/{}", prefix, path);
+            let binary = metadata.as_ref().is_some_and(|metadata| metadata.binary);
+            let too_large = metadata.as_ref().is_some_and(|metadata| metadata.size > MAX_DIFF_FILE_BYTES);
+            let file = if binary || too_large {
+                VersionFile::Opaque { digest: self.digest(&stored_path).await?, binary }
+            } else {
+                VersionFile::Text(self.storage_service.read_file(&stored_path).await?)
+            };
+            files.insert(path, file);
+        }
+        Ok(files)
+    }
+
+    /// SHA-256 of a stored file, read a block at a time.
+    async fn digest(&self, stored_path: &str) -> Result<Vec<u8>, AppError> {
+        let mut file = tokio::fs::File::open(self.storage_service.upload_dir().join(stored_path)).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hasher.finalize().to_vec())
+    }
+}
+
+fn collect_paths(nodes: &[FileNode], parent: &str, out: &mut Vec<(String, Option<FileMetadata>)>) {
+    for node in nodes {
+        let path = if parent.is_empty() {
+            node.name.clone()
+        } else {
+            format!("{}/{}", parent, node.name)
+        };
+        if node.is_dir {
+            if let Some(children) = &node.children {
+                collect_paths(children, &path, out);
+            }
+        } else {
+            out.push((path, node.metadata.clone()));
+        }
+    }
+}
+
+fn diff_file(path: &str, change: ChangeKind, old: &VersionFile, new: &VersionFile) -> FileDiff {
+    let opaque = |binary: bool| FileDiff {
+        path: path.to_string(),
+        change,
+        binary,
+        too_large: !binary,
+        additions: 0,
+        deletions: 0,
+        diff: None,
+    };
+    let (old, new) = match (old, new) {
+        (VersionFile::Text(old), VersionFile::Text(new)) => (old, new),
+        (VersionFile::Opaque { binary, .. }, _) | (_, VersionFile::Opaque { binary, .. }) => return opaque(*binary),
+    };
+    let (old_text, new_text) = match (std::str::from_utf8(old), std::str::from_utf8(new)) {
+        (Ok(old_text), Ok(new_text)) => (old_text, new_text),
+        _ => return opaque(true),
+    };
+
+    let text_diff = TextDiff::from_lines(old_text, new_text);
+    let mut additions = 0;
+    let mut deletions = 0;
+    for change in text_diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => additions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let old_header = if change == ChangeKind::Added { "/dev/null".to_string() } else { format!("a/{}", path) };
+    let new_header = if change == ChangeKind::Removed { "/dev/null".to_string() } else { format!("b/{}", path) };
+    let diff = text_diff
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_header, &new_header)
+        .to_string();
+
+    FileDiff {
+        path: path.to_string(),
+        change,
+        binary: false,
+        too_large: false,
+        additions,
+        deletions,
+        diff: Some(diff),
+    }
+}
@@ -0,0 +1,267 @@
+use minijinja::{AutoEscape, Environment, UndefinedBehavior};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use crate::error::AppError;
+
+// Template files are named `<name>.v<version>.j2`
+const TEMPLATE_EXTENSION: &str = ".j2";
+
+/// Variables of a prompt template; the struct's fields are exactly what the template can use.
+pub trait PromptTemplate: Serialize {
+    const NAME: &'static str;
+}
+
+/// A rendered prompt together with the template version it came from, e.g. `chat@v2`.
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub text: String,
+    pub version: String,
+}
+
+/// Named, versioned prompt templates loaded from a directory at startup. The highest version
+/// of each template is the one in use; older files can stay around for comparison.
+pub struct PromptRegistry {
+    env: Environment<'static>,
+    active: HashMap<String, u32>,
+}
+
+impl PromptRegistry {
+    pub fn load(dir: &str) -> Result<Self, AppError> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_auto_escape_callback(|_| AutoEscape::None);
+
+        let entries = fs::read_dir(dir)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to read prompt directory {}: {}", dir, e)))?;
+        let mut active = HashMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| AppError::InternalServerError(format!("Failed to read prompt directory {}: {}", dir, e)))?
+                .path();
+            let Some((name, version)) = path.file_name().and_then(|f| f.to_str()).and_then(parse_template_file_name) else {
+                continue;
+            };
+            let source = fs::read_to_string(&path).map_err(|e| {
+                AppError::InternalServerError(format!("Failed to read prompt template {}: {}", path.display(), e))
+            })?;
+            env.add_template_owned(version_id(&name, version), source).map_err(|e| {
+                AppError::InternalServerError(format!("Invalid prompt template {}: {}", path.display(), e))
+            })?;
+            let current = active.entry(name).or_insert(version);
+            *current = (*current).max(version);
+        }
+
+        for name in REQUIRED_PROMPTS {
+            if !active.contains_key(*name) {
+                return Err(AppError::InternalServerError(format!(
+                    "Missing prompt template `{}` in {}",
+                    name,
+                    Path::new(dir).display()
+                )));
+            }
+        }
+        info!("Loaded {} prompt templates from {}", active.len(), dir);
+        Ok(Self { env, active })
+    }
+
+    /// Version id of the template currently used for `P`, for cache keys.
+    pub fn version<P: PromptTemplate>(&self) -> String {
+        version_id(P::NAME, self.active[P::NAME])
+    }
+
+    pub fn render<P: PromptTemplate>(&self, vars: &P) -> Result<RenderedPrompt, AppError> {
+        let version = self.version::<P>();
+        let text = self
+            .env
+            .get_template(&version)
+            .and_then(|template| template.render(vars))
+            .map_err(|e| AppError::InternalServerError(format!("Failed to render prompt {}: {}", version, e)))?;
+        Ok(RenderedPrompt {
+            text: text.trim().to_string(),
+            version,
+        })
+    }
+}
+
+fn version_id(name: &str, version: u32) -> String {
+    format!("{}@v{}", name, version)
+}
+
+/// Splits `chat.v2.j2` into `("chat", 2)`.
+fn parse_template_file_name(file_name: &str) -> Option<(String, u32)> {
+    let stem = file_name.strip_suffix(TEMPLATE_EXTENSION)?;
+    let (name, version) = stem.rsplit_once(".v")?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), version.parse().ok()?))
+}
+
+// Every template the code renders; loading fails early when one is missing
+const REQUIRED_PROMPTS: &[&str] = &[
+    ChatPrompt::NAME,
+    FileInsightsPrompt::NAME,
+    FilePurposeMergePrompt::NAME,
+    DocArchitecturePrompt::NAME,
+    DocFileSummaryPrompt::NAME,
+    DocFinalPrompt::NAME,
+    ChangelogPrompt::NAME,
+    CodeReviewPrompt::NAME,
+];
+
+#[derive(Serialize)]
+pub struct ChatPrompt<'a> {
+    pub message: &'a str,
+    pub project_name: Option<&'a str>,
+    pub selected_file_name: Option<&'a str>,
+    pub selected_file_path: Option<&'a str>,
+}
+
+impl PromptTemplate for ChatPrompt<'_> {
+    const NAME: &'static str = "chat";
+}
+
+/// Position of a chunk when a file is analyzed in parts.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ChunkPart {
+    pub index: usize,
+    pub total: usize,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Serialize)]
+pub struct FileInsightsPrompt<'a> {
+    pub file_path: &'a str,
+    pub content: &'a str,
+    pub part: Option<ChunkPart>,
+}
+
+impl PromptTemplate for FileInsightsPrompt<'_> {
+    const NAME: &'static str = "file_insights";
+}
+
+#[derive(Serialize)]
+pub struct FilePurposeMergePrompt<'a> {
+    pub file_path: &'a str,
+    pub purposes: &'a [String],
+}
+
+impl PromptTemplate for FilePurposeMergePrompt<'_> {
+    const NAME: &'static str = "file_purpose_merge";
+}
+
+#[derive(Serialize)]
+pub struct DocArchitecturePrompt<'a> {
+    pub structure: &'a str,
+}
+
+impl PromptTemplate for DocArchitecturePrompt<'_> {
+    const NAME: &'static str = "doc_architecture";
+}
+
+#[derive(Serialize)]
+pub struct DocFileSummaryPrompt<'a> {
+    pub path: &'a str,
+    pub content: &'a str,
+}
+
+impl PromptTemplate for DocFileSummaryPrompt<'_> {
+    const NAME: &'static str = "doc_file_summary";
+}
+
+#[derive(Serialize)]
+pub struct DocFinalPrompt<'a> {
+    pub summaries: &'a str,
+}
+
+impl PromptTemplate for DocFinalPrompt<'_> {
+    const NAME: &'static str = "doc_final";
+}
+
+#[derive(Serialize)]
+pub struct ChangelogPrompt<'a> {
+    pub from: i32,
+    pub to: i32,
+    pub changes: &'a str,
+}
+
+impl PromptTemplate for ChangelogPrompt<'_> {
+    const NAME: &'static str = "changelog";
+}
+
+#[derive(Serialize)]
+pub struct CodeReviewPrompt<'a> {
+    pub path: &'a str,
+    pub numbered: &'a str,
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+impl PromptTemplate for CodeReviewPrompt<'_> {
+    const NAME: &'static str = "code_review";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> PromptRegistry {
+        PromptRegistry::load(concat!(env!("CARGO_MANIFEST_DIR"), "/prompts")).unwrap()
+    }
+
+    #[test]
+    fn parses_template_file_names() {
+        assert_eq!(parse_template_file_name("chat.v2.j2"), Some(("chat".to_string(), 2)));
+        assert_eq!(parse_template_file_name("doc_final.v10.j2"), Some(("doc_final".to_string(), 10)));
+        assert_eq!(parse_template_file_name("chat.j2"), None);
+        assert_eq!(parse_template_file_name("README.md"), None);
+    }
+
+    #[test]
+    fn renders_chat_variants() {
+        let registry = registry();
+        let with_file = registry
+            .render(&ChatPrompt {
+                message: "What does this do?",
+                project_name: Some("demo"),
+                selected_file_name: Some("main.rs"),
+                selected_file_path: Some("src/main.rs"),
+            })
+            .unwrap();
+        assert!(with_file.text.starts_with("Context: You are an AI assistant helping with the 'demo' project."));
+        assert!(with_file.text.contains("located at 'src/main.rs'"));
+        assert_eq!(with_file.version, registry.version::<ChatPrompt>());
+
+        let without_project = registry
+            .render(&ChatPrompt {
+                message: "Hi",
+                project_name: None,
+                selected_file_name: None,
+                selected_file_path: None,
+            })
+            .unwrap();
+        assert!(without_project.text.starts_with("User question: Hi"));
+    }
+
+    #[test]
+    fn renders_optional_chunk_part() {
+        let registry = registry();
+        let whole = registry
+            .render(&FileInsightsPrompt { file_path: "a.rs", content: "fn main() {}", part: None })
+            .unwrap();
+        assert!(!whole.text.contains("This is part"));
+
+        let part = ChunkPart { index: 2, total: 3, start_line: 40, end_line: 80 };
+        let chunk = registry
+            .render(&FileInsightsPrompt { file_path: "a.rs", content: "fn main() {}", part: Some(part) })
+            .unwrap();
+        assert!(chunk.text.contains("This is part 2 of 3 of the file (lines 40-80)."));
+    }
+}
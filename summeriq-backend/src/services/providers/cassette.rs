@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use super::{AiProvider, ChatRequest, ProviderError, ProviderErrorKind, ProviderResponse};
+use crate::error::AppError;
+
+/// How a [`CassetteProvider`] treats its cassette file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Forward requests to the real provider and save every interaction.
+    Record,
+    /// Answer only from the cassette, without touching the network.
+    Replay,
+}
+
+impl CassetteMode {
+    /// Parses `AI_CASSETTE_MODE`; `off` (or an empty value) disables cassettes.
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "off" => Ok(None),
+            "record" => Ok(Some(CassetteMode::Record)),
+            "replay" => Ok(Some(CassetteMode::Replay)),
+            other => Err(format!("unknown cassette mode '{}', expected off, record or replay", other)),
+        }
+    }
+}
+
+/// The parts of a request that decide its answer. The caller is left out so a cassette
+/// recorded by one user replays for any other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    system_prompt: String,
+    prompt: String,
+    json_mode: bool,
+    max_tokens: u32,
+    temperature: f32,
+}
+
+impl RecordedRequest {
+    fn from_request(request: &ChatRequest) -> Self {
+        Self {
+            system_prompt: request.system_prompt.clone(),
+            prompt: request.prompt.clone(),
+            json_mode: request.json_mode,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+        }
+    }
+
+    fn key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(self).expect("request serializes"));
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedError {
+    kind: ProviderErrorKind,
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Response(ProviderResponse),
+    Error(RecordedError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    key: String,
+    request: RecordedRequest,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cassette {
+    provider: String,
+    model: String,
+    supports_json_mode: bool,
+    interactions: Vec<Interaction>,
+}
+
+enum Source {
+    Recording {
+        inner: Arc<dyn AiProvider>,
+        cassette: Mutex<Cassette>,
+    },
+    /// Interactions by request key, in recorded order, and how many of each were served.
+    Replaying {
+        interactions: HashMap<String, Vec<Outcome>>,
+        served: Mutex<HashMap<String, usize>>,
+    },
+}
+
+/// Records AI interactions to a JSON fixture file, or replays them from one, so code that
+/// talks to a provider can be tested deterministically without a network or API key.
+///
+/// Requests are matched on their prompts and generation settings. A request made several
+/// times gets the recorded answers in order, and the last one once those run out.
+pub struct CassetteProvider {
+    name: String,
+    model: String,
+    supports_json_mode: bool,
+    path: PathBuf,
+    source: Source,
+}
+
+impl CassetteProvider {
+    /// Wraps `inner`, saving every interaction to `path`. An existing cassette is replaced.
+    pub fn record(inner: Arc<dyn AiProvider>, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        info!("Recording AI interactions of {} to {}", inner.name(), path.display());
+        let cassette = Cassette {
+            provider: inner.name().to_string(),
+            model: inner.model().to_string(),
+            supports_json_mode: inner.supports_json_mode(),
+            interactions: Vec::new(),
+        };
+        Self {
+            name: cassette.provider.clone(),
+            model: cassette.model.clone(),
+            supports_json_mode: cassette.supports_json_mode,
+            path,
+            source: Source::Recording { inner, cassette: Mutex::new(cassette) },
+        }
+    }
+
+    /// Loads the cassette at `path` for replay. The provider keeps the name and model it
+    /// was recorded with, so responses are attributed as they were originally.
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self, AppError> {
+        let path = path.into();
+        let data = std::fs::read_to_string(&path).map_err(|e| {
+            AppError::InternalServerError(format!("Failed to read cassette {}: {}", path.display(), e))
+        })?;
+        let cassette: Cassette = serde_json::from_str(&data).map_err(|e| {
+            AppError::InternalServerError(format!("Invalid cassette {}: {}", path.display(), e))
+        })?;
+        info!("Replaying {} AI interactions from {}", cassette.interactions.len(), path.display());
+
+        let mut interactions: HashMap<String, Vec<Outcome>> = HashMap::new();
+        for interaction in cassette.interactions {
+            interactions.entry(interaction.key).or_default().push(interaction.outcome);
+        }
+        Ok(Self {
+            name: cassette.provider,
+            model: cassette.model,
+            supports_json_mode: cassette.supports_json_mode,
+            path,
+            source: Source::Replaying { interactions, served: Mutex::new(HashMap::new()) },
+        })
+    }
+
+    fn save(path: &Path, cassette: &Cassette) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_string_pretty(cassette).expect("cassette serializes");
+        // Write next to the target and rename, so an interrupted run never leaves half a file
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+#[async_trait]
+impl AiProvider for CassetteProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_json_mode(&self) -> bool {
+        self.supports_json_mode
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<ProviderResponse, ProviderError> {
+        let recorded = RecordedRequest::from_request(request);
+        let key = recorded.key();
+
+        match &self.source {
+            Source::Recording { inner, cassette } => {
+                let result = inner.complete(request).await;
+                let outcome = match &result {
+                    Ok(response) => Outcome::Response(response.clone()),
+                    Err(error) => Outcome::Error(RecordedError { kind: error.kind, message: error.message.clone() }),
+                };
+                let mut cassette = cassette.lock().unwrap();
+                cassette.interactions.push(Interaction { key, request: recorded, outcome });
+                if let Err(e) = Self::save(&self.path, &cassette) {
+                    warn!("Failed to save cassette {}: {}", self.path.display(), e);
+                }
+                result
+            }
+            Source::Replaying { interactions, served } => {
+                let outcomes = interactions.get(&key).ok_or_else(|| {
+                    ProviderError::new(
+                        ProviderErrorKind::InvalidRequest,
+                        format!(
+                            "Cassette {} has no interaction for request {}; re-record it with AI_CASSETTE_MODE=record",
+                            self.path.display(),
+                            key
+                        ),
+                    )
+                })?;
+                let index = {
+                    let mut served = served.lock().unwrap();
+                    let count = served.entry(key).or_insert(0);
+                    *count += 1;
+                    (*count - 1).min(outcomes.len() - 1)
+                };
+                match &outcomes[index] {
+                    Outcome::Response(response) => Ok(response.clone()),
+                    Outcome::Error(error) => Err(ProviderError::new(error.kind, error.message.clone())),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MockProvider;
+    use super::*;
+
+    fn request(prompt: &str, caller: &str) -> ChatRequest {
+        ChatRequest {
+            system_prompt: "You are a code analyst.".to_string(),
+            prompt: prompt.to_string(),
+            json_mode: true,
+            max_tokens: 1024,
+            temperature: 0.2,
+            caller: caller.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_interactions_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("groq.json");
+        let inner = Arc::new(
+            MockProvider::new("groq")
+                .respond(r#"{"purpose": "first"}"#)
+                .fail_with(ProviderErrorKind::TooLarge)
+                .respond(r#"{"purpose": "other"}"#),
+        );
+
+        let recorder = CassetteProvider::record(inner.clone(), &path);
+        recorder.complete(&request("Explain main.rs", "user:a")).await.unwrap();
+        recorder.complete(&request("Explain main.rs", "user:a")).await.unwrap_err();
+        recorder.complete(&request("Explain lib.rs", "user:a")).await.unwrap();
+        assert_eq!(inner.calls(), 3);
+
+        let player = CassetteProvider::replay(&path).unwrap();
+        assert_eq!((player.name(), player.model()), ("groq", "groq-model"));
+        // The caller is not part of the match, so another user gets the same answers
+        let first = player.complete(&request("Explain main.rs", "user:b")).await.unwrap();
+        assert_eq!(first.content, r#"{"purpose": "first"}"#);
+        let second = player.complete(&request("Explain main.rs", "user:b")).await.unwrap_err();
+        assert_eq!(second.kind, ProviderErrorKind::TooLarge);
+        let other = player.complete(&request("Explain lib.rs", "user:b")).await.unwrap();
+        assert_eq!(other.content, r#"{"purpose": "other"}"#);
+        // Once a request's recordings run out its last outcome keeps being served
+        let again = player.complete(&request("Explain lib.rs", "user:b")).await.unwrap();
+        assert_eq!(again.content, r#"{"purpose": "other"}"#);
+    }
+
+    #[tokio::test]
+    async fn unrecorded_request_fails_without_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("groq.json");
+        let recorder = CassetteProvider::record(Arc::new(MockProvider::new("groq").otherwise("{}")), &path);
+        recorder.complete(&request("Explain main.rs", "user:a")).await.unwrap();
+
+        let player = CassetteProvider::replay(&path).unwrap();
+        let error = player.complete(&request("Explain util.rs", "user:a")).await.unwrap_err();
+        assert_eq!(error.kind, ProviderErrorKind::InvalidRequest);
+        assert!(error.message.contains("re-record"));
+    }
+}
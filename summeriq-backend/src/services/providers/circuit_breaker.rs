@@ -0,0 +1,125 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerSettings {
+    /// Consecutive failures after which the circuit opens.
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects requests before a probe is let through.
+    pub open_for: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// A single probe request is in flight; its outcome closes or re-opens the circuit.
+    /// A probe that never reports back (e.g. its request was cancelled) is replaced once
+    /// another open period has passed.
+    HalfOpen { since: Instant },
+}
+
+pub struct CircuitBreaker {
+    settings: BreakerSettings,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: BreakerSettings) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns whether a request may be sent now. Once the open period has elapsed, exactly
+    /// one caller is let through as a half-open probe.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                info!("Circuit half-open, sending probe request");
+                *state = BreakerState::HalfOpen { since: Instant::now() };
+                true
+            }
+            BreakerState::HalfOpen { since } if since.elapsed() >= self.settings.open_for => {
+                info!("Previous probe never completed, sending another");
+                *state = BreakerState::HalfOpen { since: Instant::now() };
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(*state, BreakerState::HalfOpen { .. }) {
+            info!("Probe succeeded, closing circuit");
+        }
+        *state = BreakerState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::HalfOpen { .. } | BreakerState::Open { .. } => self.settings.failure_threshold,
+        };
+        *state = if failures >= self.settings.failure_threshold {
+            warn!("Opening circuit for {:?} after {} failures", self.settings.open_for, failures);
+            BreakerState::Open {
+                until: Instant::now() + self.settings.open_for,
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_threshold_and_probes_once() {
+        let breaker = CircuitBreaker::new(BreakerSettings {
+            failure_threshold: 2,
+            open_for: Duration::from_millis(50),
+        });
+
+        breaker.record_failure();
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+
+        // Once the open period has elapsed: one probe, then closed until it reports back
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        breaker.record_success();
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn failed_probe_reopens_circuit() {
+        let breaker = CircuitBreaker::new(BreakerSettings {
+            failure_threshold: 1,
+            open_for: Duration::from_millis(50),
+        });
+
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.try_acquire(), "no probe after the open period");
+        breaker.record_failure();
+        assert!(!breaker.try_acquire(), "circuit closed after a failed probe");
+
+        // The failed probe starts a new open period, after which the next probe goes out
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.try_acquire());
+    }
+}
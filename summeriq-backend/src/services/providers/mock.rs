@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::{AiProvider, ChatRequest, ProviderError, ProviderErrorKind, ProviderResponse};
+
+/// Provider that answers from a script, for exercising fallback and error handling in tests.
+///
+/// Scripted outcomes are consumed in order; once the script is exhausted every request gets
+/// the fallback reply set with [`MockProvider::otherwise`], or fails as unavailable.
+pub struct MockProvider {
+    name: String,
+    model: String,
+    json_mode: bool,
+    script: Mutex<VecDeque<Result<String, ProviderErrorKind>>>,
+    fallback: Option<String>,
+    requests: Mutex<Vec<ChatRequest>>,
+}
+
+impl MockProvider {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            model: format!("{}-model", name),
+            json_mode: true,
+            script: Mutex::new(VecDeque::new()),
+            fallback: None,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn json_mode(mut self, supported: bool) -> Self {
+        self.json_mode = supported;
+        self
+    }
+
+    /// Queues a successful reply.
+    pub fn respond(self, content: &str) -> Self {
+        self.script.lock().unwrap().push_back(Ok(content.to_string()));
+        self
+    }
+
+    /// Queues a failure of the given kind.
+    pub fn fail_with(self, kind: ProviderErrorKind) -> Self {
+        self.script.lock().unwrap().push_back(Err(kind));
+        self
+    }
+
+    /// Reply used once the script is exhausted.
+    pub fn otherwise(mut self, content: &str) -> Self {
+        self.fallback = Some(content.to_string());
+        self
+    }
+
+    /// Number of requests the provider has received.
+    pub fn calls(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    /// Requests received so far, in order.
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl AiProvider for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_json_mode(&self) -> bool {
+        self.json_mode
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<ProviderResponse, ProviderError> {
+        self.requests.lock().unwrap().push(request.clone());
+        let outcome = self.script.lock().unwrap().pop_front();
+        let content = match outcome {
+            Some(Ok(content)) => content,
+            Some(Err(kind)) => {
+                return Err(ProviderError::new(kind, format!("{} failed on cue", self.name)));
+            }
+            None => self.fallback.clone().ok_or_else(|| {
+                ProviderError::new(ProviderErrorKind::Unavailable, format!("{} has no scripted reply left", self.name))
+            })?,
+        };
+        Ok(ProviderResponse { content, usage: None, reasoning: None })
+    }
+}
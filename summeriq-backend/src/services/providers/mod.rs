@@ -0,0 +1,257 @@
+pub mod cassette;
+pub mod circuit_breaker;
+pub mod mock;
+pub mod openai_compatible;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+pub use cassette::{CassetteMode, CassetteProvider};
+pub use circuit_breaker::{BreakerSettings, CircuitBreaker};
+pub use mock::MockProvider;
+pub use openai_compatible::OpenAiCompatibleProvider;
+
+/// A single chat completion request, independent of the provider serving it.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub system_prompt: String,
+    pub prompt: String,
+    pub json_mode: bool,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    /// Rate limiter queue the request is accounted to.
+    pub caller: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderResponse {
+    pub content: String,
+    /// Token counts reported by the provider, when it reports them.
+    pub usage: Option<TokenUsage>,
+    /// Reasoning returned in a separate field by providers that parse it out themselves.
+    pub reasoning: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+/// Which provider and model produced a response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServedBy {
+    pub provider: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorKind {
+    /// Outage, 5xx, network failure or bad credentials; the next provider is tried.
+    Unavailable,
+    /// Still rate limited after retrying; the next provider is tried.
+    RateLimited,
+    /// The prompt exceeds what the provider accepts.
+    TooLarge,
+    /// JSON mode was requested and the generation was not valid JSON.
+    InvalidJson,
+    /// Any other rejection of the request itself.
+    InvalidRequest,
+}
+
+// Providers still rate limited after their own retries rarely recover within a minute
+const PROVIDER_RETRY_AFTER_SECS: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct ProviderError {
+    pub kind: ProviderErrorKind,
+    pub message: String,
+}
+
+impl ProviderError {
+    pub fn new(kind: ProviderErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+
+    /// Whether the failure says something about the provider rather than the request.
+    fn is_provider_failure(&self) -> bool {
+        matches!(self.kind, ProviderErrorKind::Unavailable | ProviderErrorKind::RateLimited)
+    }
+}
+
+impl From<ProviderError> for AppError {
+    fn from(error: ProviderError) -> AppError {
+        match error.kind {
+            ProviderErrorKind::Unavailable => AppError::InternalServerError(error.message),
+            ProviderErrorKind::RateLimited => AppError::RateLimited {
+                message: "AI service is rate limited. Please try again later.".to_string(),
+                retry_after_secs: PROVIDER_RETRY_AFTER_SECS,
+            },
+            ProviderErrorKind::TooLarge => {
+                AppError::BadRequest("AI request too large. Please reduce the size of your request.".to_string())
+            }
+            ProviderErrorKind::InvalidJson | ProviderErrorKind::InvalidRequest => {
+                AppError::InternalServerError(error.message)
+            }
+        }
+    }
+}
+
+#[async_trait]
+pub trait AiProvider: Send + Sync {
+    fn name(&self) -> &str;
+    fn model(&self) -> &str;
+    fn supports_json_mode(&self) -> bool;
+    async fn complete(&self, request: &ChatRequest) -> Result<ProviderResponse, ProviderError>;
+}
+
+struct ChainEntry {
+    provider: Arc<dyn AiProvider>,
+    breaker: CircuitBreaker,
+}
+
+/// Ordered list of providers: each request goes to the first provider whose circuit is
+/// closed, falling back to the next one when a provider is down or rate limited.
+pub struct ProviderChain {
+    entries: Vec<ChainEntry>,
+}
+
+impl ProviderChain {
+    pub fn new(providers: Vec<Arc<dyn AiProvider>>, breaker: BreakerSettings) -> Self {
+        let entries = providers
+            .into_iter()
+            .map(|provider| {
+                info!("Registered AI provider {} ({})", provider.name(), provider.model());
+                ChainEntry {
+                    provider,
+                    breaker: CircuitBreaker::new(breaker),
+                }
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub async fn complete(&self, request: &ChatRequest) -> Result<(ProviderResponse, ServedBy), ProviderError> {
+        let mut last_error = None;
+        for entry in &self.entries {
+            let name = entry.provider.name();
+            if !entry.breaker.try_acquire() {
+                info!("Skipping AI provider {}: circuit open", name);
+                continue;
+            }
+
+            let mut provider_request = request.clone();
+            provider_request.json_mode = request.json_mode && entry.provider.supports_json_mode();
+            match entry.provider.complete(&provider_request).await {
+                Ok(response) => {
+                    entry.breaker.record_success();
+                    let served_by = ServedBy {
+                        provider: name.to_string(),
+                        model: entry.provider.model().to_string(),
+                    };
+                    return Ok((response, served_by));
+                }
+                Err(e) if e.is_provider_failure() => {
+                    entry.breaker.record_failure();
+                    warn!("AI provider {} failed, trying next provider: {}", name, e.message);
+                    last_error = Some(e);
+                }
+                Err(e) => {
+                    // The request itself was rejected; another provider will not do better
+                    entry.breaker.record_success();
+                    return Err(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            ProviderError::new(
+                ProviderErrorKind::Unavailable,
+                "AI service is temporarily unavailable. Please try again in a few moments.",
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            system_prompt: "system".to_string(),
+            prompt: "hello".to_string(),
+            json_mode: false,
+            max_tokens: 100,
+            temperature: 0.0,
+            caller: "test".to_string(),
+        }
+    }
+
+    fn breaker() -> BreakerSettings {
+        BreakerSettings {
+            failure_threshold: 2,
+            open_for: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_next_provider() {
+        let primary = Arc::new(MockProvider::new("primary").fail_with(ProviderErrorKind::Unavailable));
+        let secondary = Arc::new(MockProvider::new("secondary").respond("from secondary"));
+        let chain = ProviderChain::new(vec![primary.clone(), secondary.clone()], breaker());
+
+        let (response, served_by) = chain.complete(&request()).await.unwrap();
+
+        assert_eq!(response.content, "from secondary");
+        assert_eq!(served_by.provider, "secondary");
+        assert_eq!(primary.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn request_errors_do_not_fall_back() {
+        let primary = Arc::new(MockProvider::new("primary").fail_with(ProviderErrorKind::TooLarge));
+        let secondary = Arc::new(MockProvider::new("secondary").respond("unused"));
+        let chain = ProviderChain::new(vec![primary, secondary.clone()], breaker());
+
+        let error = chain.complete(&request()).await.unwrap_err();
+
+        assert_eq!(error.kind, ProviderErrorKind::TooLarge);
+        assert_eq!(secondary.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn open_circuit_skips_provider() {
+        let primary = Arc::new(
+            MockProvider::new("primary")
+                .fail_with(ProviderErrorKind::Unavailable)
+                .fail_with(ProviderErrorKind::Unavailable)
+                .respond("recovered"),
+        );
+        let secondary = Arc::new(MockProvider::new("secondary").respond("a").respond("b").respond("c"));
+        let chain = ProviderChain::new(vec![primary.clone(), secondary], breaker());
+
+        for _ in 0..3 {
+            let (_, served_by) = chain.complete(&request()).await.unwrap();
+            assert_eq!(served_by.provider, "secondary");
+        }
+
+        // Two failures opened the circuit, so the third request never reached the primary
+        assert_eq!(primary.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn exhausted_rate_limits_are_too_many_requests() {
+        let primary = Arc::new(MockProvider::new("primary").fail_with(ProviderErrorKind::RateLimited));
+        let chain = ProviderChain::new(vec![primary], breaker());
+
+        let error: AppError = chain.complete(&request()).await.unwrap_err().into();
+
+        assert!(matches!(error, AppError::RateLimited { retry_after_secs: PROVIDER_RETRY_AFTER_SECS, .. }));
+    }
+}
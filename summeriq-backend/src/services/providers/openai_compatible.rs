@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use super::{AiProvider, ChatRequest, ProviderError, ProviderErrorKind, ProviderResponse, TokenUsage};
+use crate::config::AiProviderConfig;
+use crate::services::rate_limit::{AiRateLimiter, RateLimits};
+use crate::services::tokens::count_tokens;
+
+// Retries after a 429 before the provider is reported as rate limited
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Provider speaking the OpenAI chat completions API (Groq, OpenAI, OpenRouter, ...).
+/// Each provider has its own rate limiter, since limits are per account and endpoint.
+pub struct OpenAiCompatibleProvider {
+    client: Client,
+    name: String,
+    base_url: String,
+    model: String,
+    api_key: String,
+    supports_json_mode: bool,
+    rate_limiter: AiRateLimiter,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(config: &AiProviderConfig) -> Self {
+        info!(
+            "Initializing AI provider {} with API key: {}...",
+            config.name,
+            config.api_key.chars().take(4).collect::<String>()
+        );
+        Self {
+            client: Client::new(),
+            name: config.name.clone(),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            model: config.model.clone(),
+            api_key: config.api_key.clone(),
+            supports_json_mode: config.json_mode,
+            rate_limiter: AiRateLimiter::new(RateLimits {
+                requests_per_minute: config.requests_per_minute,
+                tokens_per_minute: config.tokens_per_minute,
+                max_concurrency: config.max_concurrency,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl AiProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_json_mode(&self) -> bool {
+        self.supports_json_mode
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<ProviderResponse, ProviderError> {
+        let estimated_tokens =
+            (count_tokens(&request.system_prompt) + count_tokens(&request.prompt)) as u32 + request.max_tokens;
+        let mut body = json!({
+            "model": self.model,
+            "messages": [
+                {"role": "system", "content": request.system_prompt},
+                {"role": "user", "content": request.prompt}
+            ],
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens
+        });
+        if request.json_mode {
+            body["response_format"] = json!({"type": "json_object"});
+        }
+
+        let mut retries = 0;
+        loop {
+            let permit = self.rate_limiter.acquire(&request.caller, estimated_tokens).await;
+            let response = self.client
+                .post(format!("{}/chat/completions", self.base_url))
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| {
+                    error!("Failed to send request to {}: {}", self.name, e);
+                    ProviderError::new(
+                        ProviderErrorKind::Unavailable,
+                        format!("Failed to connect to AI service: {}", e),
+                    )
+                })?;
+            self.rate_limiter.observe_headers(response.headers());
+            info!("Received response from {} with status: {}", self.name, response.status());
+            let status = response.status();
+            if !status.is_success() {
+                let headers = response.headers().clone();
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                error!("API error response from {}: {}", self.name, error_text);
+                let (kind, message) = match status.as_u16() {
+                    429 => {
+                        let backoff = Duration::from_secs(1 << retries.min(5));
+                        let wait = self.rate_limiter.observe_rate_limited(&headers, backoff);
+                        drop(permit);
+                        retries += 1;
+                        if retries < MAX_RATE_LIMIT_RETRIES {
+                            warn!("Rate limited by {}. Retrying in {:?}... (attempt {}/{})", self.name, wait, retries, MAX_RATE_LIMIT_RETRIES);
+                            continue;
+                        }
+                        (ProviderErrorKind::RateLimited, "AI service is rate limited. Please try again later.".to_string())
+                    }
+                    413 => (ProviderErrorKind::TooLarge, "AI request too large. Please reduce the size of your request.".to_string()),
+                    // Groq rejects JSON-mode generations that are not valid JSON with a 400
+                    400 if error_text.contains("json_validate_failed") => {
+                        (ProviderErrorKind::InvalidJson, "the response was not valid JSON".to_string())
+                    }
+                    401 | 403 => (ProviderErrorKind::Unavailable, "Invalid API key. Please check your configuration.".to_string()),
+                    503 => (
+                        ProviderErrorKind::Unavailable,
+                        "AI service is temporarily unavailable. Please try again in a few moments.".to_string(),
+                    ),
+                    code if code >= 500 => (ProviderErrorKind::Unavailable, format!("AI service error: {} - {}", status, error_text)),
+                    _ => (ProviderErrorKind::InvalidRequest, format!("AI service error: {} - {}", status, error_text)),
+                };
+                return Err(ProviderError::new(kind, message));
+            }
+
+            let response_body = response.json::<Value>().await.map_err(|e| {
+                error!("Failed to parse response from {}: {}", self.name, e);
+                ProviderError::new(ProviderErrorKind::Unavailable, "Failed to parse AI service response")
+            })?;
+            let message = &response_body["choices"][0]["message"];
+            let content = message["content"]
+                .as_str()
+                .ok_or_else(|| {
+                    error!("Invalid response format from {}", self.name);
+                    ProviderError::new(ProviderErrorKind::Unavailable, "Invalid response from AI service")
+                })?;
+            if let Some(used_tokens) = response_body["usage"]["total_tokens"].as_u64() {
+                permit.settle(used_tokens as u32);
+            }
+            // Groq calls the separated reasoning `reasoning`, DeepSeek `reasoning_content`
+            let reasoning = message["reasoning"]
+                .as_str()
+                .or_else(|| message["reasoning_content"].as_str())
+                .filter(|r| !r.trim().is_empty())
+                .map(str::to_string);
+            let usage = &response_body["usage"];
+            let usage = match (usage["prompt_tokens"].as_u64(), usage["completion_tokens"].as_u64()) {
+                (Some(prompt_tokens), Some(completion_tokens)) => Some(TokenUsage {
+                    prompt_tokens: prompt_tokens as u32,
+                    completion_tokens: completion_tokens as u32,
+                }),
+                _ => None,
+            };
+            return Ok(ProviderResponse {
+                content: content.to_string(),
+                usage,
+                reasoning,
+            });
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use reqwest::header::HeaderMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Provider limits the AI rate limiter enforces.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    pub requests_per_minute: u32,
+    pub tokens_per_minute: u32,
+    pub max_concurrency: usize,
+}
+
+/// A bucket holding up to `capacity` units, refilled continuously over one minute.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            capacity: per_minute.max(1) as f64,
+            available: per_minute.max(1) as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// Time until `amount` units are available. Requests larger than the whole bucket only
+    /// wait for a full bucket, so they are not starved forever.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let needed = amount.min(self.capacity) - self.available;
+        if needed <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(needed * 60.0 / self.capacity)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+
+    fn give_back(&mut self, amount: f64) {
+        self.available = (self.available + amount).min(self.capacity);
+    }
+
+    /// Lowers the local estimate to what the provider reports as remaining.
+    fn clamp_to(&mut self, remaining: f64) {
+        self.available = self.available.min(remaining);
+    }
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    requests: TokenBucket,
+    tokens: TokenBucket,
+    /// Set from `retry-after` / `x-ratelimit-reset-*` when the provider asks us to back off.
+    paused_until: Option<Instant>,
+    /// Waiting request ids per caller, in arrival order.
+    queues: HashMap<String, VecDeque<u64>>,
+    /// Callers with waiting requests, served round-robin.
+    turn_order: VecDeque<String>,
+    next_id: u64,
+}
+
+impl LimiterState {
+    fn remove_waiter(&mut self, caller: &str, id: u64) {
+        if let Some(queue) = self.queues.get_mut(caller) {
+            queue.retain(|waiting| *waiting != id);
+            if queue.is_empty() {
+                self.queues.remove(caller);
+                self.turn_order.retain(|c| c != caller);
+            }
+        }
+    }
+}
+
+/// Token-bucket rate limiter with a bounded concurrency pool and round-robin fairness
+/// between callers, so one user's burst cannot starve everyone else.
+#[derive(Clone)]
+pub struct AiRateLimiter {
+    state: Arc<Mutex<LimiterState>>,
+    turn_changed: Arc<Notify>,
+    concurrency: Arc<Semaphore>,
+}
+
+/// Grant to send one request; holds a concurrency slot until dropped.
+pub struct RatePermit {
+    limiter: AiRateLimiter,
+    reserved_tokens: u32,
+    _slot: OwnedSemaphorePermit,
+}
+
+impl RatePermit {
+    /// Corrects the token bucket once the provider reports the real usage of the request.
+    pub fn settle(&self, used_tokens: u32) {
+        if used_tokens < self.reserved_tokens {
+            let mut state = self.limiter.state.lock().unwrap();
+            state.tokens.give_back((self.reserved_tokens - used_tokens) as f64);
+        }
+    }
+}
+
+/// Removes a queued request when the caller gives up (e.g. the HTTP client disconnects).
+struct QueueGuard<'a> {
+    limiter: &'a AiRateLimiter,
+    caller: &'a str,
+    id: u64,
+    active: bool,
+}
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        if self.active {
+            self.limiter.state.lock().unwrap().remove_waiter(self.caller, self.id);
+            self.limiter.turn_changed.notify_waiters();
+        }
+    }
+}
+
+impl AiRateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        info!(
+            "Initializing AI rate limiter: {} requests/min, {} tokens/min, {} concurrent",
+            limits.requests_per_minute, limits.tokens_per_minute, limits.max_concurrency
+        );
+        Self {
+            state: Arc::new(Mutex::new(LimiterState {
+                requests: TokenBucket::new(limits.requests_per_minute),
+                tokens: TokenBucket::new(limits.tokens_per_minute),
+                paused_until: None,
+                queues: HashMap::new(),
+                turn_order: VecDeque::new(),
+                next_id: 0,
+            })),
+            turn_changed: Arc::new(Notify::new()),
+            concurrency: Arc::new(Semaphore::new(limits.max_concurrency.max(1))),
+        }
+    }
+
+    /// Waits until `caller` may send a request estimated at `tokens` tokens.
+    pub async fn acquire(&self, caller: &str, tokens: u32) -> RatePermit {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.queues.entry(caller.to_string()).or_default().push_back(id);
+            if !state.turn_order.iter().any(|c| c == caller) {
+                state.turn_order.push_back(caller.to_string());
+            }
+            id
+        };
+        let mut guard = QueueGuard { limiter: self, caller, id, active: true };
+
+        loop {
+            let notified = self.turn_changed.notified();
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let my_turn = state.turn_order.front().map(String::as_str) == Some(caller)
+                    && state.queues.get(caller).and_then(|q| q.front()) == Some(&id);
+                if !my_turn {
+                    None
+                } else {
+                    let now = Instant::now();
+                    state.requests.refill(now);
+                    state.tokens.refill(now);
+                    let paused = state
+                        .paused_until
+                        .map(|until| until.saturating_duration_since(now))
+                        .unwrap_or(Duration::ZERO);
+                    let wait = paused
+                        .max(state.requests.wait_for(1.0))
+                        .max(state.tokens.wait_for(tokens as f64));
+                    if wait.is_zero() {
+                        state.requests.take(1.0);
+                        state.tokens.take(tokens as f64);
+                        state.remove_waiter(caller, id);
+                        // Rotate so the next caller gets the following turn
+                        if state.queues.contains_key(caller) {
+                            state.turn_order.retain(|c| c != caller);
+                            state.turn_order.push_back(caller.to_string());
+                        }
+                        guard.active = false;
+                        break;
+                    }
+                    Some(wait)
+                }
+            };
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => notified.await,
+            }
+        }
+        self.turn_changed.notify_waiters();
+
+        let slot = self
+            .concurrency
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("AI concurrency semaphore closed");
+        RatePermit {
+            limiter: self.clone(),
+            reserved_tokens: tokens,
+            _slot: slot,
+        }
+    }
+
+    /// Applies `x-ratelimit-*` headers from a provider response.
+    pub fn observe_headers(&self, headers: &HeaderMap) {
+        let remaining_requests = header_f64(headers, "x-ratelimit-remaining-requests");
+        let remaining_tokens = header_f64(headers, "x-ratelimit-remaining-tokens");
+        let reset_requests = header_duration(headers, "x-ratelimit-reset-requests");
+        let reset_tokens = header_duration(headers, "x-ratelimit-reset-tokens");
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if let Some(remaining) = remaining_requests {
+            state.requests.refill(now);
+            state.requests.clamp_to(remaining);
+            if remaining < 1.0 {
+                if let Some(reset) = reset_requests {
+                    pause(&mut state, now + reset);
+                }
+            }
+        }
+        if let Some(remaining) = remaining_tokens {
+            state.tokens.refill(now);
+            state.tokens.clamp_to(remaining);
+            if remaining < 1.0 {
+                if let Some(reset) = reset_tokens {
+                    pause(&mut state, now + reset);
+                }
+            }
+        }
+    }
+
+    /// Pauses all callers after a 429, honouring `retry-after` when the provider sends it.
+    /// Returns how long requests are paused.
+    pub fn observe_rate_limited(&self, headers: &HeaderMap, fallback: Duration) -> Duration {
+        let wait = headers
+            .get("retry-after")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .map(Duration::from_secs_f64)
+            .or_else(|| header_duration(headers, "x-ratelimit-reset-tokens"))
+            .or_else(|| header_duration(headers, "x-ratelimit-reset-requests"))
+            .unwrap_or(fallback);
+        warn!("AI provider rate limited us, pausing requests for {:?}", wait);
+        let mut state = self.state.lock().unwrap();
+        pause(&mut state, Instant::now() + wait);
+        drop(state);
+        self.turn_changed.notify_waiters();
+        wait
+    }
+}
+
+/// Rate limiter queue key for requests made on behalf of a signed-in user.
+pub fn user_caller_key(user_id: Uuid) -> String {
+    format!("user:{}", user_id)
+}
+
+fn pause(state: &mut LimiterState, until: Instant) {
+    if state.paused_until.is_none_or(|current| current < until) {
+        state.paused_until = Some(until);
+    }
+}
+
+fn header_f64(headers: &HeaderMap, name: &str) -> Option<f64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn header_duration(headers: &HeaderMap, name: &str) -> Option<Duration> {
+    parse_reset_duration(headers.get(name)?.to_str().ok()?)
+}
+
+/// Parses reset durations as sent by OpenAI-compatible APIs, e.g. `7.66s`, `2m59.56s`, `120ms`.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        let seconds = match c {
+            'h' => amount * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                amount / 1000.0
+            }
+            'm' => amount * 60.0,
+            's' => amount,
+            _ => return None,
+        };
+        total += seconds;
+    }
+    if !number.is_empty() {
+        // Bare numbers are seconds
+        total += number.parse::<f64>().ok()?;
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_reset_durations() {
+        assert_eq!(parse_reset_duration("7.5s"), Some(Duration::from_millis(7500)));
+        assert_eq!(parse_reset_duration("2m30s"), Some(Duration::from_secs(150)));
+        assert_eq!(parse_reset_duration("120ms"), Some(Duration::from_millis(120)));
+        assert_eq!(parse_reset_duration("3"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_reset_duration("soon"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn callers_are_served_round_robin() {
+        let limiter = AiRateLimiter::new(RateLimits {
+            requests_per_minute: 60,
+            tokens_per_minute: 100_000,
+            max_concurrency: 1,
+        });
+        // Drain the request bucket so every grant has to wait for a refill
+        limiter.state.lock().unwrap().requests.available = 0.0;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (caller, count) in [("alice", 3), ("bob", 1)] {
+            for _ in 0..count {
+                let limiter = limiter.clone();
+                let order = order.clone();
+                handles.push(tokio::spawn(async move {
+                    let _permit = limiter.acquire(caller, 10).await;
+                    order.lock().unwrap().push(caller);
+                }));
+                tokio::task::yield_now().await;
+            }
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec!["alice", "bob", "alice", "alice"]);
+    }
+}
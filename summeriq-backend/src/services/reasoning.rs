@@ -0,0 +1,126 @@
+// Tags reasoning models use to wrap their chain of thought
+const REASONING_TAGS: &[&str] = &["think", "thinking", "reasoning"];
+
+/// Model output split into the final answer and the reasoning that preceded it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedOutput {
+    pub answer: String,
+    pub reasoning: Option<String>,
+}
+
+/// Separates the reasoning block of a reasoning model from its answer.
+///
+/// Only structure is used: a reasoning block is recognised when the output opens with a
+/// `<think>`-style tag, or when a closing tag stands on its own line before any opening tag
+/// (chat templates that prefill `<think>` make the model emit only the closing half). Tags
+/// appearing later in the answer, e.g. inside code samples, are left untouched. An unclosed
+/// block means the generation stopped while still reasoning, so the answer is empty.
+pub fn split_reasoning(content: &str) -> ParsedOutput {
+    let trimmed = content.trim_start();
+
+    for tag in REASONING_TAGS {
+        let open = format!("<{}>", tag);
+        let close = format!("</{}>", tag);
+
+        if let Some(rest) = trimmed.strip_prefix(&open) {
+            return match rest.find(&close) {
+                Some(end) => parsed(&rest[end + close.len()..], &rest[..end]),
+                None => parsed("", rest),
+            };
+        }
+
+        if let Some(end) = closing_line(content, &close) {
+            if !content[..end].contains(&open) {
+                return parsed(&content[end + close.len()..], &content[..end]);
+            }
+        }
+    }
+
+    ParsedOutput {
+        answer: content.trim().to_string(),
+        reasoning: None,
+    }
+}
+
+/// Byte offset of the first line consisting only of `close`.
+fn closing_line(content: &str, close: &str) -> Option<usize> {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim() == close {
+            return Some(offset + line.find(close)?);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn parsed(answer: &str, reasoning: &str) -> ParsedOutput {
+    let reasoning = reasoning.trim();
+    ParsedOutput {
+        answer: answer.trim().to_string(),
+        reasoning: (!reasoning.is_empty()).then(|| reasoning.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_think_block_from_answer() {
+        let transcript = "<think>\nOkay, so the user is asking what main.rs does. Let me look at the file. \
+It sets up an actix-web server and registers routes. I should explain that simply.\n</think>\n\n\
+So, in short: `main.rs` is the entry point. First, it loads the configuration; then it starts the HTTP server.\n\n\
+I think the most important part is the route table.";
+
+        let output = split_reasoning(transcript);
+
+        assert_eq!(
+            output.answer,
+            "So, in short: `main.rs` is the entry point. First, it loads the configuration; then it starts the HTTP server.\n\n\
+I think the most important part is the route table."
+        );
+        assert!(output.reasoning.unwrap().starts_with("Okay, so the user is asking"));
+    }
+
+    #[test]
+    fn handles_prefilled_think_tag() {
+        let transcript = "Alright, the user wants a summary of Cargo.toml.\nIt lists the dependencies.\n</think>\n\n\
+This file declares the crate's dependencies, including actix-web and sqlx.";
+
+        let output = split_reasoning(transcript);
+
+        assert_eq!(output.answer, "This file declares the crate's dependencies, including actix-web and sqlx.");
+        assert_eq!(
+            output.reasoning.as_deref(),
+            Some("Alright, the user wants a summary of Cargo.toml.\nIt lists the dependencies.")
+        );
+    }
+
+    #[test]
+    fn unclosed_block_has_no_answer() {
+        let output = split_reasoning("<think>\nLet me think about the architecture. First, the handlers");
+
+        assert_eq!(output.answer, "");
+        assert_eq!(output.reasoning.as_deref(), Some("Let me think about the architecture. First, the handlers"));
+    }
+
+    #[test]
+    fn keeps_answers_without_reasoning_intact() {
+        let transcript = "Let me explain: the parser strips `<think>` blocks.\n\n\
+```rust\nlet block = \"<think>...</think>\";\n```\n\nOkay, that's all.";
+
+        let output = split_reasoning(transcript);
+
+        assert_eq!(output.answer, transcript);
+        assert_eq!(output.reasoning, None);
+    }
+
+    #[test]
+    fn empty_think_block_yields_no_reasoning() {
+        let output = split_reasoning("<think>\n\n</think>\n\nHello! How can I help with your project?");
+
+        assert_eq!(output.answer, "Hello! How can I help with your project?");
+        assert_eq!(output.reasoning, None);
+    }
+}
@@ -0,0 +1,321 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::review::{FindingCategory, FindingFilter, ReviewFinding, ReviewReport, Severity};
+use crate::models::usage::AiFeature;
+use crate::models::upload::extracted_dir_for;
+use crate::services::ai::AIService;
+use crate::services::prompts::CodeReviewPrompt;
+use crate::services::providers::ServedBy;
+use crate::services::storage::FileNode;
+use crate::services::rate_limit::user_caller_key;
+use crate::services::tokens::split_into_chunks;
+use crate::services::usage::UsageContext;
+use crate::services::StorageService;
+
+// Largest file a whole-project review reads
+const MAX_REVIEW_FILE_BYTES: usize = 30_000;
+// Severity names from least to most severe, used for ranking in SQL
+const SEVERITY_ORDER: [&str; 5] = ["info", "low", "medium", "high", "critical"];
+
+const REVIEWABLE_EXTENSIONS: &[&str] = &[
+    "rs", "ts", "tsx", "js", "jsx", "mjs", "cjs", "py", "go", "java", "kt", "rb", "php", "c", "h",
+    "cpp", "hpp", "cs", "swift", "scala", "sql", "sh",
+];
+
+/// Shape the AI must answer with; unknown fields are rejected so drift is caught early.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct ReviewOutput {
+    findings: Vec<RawFinding>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct RawFinding {
+    category: FindingCategory,
+    severity: Severity,
+    /// Path of the reviewed file, exactly as given in the prompt.
+    file: String,
+    /// First affected line (1-based).
+    line_start: i32,
+    /// Last affected line (inclusive).
+    line_end: i32,
+    explanation: String,
+    suggested_fix: Option<String>,
+}
+
+/// Provider, model and prompt version behind a batch of findings.
+#[derive(Debug, Clone)]
+struct Source {
+    served_by: ServedBy,
+    prompt_version: String,
+}
+
+/// AI code review of uploads. Callers check that the user may access the upload first.
+#[derive(Clone)]
+pub struct ReviewService {
+    pool: PgPool,
+    storage_service: StorageService,
+    ai_service: AIService,
+    max_project_files: usize,
+}
+
+impl ReviewService {
+    pub fn new(pool: PgPool, storage_service: StorageService, ai_service: AIService, max_project_files: usize) -> Self {
+        Self { pool, storage_service, ai_service, max_project_files }
+    }
+
+    /// Reviews one file of an upload, replacing any earlier findings for that file.
+    pub async fn review_file(&self, user_id: Uuid, upload_id: Uuid, path: &str) -> Result<ReviewReport, AppError> {
+        let extracted_dir = self.find_upload_dir(upload_id).await?;
+        let path = path.trim_start_matches('/');
+        if path.split('/').any(|part| part == "..") {
+            return Err(AppError::BadRequest("Invalid file path".to_string()));
+        }
+
+        let content = self.storage_service.read_project_file(&extracted_dir, path).await?;
+        let content = String::from_utf8(content)
+            .map_err(|_| AppError::BadRequest("Only text files can be reviewed".to_string()))?;
+
+        let findings = self.review_content(user_id, upload_id, path, &content).await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM review_findings WHERE upload_id = $1 AND file_path = $2",
+            upload_id,
+            path
+        )
+        .execute(&mut *tx)
+        .await?;
+        let stored = insert_findings(&mut tx, upload_id, findings).await?;
+        tx.commit().await?;
+
+        info!("Stored {} review findings for {} in upload {}", stored.len(), path, upload_id);
+        Ok(ReviewReport {
+            findings: stored,
+            files_reviewed: 1,
+            files_total: 1,
+            truncated: false,
+        })
+    }
+
+    /// Reviews the source files of an upload, up to the configured file limit, replacing all
+    /// earlier findings for it. The report says when files were left out.
+    pub async fn review_project(&self, user_id: Uuid, upload_id: Uuid) -> Result<ReviewReport, AppError> {
+        let extracted_dir = self.find_upload_dir(upload_id).await?;
+        let nodes = self.storage_service.list_files(&extracted_dir).await?;
+
+        let mut paths = Vec::new();
+        collect_reviewable(&nodes, &mut paths);
+        let files_total = paths.len();
+        if files_total > self.max_project_files {
+            warn!("Reviewing only {} of {} files in upload {}", self.max_project_files, files_total, upload_id);
+            paths.truncate(self.max_project_files);
+        }
+
+        let mut findings = Vec::new();
+        let mut files_reviewed = 0;
+        for path in &paths {
+            let content = match self.storage_service.read_file(&format!("{}/{}", extracted_dir, path)).await {
+                Ok(bytes) if bytes.len() <= MAX_REVIEW_FILE_BYTES => match String::from_utf8(bytes) {
+                    Ok(content) => content,
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            match self.review_content(user_id, upload_id, path, &content).await {
+                Ok(file_findings) => {
+                    findings.extend(file_findings);
+                    files_reviewed += 1;
+                }
+                Err(e) => warn!("Skipping review of {}: {}", path, e),
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM review_findings WHERE upload_id = $1", upload_id)
+            .execute(&mut *tx)
+            .await?;
+        let stored = insert_findings(&mut tx, upload_id, findings).await?;
+        tx.commit().await?;
+
+        info!("Stored {} review findings for {} files in upload {}", stored.len(), files_reviewed, upload_id);
+        Ok(ReviewReport {
+            findings: stored,
+            files_reviewed,
+            files_total,
+            truncated: files_total > paths.len(),
+        })
+    }
+
+    pub async fn list_findings(&self, upload_id: Uuid, filter: FindingFilter) -> Result<Vec<ReviewFinding>, AppError> {
+        self.find_upload_dir(upload_id).await?;
+
+        let findings = sqlx::query_as!(
+            ReviewFinding,
+            r#"
+            SELECT id, upload_id, file_path, category, severity, line_start, line_end,
+                   explanation, suggested_fix, ai_provider, ai_model, prompt_version, created_at
+            FROM review_findings
+            WHERE upload_id = $1
+              AND ($2::text IS NULL OR category = $2)
+              AND ($3::text IS NULL OR severity = $3)
+              AND ($4::text IS NULL OR array_position($5::text[], severity) >= array_position($5::text[], $4))
+              AND ($6::text IS NULL OR file_path = $6)
+            ORDER BY array_position($5::text[], severity) DESC, file_path, line_start
+            "#,
+            upload_id,
+            filter.category.map(|c| c.as_str()),
+            filter.severity.map(|s| s.as_str()),
+            filter.min_severity.map(|s| s.as_str()),
+            &SEVERITY_ORDER.map(String::from)[..],
+            filter.file
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(findings)
+    }
+
+    async fn find_upload_dir(&self, upload_id: Uuid) -> Result<String, AppError> {
+        let upload = sqlx::query!("SELECT filename FROM uploads WHERE id = $1", upload_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Upload not found".to_string()))?;
+
+        Ok(extracted_dir_for(&upload.filename))
+    }
+
+    /// Asks the AI for findings on a single file, reviewing it in chunks when it does not
+    /// fit into one request. Line numbers always refer to the whole file.
+    async fn review_content(&self, user_id: Uuid, upload_id: Uuid, path: &str, content: &str) -> Result<Vec<(RawFinding, Source)>, AppError> {
+        let ai_service = self.ai_service.for_caller(&user_caller_key(user_id)).with_context(UsageContext {
+            feature: AiFeature::Review,
+            user_id: Some(user_id),
+            upload_id: Some(upload_id),
+        });
+        let budget = self.ai_service.token_budget();
+        if budget.fits(content) {
+            let line_count = content.lines().count().max(1);
+            return review_lines(&ai_service, path, content, 1, line_count).await;
+        }
+
+        let chunks = split_into_chunks(content, budget.content_tokens());
+        info!("File {} exceeds the token budget, reviewing it in {} chunks", path, chunks.len());
+        let mut findings = Vec::new();
+        for chunk in &chunks {
+            findings.extend(review_lines(&ai_service, path, &chunk.text, chunk.start_line, chunk.end_line).await?);
+        }
+        Ok(findings)
+    }
+}
+
+/// Reviews the lines `first_line..=last_line` of a file, given as `content`. Each finding is
+/// paired with the provider and prompt version that produced it.
+async fn review_lines(ai_service: &AIService, path: &str, content: &str, first_line: usize, last_line: usize) -> Result<Vec<(RawFinding, Source)>, AppError> {
+    let numbered: String = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| format!("{:>5} | {}\n", first_line + i, line))
+        .collect();
+
+    let prompt = ai_service.prompts().render(&CodeReviewPrompt {
+        path,
+        numbered: &numbered,
+        first_line,
+        last_line,
+    })?;
+
+    let (first, last) = (first_line as i32, last_line as i32);
+    let output = ai_service
+        .complete_json_with(&prompt, |output: &ReviewOutput| validate_review(output, path, first, last))
+        .await?;
+
+    info!(
+        "Review of {} (lines {}-{}) by {} produced {} findings",
+        path, first_line, last_line, output.served_by.provider, output.value.findings.len()
+    );
+    let source = Source {
+        served_by: output.served_by,
+        prompt_version: output.prompt_version,
+    };
+    Ok(output.value.findings.into_iter().map(|finding| (finding, source.clone())).collect())
+}
+
+async fn insert_findings(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    upload_id: Uuid,
+    findings: Vec<(RawFinding, Source)>,
+) -> Result<Vec<ReviewFinding>, AppError> {
+    let mut stored = Vec::with_capacity(findings.len());
+    for (finding, source) in findings {
+        let row = sqlx::query_as!(
+            ReviewFinding,
+            r#"
+            INSERT INTO review_findings (upload_id, file_path, category, severity, line_start, line_end, explanation, suggested_fix, ai_provider, ai_model, prompt_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING id, upload_id, file_path, category, severity, line_start, line_end, explanation, suggested_fix, ai_provider, ai_model, prompt_version, created_at
+            "#,
+            upload_id,
+            finding.file,
+            finding.category.as_str(),
+            finding.severity.as_str(),
+            finding.line_start,
+            finding.line_end,
+            finding.explanation,
+            finding.suggested_fix,
+            source.served_by.provider,
+            source.served_by.model,
+            source.prompt_version
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        stored.push(row);
+    }
+    Ok(stored)
+}
+
+/// Checks that every finding points at a real line range of the reviewed lines.
+fn validate_review(output: &ReviewOutput, path: &str, first_line: i32, last_line: i32) -> Result<(), String> {
+    for finding in &output.findings {
+        if finding.file != path {
+            return Err(format!("finding refers to file `{}` instead of `{}`", finding.file, path));
+        }
+        if finding.line_start < first_line || finding.line_end < finding.line_start || finding.line_end > last_line {
+            return Err(format!(
+                "invalid line range {}-{} (reviewed lines are {}-{})",
+                finding.line_start, finding.line_end, first_line, last_line
+            ));
+        }
+        if finding.explanation.trim().is_empty() {
+            return Err("finding has an empty explanation".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn collect_reviewable(nodes: &[FileNode], out: &mut Vec<String>) {
+    for node in nodes {
+        if node.is_dir {
+            if let Some(children) = &node.children {
+                collect_reviewable(children, out);
+            }
+        } else {
+            let reviewable = node
+                .name
+                .rsplit_once('.')
+                .map(|(_, ext)| REVIEWABLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                .unwrap_or(false);
+            // Generated and vendored code is not the project's to fix
+            let own_source = node.metadata.as_ref().is_none_or(|metadata| metadata.is_source());
+            if reviewable && own_source {
+                out.push(node.path.clone());
+            }
+        }
+    }
+}
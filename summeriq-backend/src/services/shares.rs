@@ -0,0 +1,329 @@
+use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::file_index::{TreePage, TreeQuery};
+use crate::models::organization::Permission;
+use crate::models::share::{CreateShareLink, CreatedShareLink, ShareLink, ShareResource, ShareViewer, SharedResource};
+use crate::models::upload::extracted_dir_for;
+use crate::services::auth_limiter::AuthLimiter;
+use crate::services::{ConversationService, FileIndexService, OrganizationService, StorageService};
+
+// Audience of share link tokens, so they can never pass for access or invitation tokens
+const SHARE_AUDIENCE: &str = "summeriq:share";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ShareClaims {
+    /// The share link; the token stops working once it is revoked.
+    sub: String,
+    aud: String,
+    exp: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShareSettings {
+    pub jwt_secret: String,
+    pub default_ttl: Duration,
+    /// Base URL of the web app that shows shared resources.
+    pub public_url: String,
+}
+
+impl ShareSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            jwt_secret: config.jwt_secret.clone(),
+            default_ttl: Duration::hours(config.share_link_ttl_hours),
+            public_url: config.public_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+/// Read-only links that show one upload, generated document or conversation to anyone
+/// holding them, until they expire or are revoked.
+pub struct ShareService {
+    pool: PgPool,
+    settings: ShareSettings,
+    organizations: OrganizationService,
+    conversations: ConversationService,
+    storage_service: StorageService,
+    file_index: FileIndexService,
+    limiter: AuthLimiter,
+}
+
+struct ShareLinkRow {
+    id: Uuid,
+    upload_id: Option<Uuid>,
+    doc_id: Option<Uuid>,
+    conversation_id: Option<Uuid>,
+    password_hash: Option<String>,
+    view_count: i32,
+    last_viewed_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl ShareLinkRow {
+    fn resource(&self) -> (ShareResource, Uuid) {
+        match (self.upload_id, self.doc_id, self.conversation_id) {
+            (Some(id), _, _) => (ShareResource::Upload, id),
+            (_, Some(id), _) => (ShareResource::Doc, id),
+            // The table guarantees exactly one of them is set
+            (_, _, id) => (ShareResource::Conversation, id.expect("share link names a resource")),
+        }
+    }
+}
+
+impl From<ShareLinkRow> for ShareLink {
+    fn from(row: ShareLinkRow) -> Self {
+        let (resource, resource_id) = row.resource();
+        ShareLink {
+            id: row.id,
+            resource,
+            resource_id,
+            password_protected: row.password_hash.is_some(),
+            view_count: row.view_count,
+            last_viewed_at: row.last_viewed_at,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+        }
+    }
+}
+
+impl ShareService {
+    pub fn new(
+        pool: PgPool,
+        settings: ShareSettings,
+        organizations: OrganizationService,
+        conversations: ConversationService,
+        storage_service: StorageService,
+        file_index: FileIndexService,
+        limiter: AuthLimiter,
+    ) -> Self {
+        Self { pool, settings, organizations, conversations, storage_service, file_index, limiter }
+    }
+
+    /// Creates a link to a resource `user_id` may share: uploads and their documents need
+    /// the share permission on the upload, conversations must be the user's own.
+    pub async fn create(&self, user_id: Uuid, request: CreateShareLink) -> Result<CreatedShareLink, AppError> {
+        match request.resource {
+            ShareResource::Upload => {
+                self.organizations
+                    .authorize_upload(user_id, request.resource_id, Permission::Share)
+                    .await?
+            }
+            ShareResource::Doc => {
+                let upload_id = sqlx::query_scalar!("SELECT upload_id FROM project_docs WHERE id = $1", request.resource_id)
+                    .fetch_optional(&self.pool)
+                    .await?
+                    .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+                self.organizations.authorize_upload(user_id, upload_id, Permission::Share).await?
+            }
+            ShareResource::Conversation => self.conversations.ensure_owner(user_id, request.resource_id).await?,
+        }
+        let password_hash = request
+            .password
+            .map(|password| hash(password.as_bytes(), DEFAULT_COST))
+            .transpose()
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        let ttl = request.expires_in_hours.map(Duration::hours).unwrap_or(self.settings.default_ttl);
+        let resource_id = |resource: ShareResource| Some(request.resource_id).filter(|_| request.resource == resource);
+
+        let row = sqlx::query_as!(
+            ShareLinkRow,
+            r#"
+            INSERT INTO share_links (user_id, upload_id, doc_id, conversation_id, password_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, upload_id, doc_id, conversation_id, password_hash, view_count, last_viewed_at, created_at, expires_at
+            "#,
+            user_id,
+            resource_id(ShareResource::Upload),
+            resource_id(ShareResource::Doc),
+            resource_id(ShareResource::Conversation),
+            password_hash,
+            Utc::now() + ttl
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let token = self.sign(&row)?;
+        info!("User {} created share link {}", user_id, row.id);
+        Ok(CreatedShareLink {
+            url: format!("{}/shared/{}", self.settings.public_url, token),
+            token,
+            link: row.into(),
+        })
+    }
+
+    /// The user's links that have not been revoked, newest first.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<ShareLink>, AppError> {
+        let rows = sqlx::query_as!(
+            ShareLinkRow,
+            r#"
+            SELECT id, upload_id, doc_id, conversation_id, password_hash, view_count, last_viewed_at, created_at, expires_at
+            FROM share_links
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(ShareLink::from).collect())
+    }
+
+    pub async fn revoke(&self, user_id: Uuid, link_id: Uuid) -> Result<(), AppError> {
+        let result = sqlx::query!(
+            "UPDATE share_links SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+            link_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("Share link not found".to_string()));
+        }
+        info!("User {} revoked share link {}", user_id, link_id);
+        Ok(())
+    }
+
+    /// The link's id and the shared resource, counting the view.
+    pub async fn view(&self, token: &str, viewer: ShareViewer<'_>) -> Result<(Uuid, SharedResource), AppError> {
+        let link = self.open(token, viewer).await?;
+        sqlx::query!(
+            "UPDATE share_links SET view_count = view_count + 1, last_viewed_at = NOW() WHERE id = $1",
+            link.id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let resource = match link.resource() {
+            (ShareResource::Upload, upload_id) => {
+                let upload = sqlx::query!(
+                    r#"
+                    SELECT filename, original_filename, size, extracted_files IS NOT NULL AS "extracted!", created_at
+                    FROM uploads WHERE id = $1
+                    "#,
+                    upload_id
+                )
+                .fetch_one(&self.pool)
+                .await?;
+                let files = if upload.extracted {
+                    let extracted_dir = extracted_dir_for(&upload.filename);
+                    Some(Box::new(self.file_index.list_upload(upload_id, &extracted_dir, &TreeQuery::default()).await?))
+                } else {
+                    None
+                };
+                SharedResource::Upload {
+                    original_filename: upload.original_filename,
+                    size: upload.size,
+                    files,
+                    created_at: upload.created_at,
+                }
+            }
+            (ShareResource::Doc, doc_id) => {
+                let doc = sqlx::query!("SELECT document, created_at FROM project_docs WHERE id = $1", doc_id)
+                    .fetch_one(&self.pool)
+                    .await?;
+                SharedResource::Doc { document: doc.document, created_at: doc.created_at }
+            }
+            (ShareResource::Conversation, conversation_id) => {
+                let (title, messages) = self.conversations.transcript(conversation_id).await?;
+                SharedResource::Conversation { title, messages }
+            }
+        };
+        Ok((link.id, resource))
+    }
+
+    /// The link's id and a page of the file tree of a shared upload, like a project tree.
+    pub async fn tree(&self, token: &str, viewer: ShareViewer<'_>, query: &TreeQuery) -> Result<(Uuid, TreePage), AppError> {
+        let link = self.open(token, viewer).await?;
+        let (ShareResource::Upload, upload_id) = link.resource() else {
+            return Err(AppError::NotFound("Directory not found".to_string()));
+        };
+        let filename = sqlx::query_scalar!("SELECT filename FROM uploads WHERE id = $1", upload_id)
+            .fetch_one(&self.pool)
+            .await?;
+        let page = self.file_index.list_upload(upload_id, &extracted_dir_for(&filename), query).await?;
+        Ok((link.id, page))
+    }
+
+    /// The link's id and the contents of a file of a shared upload, by its path inside the
+    /// upload.
+    pub async fn file(&self, token: &str, viewer: ShareViewer<'_>, path: &str) -> Result<(Uuid, Vec<u8>), AppError> {
+        let link = self.open(token, viewer).await?;
+        let (ShareResource::Upload, upload_id) = link.resource() else {
+            return Err(AppError::NotFound("File not found".to_string()));
+        };
+        let path = path.trim_start_matches('/');
+
+        let filename = sqlx::query_scalar!("SELECT filename FROM uploads WHERE id = $1", upload_id)
+            .fetch_one(&self.pool)
+            .await?;
+        let content = self
+            .storage_service
+            .read_project_file(&extracted_dir_for(&filename), path)
+            .await?;
+        Ok((link.id, content))
+    }
+
+    /// Checks the token's signature and that its link is live, then the link's password,
+    /// throttling wrong ones per address and per link.
+    async fn open(&self, token: &str, viewer: ShareViewer<'_>) -> Result<ShareLinkRow, AppError> {
+        let not_found = || AppError::NotFound("Share link not found or expired".to_string());
+        let mut validation = Validation::default();
+        validation.set_audience(&[SHARE_AUDIENCE]);
+        let claims = decode::<ShareClaims>(
+            token,
+            &DecodingKey::from_secret(self.settings.jwt_secret.as_bytes()),
+            &validation,
+        )
+        .map_err(|_| not_found())?
+        .claims;
+        let link_id = Uuid::parse_str(&claims.sub).map_err(|_| not_found())?;
+
+        let link = sqlx::query_as!(
+            ShareLinkRow,
+            r#"
+            SELECT id, upload_id, doc_id, conversation_id, password_hash, view_count, last_viewed_at, created_at, expires_at
+            FROM share_links
+            WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+            link_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(not_found)?;
+
+        if let Some(password_hash) = &link.password_hash {
+            let password = viewer
+                .password
+                .ok_or_else(|| AppError::AuthenticationError("This share link needs a password".to_string()))?;
+            self.limiter.check_share_password(viewer.address, link.id).await?;
+            if !verify(password, password_hash).unwrap_or(false) {
+                self.limiter.share_password_failed(viewer.address, link.id).await?;
+                return Err(AppError::AuthenticationError("Incorrect share link password".to_string()));
+            }
+        }
+        Ok(link)
+    }
+
+    fn sign(&self, link: &ShareLinkRow) -> Result<String, AppError> {
+        let claims = ShareClaims {
+            sub: link.id.to_string(),
+            aud: SHARE_AUDIENCE.to_string(),
+            exp: link.expires_at.timestamp() as usize,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.settings.jwt_secret.as_bytes()),
+        )
+        .map_err(|e| AppError::InternalServerError(e.to_string()))
+    }
+}
@@ -1,8 +1,12 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use tokio::fs;
 use zip::ZipArchive;
 use std::io::Cursor;
-use tracing::{info, error};
+use tracing::{info, error, warn};
+use std::collections::HashMap;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use crate::services::file_types::{self, FileMetadata};
 use std::io::Read;
 use serde::{Serialize, Deserialize};
 use std::sync::Arc;
@@ -10,26 +14,97 @@ use std::future::Future;
 use uuid::Uuid;
 use glob;
 
+/// Ignore file at the root of an extracted project, holding the patterns given with the
+/// upload. Projects may ship one; its patterns can re-include configured ones with `!`.
+pub const IGNORE_FILE: &str = ".summeriqignore";
+
+// Files in any directory whose patterns hide entries below it
+const NESTED_IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileNode {
     pub name: String,
     pub path: String,
     pub is_dir: bool,
     pub children: Option<Vec<FileNode>>,
+    /// Size, type, encoding and language of a file; absent for directories and in trees
+    /// listed before they were collected.
+    #[serde(flatten)]
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Whether `path` stays below the directory it is joined to: relative and without `..`.
+pub fn is_contained(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Path patterns an uploader excluded from their archive, such as `.env` or `secrets/**`.
+/// A pattern without `/` matches a file or directory name at any depth; other patterns match
+/// the path from the archive root. Everything below an excluded directory is excluded too.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludedPaths {
+    patterns: Vec<glob::Pattern>,
+}
+
+impl ExcludedPaths {
+    /// Parses a comma-separated list of glob patterns.
+    pub fn parse(spec: &str) -> Result<Self, crate::error::AppError> {
+        let patterns = spec
+            .split(',')
+            .map(|pattern| pattern.trim().trim_matches('/'))
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| {
+                glob::Pattern::new(pattern).map_err(|e| {
+                    crate::error::AppError::BadRequest(format!("Invalid exclude pattern {}: {}", pattern, e))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn patterns(&self) -> Vec<String> {
+        self.patterns.iter().map(|pattern| pattern.as_str().to_string()).collect()
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        let components: Vec<&str> = path.trim_matches('/').split('/').collect();
+        self.patterns.iter().any(|pattern| {
+            if pattern.as_str().contains('/') {
+                (1..=components.len()).any(|n| pattern.matches_with(&components[..n].join("/"), options))
+            } else {
+                components.iter().any(|component| pattern.matches_with(component, options))
+            }
+        })
+    }
 }
 
 #[derive(Clone)]
 pub struct StorageService {
     upload_dir: PathBuf,
+    ignore_patterns: Arc<Vec<String>>,
 }
 
 impl StorageService {
     pub fn new(upload_dir: String) -> Self {
         Self { 
-            upload_dir: PathBuf::from(upload_dir)
+            upload_dir: PathBuf::from(upload_dir),
+            ignore_patterns: Arc::new(Vec::new()),
         }
     }
 
+    /// Hides paths matching these gitignore-style patterns in every extracted project.
+    pub fn with_ignore_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.ignore_patterns = Arc::new(patterns);
+        self
+    }
+
     pub fn upload_dir(&self) -> &Path {
         &self.upload_dir
     }
@@ -52,6 +127,33 @@ impl StorageService {
         Ok(content)
     }
 
+    /// Reads `path` of the project extracted into `dir`. Ignored files are not found, just as
+    /// they are missing from listings; paths leaving the project are rejected.
+    pub async fn read_project_file(&self, dir: &str, path: &str) -> Result<Vec<u8>, crate::error::AppError> {
+        if !is_contained(path) {
+            return Err(crate::error::AppError::BadRequest("Invalid file path".to_string()));
+        }
+        if self.is_ignored(dir, path).await {
+            return Err(crate::error::AppError::NotFound(format!("File not found: {}", path)));
+        }
+        self.read_file(&format!("{}/{}", dir, path)).await
+    }
+
+    /// Removes an upload stored as `filename` and the directory it was extracted into.
+    /// Files that are already gone are skipped.
+    pub async fn delete_upload(&self, filename: &str) -> Result<(), crate::error::AppError> {
+        let file_path = self.upload_dir.join(filename);
+        if file_path.exists() {
+            fs::remove_file(&file_path).await?;
+        }
+        let extracted_dir = self.upload_dir.join(crate::models::upload::extracted_dir_for(filename));
+        if extracted_dir.is_dir() {
+            fs::remove_dir_all(&extracted_dir).await?;
+        }
+        info!("Deleted upload {:?}", file_path);
+        Ok(())
+    }
+
     pub async fn get_file_id(&self, project_name: &str) -> Result<String, crate::error::AppError> {
         use std::path::Path;
         // First try to extract UUID from project name (format: UUID_project)
@@ -88,99 +190,282 @@ impl StorageService {
         Err(crate::error::AppError::BadRequest("No UUID found for project".to_string()))
     }
 
-    pub async fn extract_zip(&self, content: &[u8], base_filename: &str) -> Result<Vec<String>, crate::error::AppError> {
-        let cursor = Cursor::new(content);
-        let mut archive = ZipArchive::new(cursor)?;
-        let extract_dir = self.upload_dir.join(base_filename);
-        
-        // Create the extraction directory
+    /// Copies `content`, a ZIP archive, without the entries `excluded` matches, so excluded
+    /// files are never written to storage.
+    pub fn remove_excluded(&self, content: &[u8], excluded: &ExcludedPaths) -> Result<Vec<u8>, crate::error::AppError> {
+        let mut archive = ZipArchive::new(Cursor::new(content))?;
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if excluded.is_excluded(file.name()) {
+                info!("Excluding {} from upload", file.name());
+                continue;
+            }
+            writer.raw_copy_file(file)?;
+        }
+        Ok(writer.finish()?.into_inner())
+    }
+
+    /// Extracts `content`, a ZIP archive, into `dir` and adds `ignore_patterns` to the
+    /// project's ignore file. Entries whose path would leave `dir`, and entries hidden by the
+    /// rules of [`StorageService::list_files`], are never written; ignore files go first so
+    /// their rules apply to the rest. Returns the paths written.
+    pub async fn extract_zip(
+        &self,
+        content: &[u8],
+        dir: &str,
+        ignore_patterns: &[String],
+    ) -> Result<Vec<String>, crate::error::AppError> {
+        let mut archive = ZipArchive::new(Cursor::new(content))?;
+        let extract_dir = self.upload_dir.join(dir);
         fs::create_dir_all(&extract_dir).await?;
-        
-        let mut extracted_files = Vec::new();
 
+        let mut entries = Vec::new();
         for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
-            let outpath = extract_dir.join(file.name());
+            let file = archive.by_index_raw(i)?;
+            let path = file.name().trim_end_matches('/');
+            if path.is_empty() {
+                continue;
+            }
+            if file.enclosed_name().is_none() || !is_contained(path) {
+                warn!("Skipping archive entry outside the project: {}", file.name());
+                continue;
+            }
+            let name = path.rsplit('/').next().unwrap_or(path);
+            let is_ignore_file = NESTED_IGNORE_FILES.contains(&name) || path == IGNORE_FILE;
+            entries.push((i, path.to_string(), file.is_dir(), is_ignore_file));
+        }
+        // Ignore files first, shallowest first, so each is checked against the ones above it
+        entries.sort_by_key(|(_, path, _, is_ignore_file)| (!is_ignore_file, path.matches('/').count()));
 
-            if file.name().ends_with('/') {
-                // Create directory
-                fs::create_dir_all(&outpath).await?;
-            } else {
-                // Extract file
-                if let Some(parent) = outpath.parent() {
-                    fs::create_dir_all(parent).await?;
-                }
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
-                fs::write(&outpath, buffer).await?;
+        let mut extracted_files = Vec::new();
+        let root_ignore_files = entries
+            .iter()
+            .take_while(|(_, path, _, is_ignore_file)| *is_ignore_file && !path.contains('/'))
+            .count();
+        for (index, path, is_dir, _) in &entries[..root_ignore_files] {
+            write_entry(&mut archive, *index, &extract_dir.join(path), *is_dir).await?;
+            extracted_files.push(path.clone());
+        }
+        self.add_ignore_patterns(dir, ignore_patterns).await?;
+
+        let matcher = self.ignore_matcher(&extract_dir);
+        let mut skipped = 0;
+        for (index, path, is_dir, is_ignore_file) in &entries[root_ignore_files..] {
+            // An ignore file is kept for its rules unless its whole directory is ignored
+            let ignored = match (is_ignore_file, path.rsplit_once('/')) {
+                (true, Some((parent, _))) => path_is_ignored(&extract_dir, parent, true, &matcher),
+                _ => path_is_ignored(&extract_dir, path, *is_dir, &matcher),
+            };
+            if ignored {
+                skipped += 1;
+                continue;
             }
-            
-            extracted_files.push(file.name().to_string());
+            write_entry(&mut archive, *index, &extract_dir.join(path), *is_dir).await?;
+            extracted_files.push(path.clone());
         }
 
-        info!("ZIP file extracted to: {:?}", extract_dir);
+        info!("ZIP file extracted to: {:?}, {} ignored entries skipped", extract_dir, skipped);
         Ok(extracted_files)
     }
 
+    /// The project tree extracted into `dir`, directories first. Entries ignored by the
+    /// project's `.gitignore` and `.ignore` files, the configured ignore patterns or the
+    /// upload's own patterns are left out.
     pub async fn list_files(&self, dir: &str) -> Result<Vec<FileNode>, crate::error::AppError> {
         let dir_path = self.upload_dir.join(dir);
-        let mut root_nodes = Vec::new();
-        
-        async fn process_directory(path: &Path, base_path: &Path) -> Result<FileNode, std::io::Error> {
-            let relative_path = path.strip_prefix(base_path).unwrap();
-            let path_str = relative_path.to_string_lossy().into_owned();
-            let name = path.file_name().unwrap().to_string_lossy().into_owned();
-            
-            let mut node = FileNode {
-                name,
-                path: path_str,
-                is_dir: path.is_dir(),
-                children: None,
-            };
-            
-            if path.is_dir() {
-                let mut entries = fs::read_dir(path).await?;
-                let mut children = Vec::new();
-                
-                while let Some(entry) = entries.next_entry().await? {
-                    let entry_path = entry.path();
-                    let child_node = Box::pin(process_directory(&entry_path, base_path)).await?;
-                    children.push(child_node);
-                }
-                
-                // Sort children: directories first, then files, both alphabetically
-                children.sort_by(|a, b| {
-                    match (a.is_dir, b.is_dir) {
-                        (true, false) => std::cmp::Ordering::Less,
-                        (false, true) => std::cmp::Ordering::Greater,
-                        _ => a.name.cmp(&b.name),
-                    }
-                });
-                
-                node.children = Some(children);
+        if !dir_path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let matcher = self.ignore_matcher(&dir_path);
+        tokio::task::spawn_blocking(move || walk_tree(&dir_path, matcher))
+            .await
+            .map_err(|e| crate::error::AppError::InternalServerError(format!("Failed to list files: {}", e)))?
+    }
+
+    /// Whether `path`, relative to the project extracted into `dir`, is hidden by the same
+    /// rules as [`StorageService::list_files`].
+    pub async fn is_ignored(&self, dir: &str, path: &str) -> bool {
+        let dir_path = self.upload_dir.join(dir);
+        let matcher = self.ignore_matcher(&dir_path);
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            let is_dir = dir_path.join(&path).is_dir();
+            path_is_ignored(&dir_path, &path, is_dir, &matcher)
+        })
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Adds `patterns` to the ignore file of the project extracted into `dir`.
+    pub async fn add_ignore_patterns(&self, dir: &str, patterns: &[String]) -> Result<(), crate::error::AppError> {
+        if patterns.is_empty() {
+            return Ok(());
+        }
+        let ignore_file = self.upload_dir.join(dir).join(IGNORE_FILE);
+        let mut content = fs::read_to_string(&ignore_file).await.unwrap_or_default();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for pattern in patterns {
+            content.push_str(pattern);
+            content.push('\n');
+        }
+        fs::write(&ignore_file, content).await?;
+        Ok(())
+    }
+
+    /// The configured patterns followed by those of the project's root ignore file, so the
+    /// project can re-include a path with `!`.
+    fn ignore_matcher(&self, root: &Path) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in self.ignore_patterns.iter() {
+            if let Err(e) = builder.add_line(None, pattern) {
+                warn!("Skipping ignore pattern {}: {}", pattern, e);
             }
-            
-            Ok(node)
-        }
-        
-        if dir_path.is_dir() {
-            let mut entries = fs::read_dir(&dir_path).await?;
-            while let Some(entry) = entries.next_entry().await? {
-                let entry_path = entry.path();
-                let node = Box::pin(process_directory(&entry_path, &dir_path)).await?;
-                root_nodes.push(node);
+        }
+        let ignore_file = root.join(IGNORE_FILE);
+        if ignore_file.is_file() {
+            if let Some(e) = builder.add(&ignore_file) {
+                warn!("Problem in {:?}: {}", ignore_file, e);
             }
-            
-            // Sort root nodes: directories first, then files, both alphabetically
-            root_nodes.sort_by(|a, b| {
-                match (a.is_dir, b.is_dir) {
-                    (true, false) => std::cmp::Ordering::Less,
-                    (false, true) => std::cmp::Ordering::Greater,
-                    _ => a.name.cmp(&b.name),
-                }
-            });
         }
-        
-        Ok(root_nodes)
+        let _ = builder.add_line(None, &format!("/{}", IGNORE_FILE));
+        builder.build().unwrap_or_else(|e| {
+            warn!("Failed to build ignore rules for {:?}: {}", root, e);
+            Gitignore::empty()
+        })
+    }
+}
+
+/// Parses a comma-separated list of gitignore-style patterns given with an upload.
+pub fn parse_ignore_patterns(spec: &str) -> Result<Vec<String>, crate::error::AppError> {
+    let patterns: Vec<String> = spec
+        .split(',')
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in &patterns {
+        builder
+            .add_line(None, pattern)
+            .map_err(|e| crate::error::AppError::BadRequest(format!("Invalid ignore pattern {}: {}", pattern, e)))?;
+    }
+    Ok(patterns)
+}
+
+/// Writes entry `index` of `archive` to `outpath`, creating the directories on the way.
+async fn write_entry(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    index: usize,
+    outpath: &Path,
+    is_dir: bool,
+) -> Result<(), crate::error::AppError> {
+    if is_dir {
+        fs::create_dir_all(outpath).await?;
+        return Ok(());
+    }
+    if let Some(parent) = outpath.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut buffer = Vec::new();
+    archive.by_index(index)?.read_to_end(&mut buffer)?;
+    fs::write(outpath, buffer).await?;
+    Ok(())
+}
+
+fn sort_nodes(nodes: &mut [FileNode]) {
+    // Directories first, then files, both alphabetically
+    nodes.sort_by(|a, b| match (a.is_dir, b.is_dir) {
+        (true, false) => std::cmp::Ordering::Less,
+        (false, true) => std::cmp::Ordering::Greater,
+        _ => a.name.cmp(&b.name),
+    });
+}
+
+/// Walks `root` with gitignore semantics and assembles the tree of what is not ignored.
+fn walk_tree(root: &Path, matcher: Gitignore) -> Result<Vec<FileNode>, crate::error::AppError> {
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .git_ignore(true)
+        .ignore(true)
+        .require_git(false)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            !matcher.matched(entry.path(), is_dir).is_ignore()
+        })
+        .build();
+
+    let mut entries = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(|e| crate::error::AppError::InternalServerError(format!("Failed to list files: {}", e)))?;
+        if entry.depth() == 0 {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_path_buf();
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let metadata = if is_dir {
+            None
+        } else {
+            file_types::inspect(entry.path(), &relative.to_string_lossy())
+                .map_err(|e| warn!("Failed to inspect {:?}: {}", entry.path(), e))
+                .ok()
+        };
+        entries.push((relative, is_dir, metadata));
     }
-} 
\ No newline at end of file
+
+    // Deepest entries first, so every directory's children are complete when it is reached
+    entries.sort_by_key(|(path, _, _)| std::cmp::Reverse(path.components().count()));
+    let mut children: HashMap<PathBuf, Vec<FileNode>> = HashMap::new();
+    for (path, is_dir, metadata) in entries {
+        let node = FileNode {
+            name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            path: path.to_string_lossy().into_owned(),
+            is_dir,
+            children: is_dir.then(|| {
+                let mut nodes = children.remove(&path).unwrap_or_default();
+                sort_nodes(&mut nodes);
+                nodes
+            }),
+            metadata,
+        };
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        children.entry(parent).or_default().push(node);
+    }
+    let mut root_nodes = children.remove(Path::new("")).unwrap_or_default();
+    sort_nodes(&mut root_nodes);
+    Ok(root_nodes)
+}
+
+/// Checks each directory on the way to `path` and then `path` itself the way the walker
+/// does: ignore files nearer the entry take precedence, `.ignore` over `.gitignore`. `path`
+/// need not exist yet, so whether it is a directory is given.
+fn path_is_ignored(root: &Path, path: &str, path_is_dir: bool, matcher: &Gitignore) -> bool {
+    let mut ignore_files: Vec<Gitignore> = Vec::new();
+    let mut gitignore_files: Vec<Gitignore> = Vec::new();
+    let mut current = root.to_path_buf();
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    for (i, component) in components.iter().enumerate() {
+        for (name, matchers) in [(".ignore", &mut ignore_files), (".gitignore", &mut gitignore_files)] {
+            let file = current.join(name);
+            if file.is_file() {
+                matchers.push(Gitignore::new(&file).0);
+            }
+        }
+        current.push(component);
+        let is_dir = i + 1 < components.len() || path_is_dir;
+        if matcher.matched(&current, is_dir).is_ignore() {
+            return true;
+        }
+        let decision = ignore_files
+            .iter()
+            .rev()
+            .chain(gitignore_files.iter().rev())
+            .map(|m| m.matched(&current, is_dir))
+            .find(|m| !m.is_none());
+        if decision.is_some_and(|m| m.is_ignore()) {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,187 @@
+use once_cell::sync::Lazy;
+use tiktoken_rs::CoreBPE;
+
+// Models served by Groq use their own tokenizers; cl100k is close enough for budgeting
+static TOKENIZER: Lazy<CoreBPE> = Lazy::new(|| tiktoken_rs::cl100k_base().expect("Failed to load cl100k tokenizer"));
+
+// Tokens kept free for the system prompt, instructions and JSON schema around file content
+const PROMPT_OVERHEAD_TOKENS: usize = 1_000;
+// Tokens reserved for the model's answer
+const COMPLETION_RESERVE_TOKENS: usize = 2_000;
+// Never split content into pieces smaller than this, even for tiny context windows
+const MIN_CHUNK_TOKENS: usize = 256;
+
+pub fn count_tokens(text: &str) -> usize {
+    TOKENIZER.encode_ordinary(text).len()
+}
+
+/// The beginning of `text`, at most `max_tokens` tokens long.
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    let tokens = TOKENIZER.encode_ordinary(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+    TOKENIZER.decode(tokens[..max_tokens].to_vec()).unwrap_or_default()
+}
+
+/// Token limits for a single request, derived from the model's context window.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBudget {
+    context_tokens: usize,
+}
+
+impl TokenBudget {
+    pub fn new(context_tokens: usize) -> Self {
+        Self { context_tokens }
+    }
+
+    /// Maximum number of tokens of embedded content (e.g. a file) per request.
+    pub fn content_tokens(&self) -> usize {
+        self.context_tokens
+            .saturating_sub(PROMPT_OVERHEAD_TOKENS + COMPLETION_RESERVE_TOKENS)
+            .max(MIN_CHUNK_TOKENS)
+    }
+
+    pub fn fits(&self, content: &str) -> bool {
+        count_tokens(content) <= self.content_tokens()
+    }
+}
+
+/// A contiguous range of lines cut out of a larger file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// First line of the chunk (1-based).
+    pub start_line: usize,
+    /// Last line of the chunk (inclusive).
+    pub end_line: usize,
+    pub text: String,
+}
+
+/// Splits `content` into chunks of at most `max_tokens` tokens.
+///
+/// Cuts are placed on syntax boundaries where possible: blank or unindented lines at bracket
+/// depth zero, which is where top-level items start in brace- and indentation-based languages
+/// alike. Runs without such a boundary are cut between lines, and single lines that are too
+/// long on their own (e.g. minified code) are cut by tokens.
+pub fn split_into_chunks(content: &str, max_tokens: usize) -> Vec<Chunk> {
+    let max_tokens = max_tokens.max(1);
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let boundaries = boundary_lines(&lines);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = start;
+        let mut tokens = 0;
+        let mut last_boundary = None;
+        while end < lines.len() {
+            let line_tokens = count_tokens(lines[end]);
+            if tokens + line_tokens > max_tokens {
+                break;
+            }
+            tokens += line_tokens;
+            end += 1;
+            if end < lines.len() && boundaries[end] {
+                last_boundary = Some(end);
+            }
+        }
+
+        if end == start {
+            // A single line exceeds the budget
+            chunks.extend(split_line_by_tokens(lines[start], start + 1, max_tokens));
+            start += 1;
+            continue;
+        }
+        if end < lines.len() {
+            if let Some(boundary) = last_boundary {
+                end = boundary;
+            }
+        }
+
+        chunks.push(Chunk {
+            start_line: start + 1,
+            end_line: end,
+            text: lines[start..end].concat(),
+        });
+        start = end;
+    }
+    chunks
+}
+
+/// Marks the lines before which a chunk may start.
+fn boundary_lines(lines: &[&str]) -> Vec<bool> {
+    let mut boundaries = Vec::with_capacity(lines.len());
+    let mut depth: i64 = 0;
+    for line in lines {
+        let trimmed = line.trim_end();
+        let unindented = !trimmed.is_empty() && !trimmed.starts_with(char::is_whitespace);
+        let closing = trimmed.trim_start().starts_with(|c| matches!(c, '}' | ')' | ']'));
+        boundaries.push(depth <= 0 && (trimmed.is_empty() || (unindented && !closing)));
+        for c in trimmed.chars() {
+            match c {
+                '{' | '(' | '[' => depth += 1,
+                '}' | ')' | ']' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    boundaries
+}
+
+fn split_line_by_tokens(line: &str, line_number: usize, max_tokens: usize) -> Vec<Chunk> {
+    let tokens = TOKENIZER.encode_ordinary(line);
+    tokens
+        .chunks(max_tokens)
+        .map(|piece| Chunk {
+            start_line: line_number,
+            end_line: line_number,
+            text: TOKENIZER.decode(piece.to_vec()).unwrap_or_default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_content_is_a_single_chunk() {
+        let chunks = split_into_chunks("fn main() {}\n", 100);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 1));
+    }
+
+    #[test]
+    fn splits_between_top_level_items() {
+        let item = "fn item() {\n    let value = 1;\n    println!(\"{}\", value);\n}\n\n";
+        let content = item.repeat(6);
+        let budget = count_tokens(item) * 2;
+
+        let chunks = split_into_chunks(&content, budget);
+
+        assert!(chunks.len() >= 3);
+        for chunk in &chunks {
+            assert!(count_tokens(&chunk.text) <= budget);
+            assert!(chunk.text.starts_with("fn item()") || chunk.text.starts_with('\n'));
+        }
+        let rejoined: String = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(rejoined, content);
+    }
+
+    #[test]
+    fn oversized_line_is_split_by_tokens() {
+        let line = "a = 1; ".repeat(200);
+        let chunks = split_into_chunks(&line, 50);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.start_line == 1 && c.end_line == 1));
+    }
+
+    #[test]
+    fn truncation_keeps_text_within_the_limit() {
+        let text = "word ".repeat(100);
+        assert_eq!(truncate_to_tokens("short", 10), "short");
+        let truncated = truncate_to_tokens(&text, 10);
+        assert!(text.starts_with(&truncated));
+        assert_eq!(count_tokens(&truncated), 10);
+    }
+}
@@ -0,0 +1,228 @@
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::usage::{
+    AdminUsageReport, AiFeature, BudgetStatus, DailyUsage, FeatureUsage, UserUsageReport, UserUsageTotal,
+};
+use crate::services::providers::ServedBy;
+
+// Reporting window used when the client does not ask for one, and the largest allowed
+const DEFAULT_REPORT_DAYS: i32 = 30;
+const MAX_REPORT_DAYS: i32 = 366;
+
+/// Who an AI call is made for; used to attribute usage and enforce budgets.
+#[derive(Debug, Clone, Copy)]
+pub struct UsageContext {
+    pub feature: AiFeature,
+    pub user_id: Option<Uuid>,
+    pub upload_id: Option<Uuid>,
+}
+
+impl Default for UsageContext {
+    fn default() -> Self {
+        Self {
+            feature: AiFeature::Other,
+            user_id: None,
+            upload_id: None,
+        }
+    }
+}
+
+/// One completed AI call.
+pub struct UsageRecord<'a> {
+    pub caller: &'a str,
+    pub context: UsageContext,
+    pub served_by: &'a ServedBy,
+    pub prompt_version: Option<&'a str>,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+/// Records token usage of AI calls and enforces daily token budgets per caller.
+#[derive(Clone)]
+pub struct UsageService {
+    pool: PgPool,
+    /// Tokens each caller may use per UTC day; 0 disables the budget.
+    default_daily_budget: u32,
+}
+
+impl UsageService {
+    pub fn new(pool: PgPool, default_daily_budget: u32) -> Self {
+        info!("Initializing UsageService with a daily budget of {} tokens", default_daily_budget);
+        Self { pool, default_daily_budget }
+    }
+
+    pub async fn record(&self, record: UsageRecord<'_>) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO ai_usage (caller, user_id, upload_id, feature, provider, model, prompt_version,
+                                  prompt_tokens, completion_tokens, total_tokens)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            record.caller,
+            record.context.user_id,
+            record.context.upload_id,
+            record.context.feature.as_str(),
+            record.served_by.provider,
+            record.served_by.model,
+            record.prompt_version,
+            record.prompt_tokens as i32,
+            record.completion_tokens as i32,
+            (record.prompt_tokens + record.completion_tokens) as i32
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Fails once `caller` has used up today's token budget.
+    pub async fn check_budget(&self, caller: &str, user_id: Option<Uuid>) -> Result<(), AppError> {
+        let status = self.budget_status(caller, user_id).await?;
+        if let (Some(limit), Some(0)) = (status.limit, status.remaining_today) {
+            warn!("Daily AI token budget of {} exhausted for {}", limit, caller);
+            return Err(AppError::QuotaExceeded(
+                "Daily AI token budget exhausted. It resets at midnight UTC.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn budget_status(&self, caller: &str, user_id: Option<Uuid>) -> Result<BudgetStatus, AppError> {
+        let limit = match user_id {
+            Some(user_id) => sqlx::query_scalar!("SELECT ai_daily_token_budget FROM users WHERE id = $1", user_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .flatten()
+                .map(i64::from),
+            None => None,
+        }
+        .unwrap_or(self.default_daily_budget as i64);
+
+        let used_today = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(total_tokens), 0)::BIGINT AS "used!"
+            FROM ai_usage
+            WHERE caller = $1 AND created_at >= date_trunc('day', now() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+            "#,
+            caller
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let limit = (limit > 0).then_some(limit);
+        Ok(BudgetStatus {
+            limit,
+            used_today,
+            remaining_today: limit.map(|limit| (limit - used_today).max(0)),
+        })
+    }
+
+    pub async fn user_report(&self, caller: &str, user_id: Uuid, days: Option<i32>) -> Result<UserUsageReport, AppError> {
+        let days = report_days(days);
+        let budget = self.budget_status(caller, Some(user_id)).await?;
+
+        let by_feature = sqlx::query_as!(
+            FeatureUsage,
+            r#"
+            SELECT feature,
+                   COUNT(*) AS "requests!",
+                   COALESCE(SUM(prompt_tokens), 0)::BIGINT AS "prompt_tokens!",
+                   COALESCE(SUM(completion_tokens), 0)::BIGINT AS "completion_tokens!",
+                   COALESCE(SUM(total_tokens), 0)::BIGINT AS "total_tokens!"
+            FROM ai_usage
+            WHERE user_id = $1 AND created_at >= now() - make_interval(days => $2)
+            GROUP BY feature
+            ORDER BY 5 DESC
+            "#,
+            user_id,
+            days
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let daily = sqlx::query_as!(
+            DailyUsage,
+            r#"
+            SELECT (created_at AT TIME ZONE 'UTC')::DATE AS "date!",
+                   COUNT(*) AS "requests!",
+                   COALESCE(SUM(total_tokens), 0)::BIGINT AS "total_tokens!"
+            FROM ai_usage
+            WHERE user_id = $1 AND created_at >= now() - make_interval(days => $2)
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+            user_id,
+            days
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(UserUsageReport { days, budget, by_feature, daily })
+    }
+
+    pub async fn admin_report(&self, days: Option<i32>) -> Result<AdminUsageReport, AppError> {
+        let days = report_days(days);
+
+        let by_user = sqlx::query_as!(
+            UserUsageTotal,
+            r#"
+            SELECT a.user_id AS "user_id?",
+                   u.email AS "email?",
+                   COUNT(*) AS "requests!",
+                   COALESCE(SUM(a.prompt_tokens), 0)::BIGINT AS "prompt_tokens!",
+                   COALESCE(SUM(a.completion_tokens), 0)::BIGINT AS "completion_tokens!",
+                   COALESCE(SUM(a.total_tokens), 0)::BIGINT AS "total_tokens!"
+            FROM ai_usage a
+            LEFT JOIN users u ON u.id = a.user_id
+            WHERE a.created_at >= now() - make_interval(days => $1)
+            GROUP BY a.user_id, u.email
+            ORDER BY 6 DESC
+            "#,
+            days
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let by_feature = sqlx::query_as!(
+            FeatureUsage,
+            r#"
+            SELECT feature,
+                   COUNT(*) AS "requests!",
+                   COALESCE(SUM(prompt_tokens), 0)::BIGINT AS "prompt_tokens!",
+                   COALESCE(SUM(completion_tokens), 0)::BIGINT AS "completion_tokens!",
+                   COALESCE(SUM(total_tokens), 0)::BIGINT AS "total_tokens!"
+            FROM ai_usage
+            WHERE created_at >= now() - make_interval(days => $1)
+            GROUP BY feature
+            ORDER BY 5 DESC
+            "#,
+            days
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let daily = sqlx::query_as!(
+            DailyUsage,
+            r#"
+            SELECT (created_at AT TIME ZONE 'UTC')::DATE AS "date!",
+                   COUNT(*) AS "requests!",
+                   COALESCE(SUM(total_tokens), 0)::BIGINT AS "total_tokens!"
+            FROM ai_usage
+            WHERE created_at >= now() - make_interval(days => $1)
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+            days
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(AdminUsageReport { days, by_user, by_feature, daily })
+    }
+}
+
+fn report_days(days: Option<i32>) -> i32 {
+    days.unwrap_or(DEFAULT_REPORT_DAYS).clamp(1, MAX_REPORT_DAYS)
+}
@@ -0,0 +1,71 @@
+//! Tests of the `--migrate-only` and `migrate status` commands of the binary, run against a
+//! throwaway database that `#[sqlx::test]` leaves unmigrated.
+
+use sqlx::PgPool;
+use std::process::{Command, Output};
+
+/// Runs the binary with `args` against the database of `pool`.
+async fn run(pool: &PgPool, args: &[&str]) -> Output {
+    let database: String = sqlx::query_scalar("SELECT current_database()").fetch_one(pool).await.unwrap();
+    let mut url = url::Url::parse(&std::env::var("DATABASE_URL").unwrap()).unwrap();
+    url.set_path(&database);
+    let storage = tempfile::tempdir().unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_summeriq-backend"))
+        .args(args)
+        .current_dir(storage.path())
+        .env("DATABASE_URL", url.as_str())
+        .env("JWT_SECRET", "integration-test-secret")
+        .env("GROQ_API_KEY", "unused")
+        .env("PROMPTS_DIR", concat!(env!("CARGO_MANIFEST_DIR"), "/prompts"))
+        .env("STORAGE_PATH", storage.path())
+        // Logs go to stdout too
+        .env("RUST_LOG", "off")
+        .output()
+        .unwrap()
+}
+
+/// `(state, version)` of every line `migrate status` printed.
+fn states(output: &Output) -> Vec<(String, String)> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next().unwrap().to_string(), fields.next().unwrap().to_string())
+        })
+        .collect()
+}
+
+#[sqlx::test(migrations = false)]
+async fn migrate_only_applies_what_status_reports_pending(pool: PgPool) {
+    let before = run(&pool, &["migrate", "status"]).await;
+    assert_eq!(before.status.code(), Some(1), "a fresh database is not at this build's schema");
+    let pending = states(&before);
+    assert!(!pending.is_empty());
+    assert!(pending.iter().all(|(state, _)| state == "pending"), "{:?}", pending);
+
+    let migrate = run(&pool, &["--migrate-only"]).await;
+    assert!(migrate.status.success(), "{}", String::from_utf8_lossy(&migrate.stderr));
+    let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM information_schema.tables WHERE table_name IN ('users', 'file_analyses')")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tables, 2);
+
+    let after = run(&pool, &["migrate", "status"]).await;
+    assert!(after.status.success());
+    let applied = states(&after);
+    assert_eq!(applied.len(), pending.len());
+    assert!(applied.iter().all(|(state, _)| state == "applied"), "{:?}", applied);
+
+    // Applying again finds nothing to do
+    assert!(run(&pool, &["--migrate-only"]).await.status.success());
+    sqlx::query("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let edited = run(&pool, &["migrate", "status"]).await;
+    assert_eq!(edited.status.code(), Some(1));
+    assert_eq!(states(&edited).last().unwrap().0, "modified");
+    assert!(!run(&pool, &["--migrate-only"]).await.status.success(), "edited migrations stop startup");
+}